@@ -0,0 +1,39 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Records the commit and time of the build for `/version`. GIT_COMMIT can be
+// set when building without the repository (e.g. in a Docker context without
+// .git), and SOURCE_DATE_EPOCH makes the build time reproducible.
+//
+// This script only reruns when the checked out commit or those variables
+// change, so BUILD_TIMESTAMP is the time the current commit was first built,
+// not the time of the last incremental build.
+fn main() {
+    // A missing path counts as changed on every build, so only existing ones
+    // are watched. `git pack-refs` and `git gc` move branches to packed-refs.
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH").ok().unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default().to_string()
+    });
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit.unwrap_or_else(|| String::from("unknown")));
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", timestamp);
+
+    // tests/mysql.rs is only built when there is a MySQL server to run it on
+    println!("cargo:rerun-if-env-changed=MYSQL_DATABASE_URL");
+    println!("cargo:rustc-check-cfg=cfg(mysql_tests)");
+    if std::env::var("MYSQL_DATABASE_URL").is_ok() {
+        println!("cargo:rustc-cfg=mysql_tests");
+    }
+}
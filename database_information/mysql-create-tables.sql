@@ -1,17 +1,17 @@
 USE defaultdb;
 CREATE TABLE `badges`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
     `name` VARCHAR(255) NOT NULL UNIQUE,
     `description` TEXT NOT NULL,
     `points` BIGINT NOT NULL,
-    `category` BIGINT UNSIGNED NOT NULL
+    `category` BIGINT NOT NULL
 );
 -- ALTER TABLE
 --     `badges` ADD PRIMARY KEY `badges_id_primary`(`id`);
 -- ALTER TABLE
 --     `badges` ADD UNIQUE `badges_name_unique`(`name`);
 CREATE TABLE `badge_categories`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
     `name` VARCHAR(255) NOT NULL UNIQUE
 );
 -- ALTER TABLE
@@ -19,7 +19,7 @@ CREATE TABLE `badge_categories`(
 -- ALTER TABLE
 --     `badge_categories` ADD UNIQUE `badge_categories_name_unique`(`name`);
 CREATE TABLE `labels`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
     `name` VARCHAR(255) NOT NULL UNIQUE
 );
 -- ALTER TABLE
@@ -27,10 +27,10 @@ CREATE TABLE `labels`(
 -- ALTER TABLE
 --     `labels` ADD UNIQUE `labels_name_unique`(`name`);
 CREATE TABLE `teams`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-    `rank` INT UNSIGNED NULL,
-    `score` BIGINT UNSIGNED NOT NULL,
-    `stage` INT UNSIGNED NOT NULL,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `rank` INT NULL,
+    `score` BIGINT NOT NULL,
+    `stage` INT NOT NULL,
     `name` VARCHAR(255) NOT NULL UNIQUE,
     `logo_url` VARCHAR(255) NULL,
     `banner_url` VARCHAR(255) NULL,
@@ -45,8 +45,8 @@ CREATE TABLE `teams`(
 -- ALTER TABLE
 --     `teams` ADD UNIQUE `teams_name_unique`(`name`);
 CREATE TABLE `persons`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-    `team_id` BIGINT UNSIGNED NOT NULL,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `team_id` BIGINT NOT NULL,
     `name` VARCHAR(255) NOT NULL,
     `career` VARCHAR(255) NOT NULL,
     `graduation_date` DATE NOT NULL,
@@ -56,15 +56,15 @@ CREATE TABLE `persons`(
 -- ALTER TABLE
 --     `persons` ADD PRIMARY KEY `persons_id_primary`(`id`);
 CREATE TABLE `label_ownerships`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
-    `team_id` BIGINT UNSIGNED NOT NULL,
-    `label_id` BIGINT UNSIGNED NOT NULL
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    `team_id` BIGINT NOT NULL,
+    `label_id` BIGINT NOT NULL
 );
 CREATE UNIQUE INDEX `index` ON label_ownerships (`team_id`, `label_id`);
 CREATE TABLE `badge_ownerships`(
-    `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY ,
-    `team_id` BIGINT UNSIGNED NOT NULL,
-    `badge_id` BIGINT UNSIGNED NOT NULL,
+    `id` BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY ,
+    `team_id` BIGINT NOT NULL,
+    `badge_id` BIGINT NOT NULL,
     `acquisition_date` DATE NOT NULL
 );
 CREATE UNIQUE INDEX `index` ON badge_ownerships (`team_id`, `badge_id`);
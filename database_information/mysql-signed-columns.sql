@@ -0,0 +1,48 @@
+-- Upgrades a database created with the old mysql-create-tables.sql, where ids,
+-- score, stage and rank were UNSIGNED. The backend reads them as signed 64 and
+-- 32 bit integers, like the Postgres schema, and fails to decode unsigned columns.
+-- The foreign keys are dropped while their columns change type.
+USE defaultdb;
+ALTER TABLE `label_ownerships` DROP FOREIGN KEY `label_ownerships_team_id_foreign`;
+ALTER TABLE `badge_ownerships` DROP FOREIGN KEY `badge_ownerships_team_id_foreign`;
+ALTER TABLE `persons` DROP FOREIGN KEY `persons_team_foreign`;
+ALTER TABLE `badge_ownerships` DROP FOREIGN KEY `badge_ownerships_badge_id_foreign`;
+ALTER TABLE `label_ownerships` DROP FOREIGN KEY `label_ownerships_label_id_foreign`;
+ALTER TABLE `badges` DROP FOREIGN KEY `badges_category_foreign`;
+
+ALTER TABLE `badges`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT,
+    MODIFY `category` BIGINT NOT NULL;
+ALTER TABLE `badge_categories`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT;
+ALTER TABLE `labels`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT;
+ALTER TABLE `teams`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT,
+    MODIFY `rank` INT NULL,
+    MODIFY `score` BIGINT NOT NULL,
+    MODIFY `stage` INT NOT NULL;
+ALTER TABLE `persons`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT,
+    MODIFY `team_id` BIGINT NOT NULL;
+ALTER TABLE `label_ownerships`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT,
+    MODIFY `team_id` BIGINT NOT NULL,
+    MODIFY `label_id` BIGINT NOT NULL;
+ALTER TABLE `badge_ownerships`
+    MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT,
+    MODIFY `team_id` BIGINT NOT NULL,
+    MODIFY `badge_id` BIGINT NOT NULL;
+
+ALTER TABLE
+    `label_ownerships` ADD CONSTRAINT `label_ownerships_team_id_foreign` FOREIGN KEY(`team_id`) REFERENCES `teams`(`id`);
+ALTER TABLE
+    `badge_ownerships` ADD CONSTRAINT `badge_ownerships_team_id_foreign` FOREIGN KEY(`team_id`) REFERENCES `teams`(`id`);
+ALTER TABLE
+    `persons` ADD CONSTRAINT `persons_team_foreign` FOREIGN KEY(`team_id`) REFERENCES `teams`(`id`);
+ALTER TABLE
+    `badge_ownerships` ADD CONSTRAINT `badge_ownerships_badge_id_foreign` FOREIGN KEY(`badge_id`) REFERENCES `badges`(`id`);
+ALTER TABLE
+    `label_ownerships` ADD CONSTRAINT `label_ownerships_label_id_foreign` FOREIGN KEY(`label_id`) REFERENCES `labels`(`id`);
+ALTER TABLE
+    `badges` ADD CONSTRAINT `badges_category_foreign` FOREIGN KEY(`category`) REFERENCES `badge_categories`(`id`);
@@ -0,0 +1,33 @@
+-- Keeps teams.score equal to the points of the team's badges. The server runs
+-- this file on start, so it can be run again at any time. Ownerships of badges
+-- that no longer exist count as no points.
+CREATE OR REPLACE FUNCTION badge_ownerships_score() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('DELETE', 'UPDATE') THEN
+        UPDATE teams SET score = score - COALESCE((SELECT points FROM badges WHERE id = OLD.badge_id), 0) WHERE id = OLD.team_id;
+    END IF;
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        UPDATE teams SET score = score + COALESCE((SELECT points FROM badges WHERE id = NEW.badge_id), 0) WHERE id = NEW.team_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION badges_score() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE teams SET score = teams.score + NEW.points - OLD.points
+        FROM badge_ownerships
+        WHERE badge_ownerships.team_id = teams.id AND badge_ownerships.badge_id = NEW.id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS badge_ownerships_score ON badge_ownerships;
+CREATE TRIGGER badge_ownerships_score
+    AFTER INSERT OR DELETE OR UPDATE OF team_id, badge_id ON badge_ownerships
+    FOR EACH ROW EXECUTE FUNCTION badge_ownerships_score();
+
+DROP TRIGGER IF EXISTS badges_score ON badges;
+CREATE TRIGGER badges_score
+    AFTER UPDATE OF points ON badges
+    FOR EACH ROW WHEN (OLD.points IS DISTINCT FROM NEW.points) EXECUTE FUNCTION badges_score();
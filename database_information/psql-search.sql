@@ -0,0 +1,12 @@
+-- Indexes used by GET /search, created when the server starts if they are missing.
+-- pg_trgm is a trusted extension, the database owner can create it.
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS teams_search_spanish ON teams USING GIN (to_tsvector('spanish', name || ' ' || description || ' ' || location));
+CREATE INDEX IF NOT EXISTS teams_search_english ON teams USING GIN (to_tsvector('english', name || ' ' || description || ' ' || location));
+CREATE INDEX IF NOT EXISTS teams_search_trigram ON teams USING GIN ((name || ' ' || description || ' ' || location) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS persons_search_spanish ON persons USING GIN (to_tsvector('spanish', name || ' ' || career));
+CREATE INDEX IF NOT EXISTS persons_search_english ON persons USING GIN (to_tsvector('english', name || ' ' || career));
+CREATE INDEX IF NOT EXISTS persons_search_trigram ON persons USING GIN ((name || ' ' || career) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS badges_search_spanish ON badges USING GIN (to_tsvector('spanish', name || ' ' || description));
+CREATE INDEX IF NOT EXISTS badges_search_english ON badges USING GIN (to_tsvector('english', name || ' ' || description));
+CREATE INDEX IF NOT EXISTS badges_search_trigram ON badges USING GIN ((name || ' ' || description) gin_trgm_ops);
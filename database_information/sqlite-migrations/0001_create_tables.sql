@@ -0,0 +1,50 @@
+-- Dates are stored as YYYY-MM-DD text, the CHECKs reject anything else
+CREATE TABLE badge_categories(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL UNIQUE
+);
+CREATE TABLE badges(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    description TEXT NOT NULL,
+    points BIGINT NOT NULL,
+    category BIGINT NOT NULL REFERENCES badge_categories(id)
+);
+CREATE TABLE labels(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL UNIQUE
+);
+CREATE TABLE teams(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rank INT NULL,
+    score BIGINT NOT NULL,
+    stage INT NOT NULL,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    logo_url VARCHAR(255) NULL,
+    banner_url VARCHAR(255) NULL,
+    description TEXT NOT NULL,
+    creation_date DATE NOT NULL CHECK (creation_date IS date(creation_date)),
+    location VARCHAR(255) NOT NULL
+);
+CREATE TABLE persons(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    team_id BIGINT NOT NULL REFERENCES teams(id),
+    name VARCHAR(255) NOT NULL,
+    career VARCHAR(255) NOT NULL,
+    graduation_date DATE NOT NULL CHECK (graduation_date IS date(graduation_date)),
+    picture_url VARCHAR(255) NULL,
+    portafolio_url VARCHAR(255) NULL
+);
+CREATE TABLE label_ownerships(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    team_id BIGINT NOT NULL REFERENCES teams(id),
+    label_id BIGINT NOT NULL REFERENCES labels(id)
+);
+CREATE UNIQUE INDEX index_label_ownerships ON label_ownerships (team_id, label_id);
+CREATE TABLE badge_ownerships(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    team_id BIGINT NOT NULL REFERENCES teams(id),
+    badge_id BIGINT NOT NULL REFERENCES badges(id),
+    acquisition_date DATE NOT NULL CHECK (acquisition_date IS date(acquisition_date))
+);
+CREATE UNIQUE INDEX index_badge_ownerships ON badge_ownerships (team_id, badge_id);
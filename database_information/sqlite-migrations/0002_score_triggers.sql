@@ -0,0 +1,22 @@
+-- Keeps teams.score equal to the points of the team's badges, like
+-- psql-score-triggers.sql does on Postgres
+CREATE TRIGGER badge_ownerships_score_insert AFTER INSERT ON badge_ownerships
+BEGIN
+    UPDATE teams SET score = score + COALESCE((SELECT points FROM badges WHERE id = NEW.badge_id), 0) WHERE id = NEW.team_id;
+END;
+
+CREATE TRIGGER badge_ownerships_score_delete AFTER DELETE ON badge_ownerships
+BEGIN
+    UPDATE teams SET score = score - COALESCE((SELECT points FROM badges WHERE id = OLD.badge_id), 0) WHERE id = OLD.team_id;
+END;
+
+CREATE TRIGGER badge_ownerships_score_update AFTER UPDATE OF team_id, badge_id ON badge_ownerships
+BEGIN
+    UPDATE teams SET score = score - COALESCE((SELECT points FROM badges WHERE id = OLD.badge_id), 0) WHERE id = OLD.team_id;
+    UPDATE teams SET score = score + COALESCE((SELECT points FROM badges WHERE id = NEW.badge_id), 0) WHERE id = NEW.team_id;
+END;
+
+CREATE TRIGGER badges_score AFTER UPDATE OF points ON badges WHEN NEW.points <> OLD.points
+BEGIN
+    UPDATE teams SET score = score + NEW.points - OLD.points WHERE id IN (SELECT team_id FROM badge_ownerships WHERE badge_id = NEW.id);
+END;
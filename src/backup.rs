@@ -0,0 +1,351 @@
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+use sqlx::FromRow;
+use crate::{integrity, ranking, RankTeam, RawID};
+
+// Increased every time the format changes. Restoring a backup with a newer
+// version than the one known by the server is refused.
+pub const BACKUP_VERSION: u32 = 1;
+
+// Portable copy of every table, ids included. Dates are kept as `YYYY-MM-DD`.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct Backup {
+    pub version: u32,
+    pub created_at: String,
+    pub badge_categories: Vec<BackupCategory>,
+    pub labels: Vec<BackupLabel>,
+    pub badges: Vec<BackupBadge>,
+    pub teams: Vec<BackupTeam>,
+    pub persons: Vec<BackupPerson>,
+    pub label_ownerships: Vec<BackupLabelOwnership>,
+    pub badge_ownerships: Vec<BackupBadgeOwnership>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupCategory {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupLabel {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupBadge {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub points: i64,
+    pub category: i64,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupTeam {
+    pub id: i64,
+    pub rank: Option<i32>,
+    pub score: i64,
+    pub stage: i32,
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub banner_url: Option<String>,
+    pub description: String,
+    pub creation_date: String,
+    pub location: String,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupPerson {
+    pub id: i64,
+    pub team_id: i64,
+    pub name: String,
+    pub career: String,
+    pub graduation_date: String,
+    pub picture_url: Option<String>,
+    pub portafolio_url: Option<String>,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupLabelOwnership {
+    pub id: i64,
+    pub team_id: i64,
+    pub label_id: i64,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Debug, ToSchema)]
+pub struct BackupBadgeOwnership {
+    pub id: i64,
+    pub team_id: i64,
+    pub badge_id: i64,
+    pub acquisition_date: String,
+}
+
+// Team whose stored score or rank didn't match the recomputed one after a restore
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Mismatch {
+    pub team_id: i64,
+    pub backup_score: i64,
+    pub score: i64,
+    pub backup_rank: Option<i32>,
+    pub rank: Option<i32>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RestoreReport {
+    pub version: u32,
+    pub teams: usize,
+    pub persons: usize,
+    pub labels: usize,
+    pub badge_categories: usize,
+    pub badges: usize,
+    pub label_ownerships: usize,
+    pub badge_ownerships: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+#[derive(FromRow, Debug)]
+struct ScoreRank {
+    id: i64,
+    score: i64,
+    rank: Option<i32>,
+}
+
+// Tables in the order they have to be filled so foreign keys are satisfied
+const TABLES: [&str; 7] = ["badge_categories", "labels", "badges", "teams", "persons", "label_ownerships", "badge_ownerships"];
+
+pub async fn backup(pool: &sqlx::postgres::PgPool) -> Result<Backup, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // Every table is read from the same snapshot
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ READ ONLY")
+        .execute(&mut tx)
+        .await?;
+    let badge_categories = sqlx::query_as::<sqlx::postgres::Postgres, BackupCategory>("SELECT * FROM badge_categories ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let labels = sqlx::query_as::<sqlx::postgres::Postgres, BackupLabel>("SELECT * FROM labels ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let badges = sqlx::query_as::<sqlx::postgres::Postgres, BackupBadge>("SELECT * FROM badges ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let teams = sqlx::query_as::<sqlx::postgres::Postgres, BackupTeam>("SELECT id, rank, score, stage, name, logo_url, banner_url, description, to_char(creation_date, 'YYYY-MM-DD') AS creation_date, location FROM teams ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let persons = sqlx::query_as::<sqlx::postgres::Postgres, BackupPerson>("SELECT id, team_id, name, career, to_char(graduation_date, 'YYYY-MM-DD') AS graduation_date, picture_url, portafolio_url FROM persons ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let label_ownerships = sqlx::query_as::<sqlx::postgres::Postgres, BackupLabelOwnership>("SELECT * FROM label_ownerships ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    let badge_ownerships = sqlx::query_as::<sqlx::postgres::Postgres, BackupBadgeOwnership>("SELECT id, team_id, badge_id, to_char(acquisition_date, 'YYYY-MM-DD') AS acquisition_date FROM badge_ownerships ORDER BY id")
+        .fetch_all(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created_at: actix_web::cookie::time::OffsetDateTime::now_utc().to_string(),
+        badge_categories,
+        labels,
+        badges,
+        teams,
+        persons,
+        label_ownerships,
+        badge_ownerships,
+    })
+}
+
+// Loads a backup into an empty database, resets the id sequences and
+// recomputes every score and the ranking, all in one transaction, reporting
+// the teams whose backed up score or rank differ.
+pub async fn restore(backup: Backup, pool: &sqlx::postgres::PgPool) -> Result<RestoreReport, String> {
+    if backup.version > BACKUP_VERSION {
+        return Err(format!("BACKUP VERSION {} IS NOT SUPPORTED (MAX {})", backup.version, BACKUP_VERSION));
+    }
+    let error = |err: sqlx::Error| format!("ERROR RESTORING: {}", err);
+
+    let mut tx = pool.begin().await.map_err(error)?;
+    for table in TABLES {
+        let row = sqlx::query_as::<sqlx::postgres::Postgres, RawID>(&format!("SELECT id FROM {} LIMIT 1", table))
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(error)?;
+        if row.is_some() {
+            return Err(format!("DATABASE IS NOT EMPTY: {} HAS ROWS", table));
+        }
+    }
+
+    for category in &backup.badge_categories {
+        sqlx::query("INSERT INTO badge_categories (id, name) VALUES ($1, $2)")
+            .bind(category.id)
+            .bind(&category.name)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for label in &backup.labels {
+        sqlx::query("INSERT INTO labels (id, name) VALUES ($1, $2)")
+            .bind(label.id)
+            .bind(&label.name)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for badge in &backup.badges {
+        sqlx::query("INSERT INTO badges (id, name, description, points, category) VALUES ($1, $2, $3, $4, $5)")
+            .bind(badge.id)
+            .bind(&badge.name)
+            .bind(&badge.description)
+            .bind(badge.points)
+            .bind(badge.category)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for team in &backup.teams {
+        // Scores and ranks are rebuilt from the badges below
+        sqlx::query("INSERT INTO teams (id, rank, score, stage, name, logo_url, banner_url, description, creation_date, location) VALUES ($1, NULL, 0, $2, $3, $4, $5, $6, $7::DATE, $8)")
+            .bind(team.id)
+            .bind(team.stage)
+            .bind(&team.name)
+            .bind(&team.logo_url)
+            .bind(&team.banner_url)
+            .bind(&team.description)
+            .bind(&team.creation_date)
+            .bind(&team.location)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for person in &backup.persons {
+        sqlx::query("INSERT INTO persons (id, team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES ($1, $2, $3, $4, $5::DATE, $6, $7)")
+            .bind(person.id)
+            .bind(person.team_id)
+            .bind(&person.name)
+            .bind(&person.career)
+            .bind(&person.graduation_date)
+            .bind(&person.picture_url)
+            .bind(&person.portafolio_url)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for ownership in &backup.label_ownerships {
+        sqlx::query("INSERT INTO label_ownerships (id, team_id, label_id) VALUES ($1, $2, $3)")
+            .bind(ownership.id)
+            .bind(ownership.team_id)
+            .bind(ownership.label_id)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    for ownership in &backup.badge_ownerships {
+        sqlx::query("INSERT INTO badge_ownerships (id, team_id, badge_id, acquisition_date) VALUES ($1, $2, $3, $4::DATE)")
+            .bind(ownership.id)
+            .bind(ownership.team_id)
+            .bind(ownership.badge_id)
+            .bind(&ownership.acquisition_date)
+            .execute(&mut tx).await.map_err(error)?;
+    }
+
+    // Ids were inserted explicitly, so the BIGSERIAL sequences have to continue after them
+    for table in TABLES {
+        sqlx::query(&format!("SELECT setval(pg_get_serial_sequence('{0}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {0}", table))
+            .execute(&mut tx).await.map_err(error)?;
+    }
+    // The score triggers, when installed, already added each badge's points.
+    // Without them the scores are still 0, so they are set here either way.
+    sqlx::query(integrity::REPAIR_SCORES).execute(&mut tx).await.map_err(error)?;
+    let teams = sqlx::query_as::<sqlx::postgres::Postgres, RankTeam>("SELECT id, score, name, rank FROM teams")
+        .fetch_all(&mut tx)
+        .await
+        .map_err(error)?;
+    ranking::write_ranks(&mut tx, &ranking::rank_changes(&teams)).await.map_err(error)?;
+    let computed = sqlx::query_as::<sqlx::postgres::Postgres, ScoreRank>("SELECT id, score, rank FROM teams")
+        .fetch_all(&mut tx)
+        .await
+        .map_err(error)?;
+    let mismatches = backup.teams.iter().filter_map(|team| {
+        let computed = computed.iter().find(|computed| computed.id == team.id)?;
+        if computed.score == team.score && computed.rank == team.rank { return None; }
+        Some(Mismatch {
+            team_id: team.id,
+            backup_score: team.score,
+            score: computed.score,
+            backup_rank: team.rank,
+            rank: computed.rank,
+        })
+    }).collect();
+    tx.commit().await.map_err(error)?;
+
+    Ok(RestoreReport {
+        version: backup.version,
+        teams: backup.teams.len(),
+        persons: backup.persons.len(),
+        labels: backup.labels.len(),
+        badge_categories: backup.badge_categories.len(),
+        badges: backup.badges.len(),
+        label_ownerships: backup.label_ownerships.len(),
+        badge_ownerships: backup.badge_ownerships.len(),
+        mismatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use crate::scratch::Scratch;
+    use super::{backup, restore, Backup, BACKUP_VERSION};
+
+    // Empties every table and restarts the ids at 1
+    const EMPTY: &str = "TRUNCATE badge_categories, labels, badges, teams, persons, label_ownerships, badge_ownerships RESTART IDENTITY";
+
+    // A backup without its creation time, to compare two of them
+    fn contents(backup: &Backup) -> Value {
+        let mut value = serde_json::to_value(backup).unwrap();
+        value.as_object_mut().unwrap().remove("created_at");
+        value
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn backups_restore_to_the_same_data() {
+        let scratch = Scratch::new("backup_round_trip").await;
+        scratch.seed().await;
+        let saved = backup(&scratch.pool).await.unwrap();
+        assert_eq!((saved.version, saved.teams.len(), saved.badge_ownerships.len()), (BACKUP_VERSION, 2, 4));
+
+        let refused = restore(serde_json::from_value(serde_json::to_value(&saved).unwrap()).unwrap(), &scratch.pool).await;
+        assert_eq!(refused.unwrap_err(), "DATABASE IS NOT EMPTY: badge_categories HAS ROWS");
+
+        scratch.execute(EMPTY).await;
+        let report = restore(serde_json::from_value(serde_json::to_value(&saved).unwrap()).unwrap(), &scratch.pool).await.unwrap();
+        assert_eq!((report.teams, report.persons, report.labels, report.badge_categories, report.badges), (2, 2, 4, 3, 2));
+        assert_eq!((report.label_ownerships, report.badge_ownerships), (4, 4));
+        assert!(report.mismatches.is_empty());
+        assert_eq!(contents(&backup(&scratch.pool).await.unwrap()), contents(&saved));
+
+        // New rows continue after the restored ids
+        for table in super::TABLES {
+            let (next, max):(i64, i64) = sqlx::query_as(&format!("SELECT nextval(pg_get_serial_sequence('{0}', 'id')), (SELECT MAX(id) FROM {0})", table))
+                .fetch_one(&scratch.pool)
+                .await
+                .unwrap();
+            assert_eq!(next, max + 1, "{}", table);
+        }
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn restoring_reports_scores_that_change() {
+        let scratch = Scratch::new("backup_mismatch").await;
+        scratch.seed().await;
+        // Moneypool's badges are worth 900, and it keeps the rank it shares with Startup-tec
+        scratch.execute("UPDATE teams SET score = 500 WHERE id = 2").await;
+        let saved = backup(&scratch.pool).await.unwrap();
+        scratch.execute(EMPTY).await;
+        let report = restore(saved, &scratch.pool).await.unwrap();
+        let mismatches:Vec<(i64, i64, i64)> = report.mismatches.iter().map(|mismatch| (mismatch.team_id, mismatch.backup_score, mismatch.score)).collect();
+        assert_eq!(mismatches, [(2, 500, 900)]);
+
+        // Without the score triggers the scores are still the badges' points
+        scratch.execute("DROP TRIGGER badge_ownerships_score ON badge_ownerships").await;
+        let saved = backup(&scratch.pool).await.unwrap();
+        scratch.execute(EMPTY).await;
+        assert!(restore(saved, &scratch.pool).await.unwrap().mismatches.is_empty());
+        let scores:Vec<(i64, i64, Option<i32>)> = sqlx::query_as("SELECT id, score, rank FROM teams ORDER BY id").fetch_all(&scratch.pool).await.unwrap();
+        assert_eq!(scores, [(1, 900, Some(1)), (2, 900, Some(1))]);
+        scratch.drop().await;
+    }
+}
@@ -0,0 +1,373 @@
+use std::io::Read;
+use std::sync::Arc;
+use futures::future;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use validator::Validate;
+use starterspace_backend::*;
+use starterspace_backend::backup::Backup;
+use starterspace_backend::config::{Config, flag_value};
+use starterspace_backend::import::ImportFiles;
+use starterspace_backend::metrics::Metrics;
+use starterspace_backend::validation::{self, ValidationFailure};
+
+// Manages the data of any backend without running the server. Every command
+// goes through the same operations as the API, so scores, ranks and validation
+// behave the same.
+const USAGE: &str = "Usage: starterspace-admin [configuration flags] <command>
+
+Commands:
+  list teams|badges|labels|categories
+  list persons <team id>
+  show <team id>
+  create team|badge|label|category|person <json>
+  add label|badge <json>
+  remove label|badge <json>
+  edit team|person|badge|label|category <id> <json>
+  delete team|person|badge|label|category <id> [--force]
+  award <badge id> <team id>... [--date YYYY-MM-DD]
+  revoke <badge id> <team id>...
+  recompute
+  seed [--file fixtures.sql]
+  import [--teams teams.csv] [--persons persons.csv] [--labels labels.csv] [--badges badges.csv]
+  export [--format json|csv|xlsx] [--output file]
+  backup [--output backup.json]
+  restore --input backup.json
+  integrity [--fix]
+
+<json> takes the body the API takes, `@file` to read it from a file or `-` for stdin.
+Configuration flags are the server's, like --database-url or --config.";
+
+// Sample data for each database, loaded by `seed`
+const PSQL_SEED: &str = include_str!("../../database_information/psql-temp-values.sql");
+const MYSQL_SEED: &str = include_str!("../../database_information/mysql-temp-values.sql");
+const SQLITE_SEED: &str = include_str!("../../database_information/sqlite-temp-values.sql");
+
+#[actix_web::main]
+async fn main() {
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    let command = command_start(&args);
+    if command == args.len() || args[command] == "help" || args[command] == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+    let config = match Config::load(&args[..command]) {
+        Ok(config) => config,
+        Err(errors) => fail(errors.join("\n")),
+    };
+    let args = &args[command..];
+    validation::set_max_stage(config.teams.max_stage);
+
+    let repo = repository::connect(&config.database).await.unwrap_or_else(|err| fail(format!("Unable to connect to database: {}", err)));
+    // Nothing is served, so nothing is cached
+    let state = AppState { metrics: Arc::new(Metrics::new(config.database.max_connections)), ..AppState::new(repo) };
+    if let Err(err) = run(&state, &config, args).await {
+        fail(err);
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+// Index of the first argument after the leading configuration flags
+fn command_start(args: &[String]) -> usize {
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with("--") && args[i] != "--help" {
+        i += if args[i].contains('=') || args[i] == "--print-config" { 1 } else { 2 };
+    }
+    i.min(args.len())
+}
+
+// Flags of the commands that take no value
+const SWITCHES: [&str; 2] = ["--force", "--fix"];
+
+async fn run(state: &AppState, config: &Config, args: &[String]) -> Result<(), String> {
+    let positional = positional(args);
+    let arg = |i: usize, name: &str| positional.get(i).copied().ok_or_else(|| format!("Missing {}\n\n{}", name, USAGE));
+    match positional[0] {
+        "list" => match arg(1, "what to list")? {
+            "teams" => print_json(&teams(state).await),
+            "badges" => print_json(&state.repo.badges().await),
+            "labels" => print_json(&state.repo.labels().await),
+            "categories" => print_json(&state.repo.categories().await),
+            "persons" => print_json(&state.repo.team_persons(id(arg(2, "team id")?)?).await),
+            other => Err(format!("Can't list {}", other)),
+        },
+        "show" => {
+            let raw_team = state.repo.team(id(arg(1, "team id")?)?).await.ok_or("ID does not exist")?;
+            print_json(&Team::from(raw_team, state.repo.as_ref()).await)
+        },
+        "create" => {
+            let json = body(arg(2, "json")?)?;
+            match arg(1, "what to create")? {
+                "team" => println!("Created team {}", operations::create_team(state, valid(&json)?).await?),
+                "badge" => println!("Created badge {}", operations::create_badge(state, valid(&json)?).await?),
+                "label" => println!("Created label {}", operations::create_label(state, valid(&json)?).await?),
+                "category" => println!("Created category {}", operations::create_category(state, valid(&json)?).await?),
+                "person" => {
+                    operations::add_person(state, valid(&json)?).await?;
+                    println!("Added person");
+                },
+                other => return Err(format!("Can't create {}", other)),
+            }
+            Ok(())
+        },
+        "add" => {
+            let json = body(arg(2, "json")?)?;
+            match arg(1, "what to add")? {
+                "label" => operations::add_label(state, parse(&json)?).await?,
+                "badge" => operations::add_badge(state, valid(&json)?).await?,
+                other => return Err(format!("Can't add {}", other)),
+            }
+            println!("Success");
+            Ok(())
+        },
+        "remove" => {
+            let json = body(arg(2, "json")?)?;
+            match arg(1, "what to remove")? {
+                "label" => operations::remove_label(state, parse(&json)?).await?,
+                "badge" => operations::remove_badge(state, parse(&json)?).await?,
+                other => return Err(format!("Can't remove {}", other)),
+            }
+            println!("Success");
+            Ok(())
+        },
+        "edit" => {
+            let body = EditBody::parse(arg(1, "what to edit")?, &body(arg(3, "json")?)?)?;
+            body.check().map_err(invalid)?;
+            operations::edit(state, id(arg(2, "id")?)?, body).await?;
+            println!("Success");
+            Ok(())
+        },
+        "delete" => {
+            let force = args.iter().any(|arg| arg == "--force");
+            operations::delete(state, arg(1, "what to delete")?, id(arg(2, "id")?)?, force).await?;
+            println!("Success");
+            Ok(())
+        },
+        "award" => {
+            let acquisition_date = flag_value(args, "--date")
+                .unwrap_or_else(|| actix_web::cookie::time::OffsetDateTime::now_utc().date().to_string());
+            let award = BadgeAward { badge_id: id(arg(1, "badge id")?)?, acquisition_date, team_ids: team_ids(&positional)? };
+            validation::check(&award).map_err(invalid)?;
+            print_json(&operations::award_badge(state, award).await?)
+        },
+        "revoke" => {
+            let revoke = BadgeRevoke { badge_id: id(arg(1, "badge id")?)?, team_ids: team_ids(&positional)? };
+            validation::check(&revoke).map_err(invalid)?;
+            print_json(&operations::revoke_badge(state, revoke).await?)
+        },
+        "recompute" => {
+            operations::update_rankings(state).await;
+            println!("Scores and rankings updated");
+            Ok(())
+        },
+        // The fixture file loads with the stored scores, recomputing fixes them
+        "seed" => {
+            let sql = match flag_value(args, "--file") {
+                Some(path) => std::fs::read_to_string(&path).map_err(|err| format!("Unable to read {}: {}", path, err))?,
+                None => String::from(match config.database.url.split(':').next().unwrap_or_default() {
+                    "mysql" | "mariadb" => MYSQL_SEED,
+                    "sqlite" => SQLITE_SEED,
+                    _ => PSQL_SEED,
+                }),
+            };
+            state.repo.seed(&sql).await?;
+            operations::update_rankings(state).await;
+            println!("Seeded {} teams", state.repo.teams().await.len());
+            Ok(())
+        },
+        "import" => {
+            let pool = state.repo.pool().ok_or(repository::UNSUPPORTED)?;
+            let read = |flag: &str| flag_value(args, flag).map(std::fs::read_to_string).transpose().map_err(|err| format!("Unable to read {}: {}", flag, err));
+            let files = ImportFiles { teams: read("--teams")?, persons: read("--persons")?, labels: read("--labels")?, badges: read("--badges")? };
+            match import::import(files, pool).await {
+                Ok(report) => {
+                    println!("Imported {} teams, {} persons, {} labels and {} badges", report.teams, report.persons, report.labels, report.badges);
+                    Ok(())
+                },
+                Err(errors) => {
+                    let rows:Vec<String> = errors.iter().map(|error| format!("{} row {}: {}", error.file, error.row, error.error)).collect();
+                    Err(format!("{}\nNothing was imported", rows.join("\n")))
+                },
+            }
+        },
+        "export" => {
+            let pool = state.repo.pool().ok_or(repository::UNSUPPORTED)?;
+            let dataset = export::load(pool).await.map_err(|err| format!("ERROR READING DATABASE: {}", err))?;
+            let format = flag_value(args, "--format").unwrap_or_else(|| String::from("json"));
+            let bytes = match &format[..] {
+                "json" => dataset.to_json().map_err(|err| err.to_string())?,
+                "csv" => dataset.to_csv_zip()?,
+                "xlsx" => dataset.to_xlsx()?,
+                _ => return Err(String::from("FORMAT IS NOT AVAILABLE FOR EXPORT")),
+            };
+            match flag_value(args, "--output") {
+                Some(path) => std::fs::write(&path, bytes).map_err(|err| format!("Unable to write {}: {}", path, err)),
+                None => {
+                    use std::io::Write;
+                    std::io::stdout().write_all(&bytes).map_err(|err| err.to_string())
+                },
+            }
+        },
+        "backup" => {
+            let pool = state.repo.pool().ok_or(repository::UNSUPPORTED)?;
+            let backup = backup::backup(pool).await.map_err(|err| format!("ERROR READING DATABASE: {}", err))?;
+            let backup = serde_json::to_string_pretty(&backup).unwrap();
+            match flag_value(args, "--output") {
+                Some(path) => std::fs::write(&path, backup).map_err(|err| format!("Unable to write {}: {}", path, err)),
+                None => {
+                    println!("{}", backup);
+                    Ok(())
+                },
+            }
+        },
+        "restore" => {
+            let pool = state.repo.pool().ok_or(repository::UNSUPPORTED)?;
+            let path = flag_value(args, "--input").ok_or_else(|| format!("Missing --input\n\n{}", USAGE))?;
+            let backup = std::fs::read_to_string(&path).map_err(|err| format!("Unable to read {}: {}", path, err))?;
+            let backup:Backup = serde_json::from_str(&backup).map_err(|err| format!("ERROR PARSING JSON: {}", err))?;
+            let report = backup::restore(backup, pool).await?;
+            println!("Restored {} teams, {} persons, {} labels, {} categories, {} badges, {} label ownerships and {} badge ownerships",
+                report.teams, report.persons, report.labels, report.badge_categories, report.badges, report.label_ownerships, report.badge_ownerships);
+            for mismatch in &report.mismatches {
+                println!("Team {} was stored with score {} and rank {:?}, recomputed as score {} and rank {:?}",
+                    mismatch.team_id, mismatch.backup_score, mismatch.backup_rank, mismatch.score, mismatch.rank);
+            }
+            Ok(())
+        },
+        // Exits with 1 while there are problems left
+        "integrity" => {
+            let pool = state.repo.pool().ok_or(repository::UNSUPPORTED)?;
+            if args.iter().any(|arg| arg == "--fix") {
+                let repair = integrity::repair(pool).await.map_err(|err| format!("ERROR REPAIRING DATABASE: {}", err))?;
+                println!("Deleted {} orphaned ownerships, fixed {} scores and {} ranks", repair.deleted_ownerships, repair.scores, repair.ranks);
+            }
+            let report = integrity::check(pool).await.map_err(|err| format!("ERROR READING DATABASE: {}", err))?;
+            for orphan in &report.orphans {
+                println!("{} {} has {} {}, which does not exist", orphan.table, orphan.id, orphan.column, orphan.missing_id);
+            }
+            for drift in &report.scores {
+                println!("Team {} ({}) has score {}, its badges add up to {}", drift.team_id, drift.name, drift.score, drift.expected);
+            }
+            let rank = |rank: Option<i32>| rank.map_or(String::from("none"), |rank| rank.to_string());
+            for drift in &report.ranks {
+                println!("Team {} has rank {}, its score gives rank {}", drift.team_id, rank(drift.rank), rank(drift.expected));
+            }
+            for duplicate in &report.duplicates {
+                println!("{} {:?} have names that only differ in case or spacing: {:?}", duplicate.table, duplicate.ids, duplicate.name);
+            }
+            if !report.consistent { return Err(String::from("The database has problems")); }
+            println!("No problems found");
+            Ok(())
+        },
+        other => Err(format!("Unknown command {}\n\n{}", other, USAGE)),
+    }
+}
+
+// Every team, sorted like `/teams`
+async fn teams(state: &AppState) -> Vec<Team> {
+    let raw_teams = state.repo.teams().await;
+    let teams = raw_teams.into_iter().map(|raw_team| Team::from(raw_team, state.repo.as_ref()));
+    let mut teams = future::join_all(teams).await;
+    Team::sort(&mut teams);
+    teams
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+    Ok(())
+}
+
+fn id(arg: &str) -> Result<i64, String> {
+    arg.parse().map_err(|_| format!("{} is not an id", arg))
+}
+
+// The arguments of a command without its flags and their values
+fn positional(args: &[String]) -> Vec<&str> {
+    let mut positional = vec![];
+    let mut i = 0;
+    while i < args.len() {
+        if !args[i].starts_with("--") {
+            positional.push(args[i].as_str());
+            i += 1;
+        } else {
+            i += if SWITCHES.contains(&args[i].as_str()) { 1 } else { 2 };
+        }
+    }
+    positional
+}
+
+// The ids after the badge id
+fn team_ids(positional: &[&str]) -> Result<Vec<i64>, String> {
+    positional.iter().skip(2).map(|arg| id(arg)).collect()
+}
+
+// A JSON body given inline, as `@file` or as `-` for stdin
+fn body(arg: &str) -> Result<String, String> {
+    if arg == "-" {
+        let mut json = String::new();
+        std::io::stdin().read_to_string(&mut json).map_err(|err| err.to_string())?;
+        return Ok(json);
+    }
+    match arg.strip_prefix('@') {
+        Some(path) => std::fs::read_to_string(path).map_err(|err| format!("Unable to read {}: {}", path, err)),
+        None => Ok(String::from(arg)),
+    }
+}
+
+fn parse<T: DeserializeOwned>(json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|err| format!("ERROR PARSING JSON: {}", err))
+}
+
+fn valid<T: DeserializeOwned + Validate>(json: &str) -> Result<T, String> {
+    let value = parse(json)?;
+    validation::check(&value).map_err(invalid)?;
+    Ok(value)
+}
+
+fn invalid(failure: ValidationFailure) -> String {
+    let errors:Vec<String> = failure.errors.iter().map(|error| format!("{}: {}", error.field, error.message)).collect();
+    errors.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').filter(|arg| !arg.is_empty()).map(String::from).collect()
+    }
+
+    #[test]
+    fn the_command_follows_the_configuration_flags() {
+        assert_eq!(command_start(&args("list teams")), 0);
+        assert_eq!(command_start(&args("--database-url sqlite://a.db list teams")), 2);
+        assert_eq!(command_start(&args("--database-url=sqlite://a.db --port 80 show 1")), 3);
+        assert_eq!(command_start(&args("--print-config recompute")), 1);
+        assert_eq!(command_start(&args("--config starterspace.toml --help")), 2);
+        // Only flags, or a flag missing its value
+        assert_eq!(command_start(&args("--port 80")), 2);
+        assert_eq!(command_start(&args("--port")), 1);
+        assert_eq!(command_start(&args("")), 0);
+    }
+
+    #[test]
+    fn flags_and_their_values_are_not_positional() {
+        assert_eq!(positional(&args("award --date 2023-05-01 1 2")), ["award", "1", "2"]);
+        assert_eq!(positional(&args("award 1 2 --date 2023-05-01 3")), ["award", "1", "2", "3"]);
+        assert_eq!(positional(&args("delete team --force 4")), ["delete", "team", "4"]);
+        assert_eq!(positional(&args("integrity --fix")), ["integrity"]);
+        assert_eq!(positional(&args("edit badge 1 -")), ["edit", "badge", "1", "-"]);
+    }
+
+    #[test]
+    fn team_ids_follow_the_badge_id() {
+        assert_eq!(team_ids(&positional(&args("award --date 2023-05-01 1 2 3"))), Ok(vec![2, 3]));
+        assert_eq!(team_ids(&positional(&args("revoke 1 4 --date 2023-05-01"))), Ok(vec![4]));
+        assert_eq!(team_ids(&positional(&args("revoke 1"))), Ok(vec![]));
+        assert_eq!(team_ids(&positional(&args("award 1 2 two"))), Err(String::from("two is not an id")));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::Team;
+
+// Keeps the assembled teams so `/teams` and `/team/{id}` don't hit the database
+// on every request. Every handler that writes to the database is responsible for
+// invalidating the entries it touches.
+pub struct TeamCache {
+    enabled: bool,
+    teams: RwLock<Option<Vec<Team>>>,
+    team: RwLock<HashMap<i64, Team>>,
+    // Bumped on every invalidation, so a load that started before a write
+    // doesn't store stale data after it.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub invalidations: u64,
+    pub cached_teams: usize,
+    pub list_cached: bool,
+}
+
+impl TeamCache {
+    pub fn new(enabled: bool) -> TeamCache {
+        TeamCache {
+            enabled,
+            teams: RwLock::new(None),
+            team: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Value to pass back to `store_*` once the data has been loaded.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn teams(&self) -> Option<Vec<Team>> {
+        if !self.enabled { return None; }
+        let teams = self.teams.read().unwrap().clone();
+        self.count(teams.is_some());
+        teams
+    }
+
+    pub fn team(&self, id: i64) -> Option<Team> {
+        if !self.enabled { return None; }
+        let team = self.team.read().unwrap().get(&id).cloned();
+        self.count(team.is_some());
+        team
+    }
+
+    pub fn store_teams(&self, teams: &[Team], generation: u64) {
+        if !self.enabled { return; }
+        let mut cached = self.teams.write().unwrap();
+        if self.generation() == generation {
+            *cached = Some(teams.to_vec());
+        }
+    }
+
+    pub fn store_team(&self, team: &Team, generation: u64) {
+        if !self.enabled { return; }
+        let mut cached = self.team.write().unwrap();
+        if self.generation() == generation {
+            cached.insert(team.id, team.clone());
+        }
+    }
+
+    // A team was added or removed without changing any other team.
+    pub fn invalidate_list(&self) {
+        let mut teams = self.teams.write().unwrap();
+        self.bump();
+        *teams = None;
+    }
+
+    // Only the team with `id` changed, but it is also part of the list.
+    pub fn invalidate_team(&self, id: i64) {
+        let mut teams = self.teams.write().unwrap();
+        let mut team = self.team.write().unwrap();
+        self.bump();
+        *teams = None;
+        team.remove(&id);
+    }
+
+    // Scores, rankings or shared objects (labels, badges, categories) changed.
+    pub fn invalidate_all(&self) {
+        let mut teams = self.teams.write().unwrap();
+        let mut team = self.team.write().unwrap();
+        self.bump();
+        *teams = None;
+        team.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let hit_rate = if hits + misses == 0 { 0.0 } else { hits as f64 / (hits + misses) as f64 };
+        CacheStats {
+            enabled: self.enabled,
+            hits,
+            misses,
+            hit_rate,
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+            cached_teams: self.team.read().unwrap().len(),
+            list_cached: self.teams.read().unwrap().is_some(),
+        }
+    }
+
+    fn bump(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn count(&self, hit: bool) {
+        if hit { self.hits.fetch_add(1, Ordering::Relaxed); }
+        else { self.misses.fetch_add(1, Ordering::Relaxed); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Team;
+    use super::TeamCache;
+
+    fn team(id: i64) -> Team {
+        let mut team = Team::new();
+        team.id = id;
+        team.name = format!("team {}", id);
+        team
+    }
+
+    // A cache holding the list and teams 1 and 2
+    fn filled() -> TeamCache {
+        let cache = TeamCache::new(true);
+        let generation = cache.generation();
+        cache.store_teams(&[team(1), team(2)], generation);
+        cache.store_team(&team(1), generation);
+        cache.store_team(&team(2), generation);
+        cache
+    }
+
+    #[test]
+    fn invalidating_a_team_keeps_the_others() {
+        let cache = filled();
+        cache.invalidate_team(1);
+        assert!(cache.team(1).is_none());
+        assert_eq!(cache.team(2).map(|team| team.id), Some(2));
+        // The team is part of the list
+        assert!(cache.teams().is_none());
+
+        let cache = filled();
+        cache.invalidate_list();
+        assert!(cache.teams().is_none());
+        assert!(cache.team(1).is_some() && cache.team(2).is_some());
+    }
+
+    #[test]
+    fn invalidating_all_empties_the_cache() {
+        let cache = filled();
+        cache.invalidate_all();
+        assert!(cache.teams().is_none());
+        assert!(cache.team(1).is_none() && cache.team(2).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.cached_teams, stats.list_cached, stats.invalidations), (0, false, 1));
+    }
+
+    #[test]
+    fn a_disabled_cache_stores_nothing() {
+        let cache = TeamCache::new(false);
+        cache.store_teams(&[team(1)], cache.generation());
+        cache.store_team(&team(1), cache.generation());
+        assert!(cache.teams().is_none() && cache.team(1).is_none());
+        let stats = cache.stats();
+        assert!(!stats.enabled);
+        // Disabled lookups are neither hits nor misses
+        assert_eq!((stats.hits, stats.misses, stats.cached_teams, stats.list_cached), (0, 0, 0, false));
+    }
+
+    #[test]
+    fn lookups_count_hits_and_misses() {
+        let cache = TeamCache::new(true);
+        assert_eq!(cache.stats().hit_rate, 0.0);
+        cache.teams();
+        cache.team(1);
+        cache.store_team(&team(1), cache.generation());
+        cache.team(1);
+        cache.team(1);
+        cache.team(2);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 3));
+        assert_eq!(stats.hit_rate, 0.4);
+        assert_eq!((stats.cached_teams, stats.list_cached), (1, false));
+    }
+
+    #[test]
+    fn reads_started_before_a_write_are_not_stored() {
+        let cache = TeamCache::new(true);
+        // A request reads the database, then a write invalidates before it stores
+        let generation = cache.generation();
+        cache.invalidate_team(1);
+        cache.store_team(&team(1), generation);
+        cache.store_teams(&[team(1)], generation);
+        assert!(cache.team(1).is_none() && cache.teams().is_none());
+
+        // A read started after the write is stored
+        let generation = cache.generation();
+        cache.store_team(&team(1), generation);
+        cache.store_teams(&[team(1)], generation);
+        assert!(cache.team(1).is_some() && cache.teams().is_some());
+    }
+}
@@ -0,0 +1,266 @@
+use std::io::Write;
+use futures::future;
+use crate::{Team, RawTeam, RawPerson, RawBadge, Category, BadgeOwnership};
+
+// Everything needed to rebuild the data for reporting, already in the shape
+// of the tables that are handed out.
+pub struct Dataset {
+    pub teams: Vec<Team>,
+    pub sheets: Vec<Sheet>,
+}
+
+pub struct Sheet {
+    pub name: &'static str,
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+pub enum Cell {
+    Text(String),
+    Number(i64),
+    Empty,
+}
+
+impl Cell {
+    fn text(value: &Option<String>) -> Cell {
+        match value {
+            Some(value) => Cell::Text(value.clone()),
+            None => Cell::Empty,
+        }
+    }
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::Text(text) => text.clone(),
+            Cell::Number(number) => number.to_string(),
+            Cell::Empty => String::new(),
+        }
+    }
+}
+
+pub async fn load(pool: &sqlx::postgres::PgPool) -> Result<Dataset, sqlx::Error> {
+    let raw_teams = sqlx::query_as::<sqlx::postgres::Postgres, RawTeam>("SELECT * FROM teams")
+        .fetch_all(pool)
+        .await?;
+    let repo = crate::repository::PgRepository::new(pool.clone());
+    let teams = raw_teams.into_iter().map(|raw_team| Team::from(raw_team, &repo));
+    let mut teams = future::join_all(teams).await;
+    Team::sort(&mut teams);
+    let persons = sqlx::query_as::<sqlx::postgres::Postgres, RawPerson>("SELECT * FROM persons ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let badges = sqlx::query_as::<sqlx::postgres::Postgres, RawBadge>("SELECT * FROM badges ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let categories = sqlx::query_as::<sqlx::postgres::Postgres, Category>("SELECT * FROM badge_categories ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    let ownerships = sqlx::query_as::<sqlx::postgres::Postgres, BadgeOwnership>("SELECT * FROM badge_ownerships ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+    Ok(Dataset::from(teams, persons, badges, categories, ownerships))
+}
+
+impl Dataset {
+    // `teams` in the order of `/teams`, the other rows by id
+    pub fn from(teams: Vec<Team>, persons: Vec<RawPerson>, badges: Vec<RawBadge>, categories: Vec<Category>, ownerships: Vec<BadgeOwnership>) -> Dataset {
+        let team_sheet = Sheet {
+            name: "teams",
+            header: vec!["id", "name", "score", "rank", "stage", "labels", "description", "creation_date", "location", "logo_url", "banner_url"],
+            rows: teams.iter().map(|team| vec![
+                Cell::Number(team.id),
+                Cell::Text(team.name.clone()),
+                Cell::Number(team.score),
+                match team.rank { Some(rank) => Cell::Number(rank as i64), None => Cell::Empty },
+                Cell::Number(team.stage as i64),
+                Cell::Text(team.labels.iter().map(|label| label.name.clone()).collect::<Vec<String>>().join(";")),
+                Cell::Text(team.description.clone()),
+                Cell::Text(team.creation_date.clone()),
+                Cell::Text(team.location.clone()),
+                Cell::text(&team.logo_url),
+                Cell::text(&team.banner_url),
+            ]).collect(),
+        };
+        let person_sheet = Sheet {
+            name: "persons",
+            header: vec!["id", "team_id", "name", "career", "graduation_date", "picture_url", "portafolio_url"],
+            rows: persons.iter().map(|person| vec![
+                Cell::Number(person.id),
+                Cell::Number(person.team_id),
+                Cell::Text(person.name.clone()),
+                Cell::Text(person.career.clone()),
+                Cell::Text(person.graduation_date.to_string()),
+                Cell::text(&person.picture_url),
+                Cell::text(&person.portafolio_url),
+            ]).collect(),
+        };
+        let badge_sheet = Sheet {
+            name: "badges",
+            header: vec!["id", "name", "description", "points", "category_id", "category"],
+            rows: badges.iter().map(|badge| vec![
+                Cell::Number(badge.id),
+                Cell::Text(badge.name.clone()),
+                Cell::Text(badge.description.clone()),
+                Cell::Number(badge.points),
+                Cell::Number(badge.category),
+                match categories.iter().find(|category| category.id == badge.category) {
+                    Some(category) => Cell::Text(category.name.clone()),
+                    None => Cell::Empty,
+                },
+            ]).collect(),
+        };
+        let ownership_sheet = Sheet {
+            name: "badge_ownerships",
+            header: vec!["id", "team_id", "badge_id", "acquisition_date"],
+            rows: ownerships.iter().map(|ownership| vec![
+                Cell::Number(ownership.id),
+                Cell::Number(ownership.team_id),
+                Cell::Number(ownership.badge_id),
+                Cell::Text(ownership.acquisition_date.to_string()),
+            ]).collect(),
+        };
+
+        Dataset {
+            teams,
+            sheets: vec![team_sheet, person_sheet, badge_sheet, ownership_sheet],
+        }
+    }
+
+    // Same serialization as `/teams`
+    pub fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(&self.teams)
+    }
+
+    // A zip with one CSV file per sheet
+    pub fn to_csv_zip(&self) -> Result<Vec<u8>, String> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        for sheet in &self.sheets {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            writer.write_record(&sheet.header).map_err(|err| err.to_string())?;
+            for row in &sheet.rows {
+                writer.write_record(row.iter().map(Cell::to_csv)).map_err(|err| err.to_string())?;
+            }
+            let bytes = writer.into_inner().map_err(|err| err.to_string())?;
+            zip.start_file(format!("{}.csv", sheet.name), options).map_err(|err| err.to_string())?;
+            zip.write_all(&bytes).map_err(|err| err.to_string())?;
+        }
+        let cursor = zip.finish().map_err(|err| err.to_string())?;
+        Ok(cursor.into_inner())
+    }
+
+    // A workbook with one worksheet per sheet
+    pub fn to_xlsx(&self) -> Result<Vec<u8>, String> {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        for sheet in &self.sheets {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(sheet.name).map_err(|err| err.to_string())?;
+            for (col, title) in sheet.header.iter().enumerate() {
+                worksheet.write_string(0, col as u16, *title).map_err(|err| err.to_string())?;
+            }
+            for (row, cells) in sheet.rows.iter().enumerate() {
+                for (col, cell) in cells.iter().enumerate() {
+                    let (row, col) = (row as u32 + 1, col as u16);
+                    match cell {
+                        Cell::Text(text) => { worksheet.write_string(row, col, text).map_err(|err| err.to_string())?; },
+                        Cell::Number(number) => { worksheet.write_number(row, col, *number as f64).map_err(|err| err.to_string())?; },
+                        Cell::Empty => {},
+                    }
+                }
+            }
+        }
+        workbook.save_to_buffer().map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use actix_web::cookie::time::{Date, Month};
+    use serde_json::json;
+    use crate::*;
+    use super::Dataset;
+
+    fn date(day: u8) -> Date {
+        Date::from_calendar_date(2023, Month::May, day).unwrap()
+    }
+
+    // Rocket has a label, a badge and a person, Comet has nothing
+    fn dataset() -> Dataset {
+        let category = Category { id: 1, name: String::from("Pitch") };
+        let badge = RawBadge { id: 5, name: String::from("Demo day"), description: String::from("Pitched, twice"), points: 300, category: 1 };
+        let ownership = BadgeOwnership { id: 9, team_id: 1, badge_id: 5, acquisition_date: date(1) };
+        let mut rocket = Team::new();
+        rocket.id = 1;
+        rocket.name = String::from("Rocket");
+        rocket.score = 300;
+        rocket.rank = Some(1);
+        rocket.stage = 2;
+        rocket.creation_date = String::from("2023-02-26");
+        rocket.labels = vec![Label { id: 3, name: String::from("Fintech") }, Label { id: 4, name: String::from("AI") }];
+        rocket.badges = vec![OwnedBadge::from(badge.clone(), category.clone(), &ownership)];
+        rocket.persons = vec![Person { id: 7, team_id: 1, name: String::from("Ana"), career: String::from("ITC"), graduation_date: String::from("2023-05-02"), picture_url: None, portafolio_url: None }];
+        let mut comet = Team::new();
+        comet.id = 2;
+        comet.name = String::from("Comet");
+        comet.logo_url = Some(String::from("https://comet.dev/logo.png"));
+        let person = RawPerson { id: 7, team_id: 1, name: String::from("Ana"), career: String::from("ITC"), graduation_date: date(2), picture_url: None, portafolio_url: None };
+        Dataset::from(vec![rocket, comet], vec![person], vec![badge], vec![category], vec![ownership])
+    }
+
+    fn unzip(bytes: Vec<u8>) -> zip::ZipArchive<std::io::Cursor<Vec<u8>>> {
+        zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    fn entry(archive: &mut zip::ZipArchive<std::io::Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut text = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn csv_zip_has_a_file_per_sheet() {
+        let mut archive = unzip(dataset().to_csv_zip().unwrap());
+        let mut names:Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["badge_ownerships.csv", "badges.csv", "persons.csv", "teams.csv"]);
+
+        assert_eq!(entry(&mut archive, "teams.csv"), "\
+            id,name,score,rank,stage,labels,description,creation_date,location,logo_url,banner_url\n\
+            1,Rocket,300,1,2,Fintech;AI,,2023-02-26,,,\n\
+            2,Comet,0,,0,,,,,https://comet.dev/logo.png,\n");
+        assert_eq!(entry(&mut archive, "persons.csv"), "\
+            id,team_id,name,career,graduation_date,picture_url,portafolio_url\n\
+            7,1,Ana,ITC,2023-05-02,,\n");
+        // Cells with commas are quoted
+        assert_eq!(entry(&mut archive, "badges.csv"), "\
+            id,name,description,points,category_id,category\n\
+            5,Demo day,\"Pitched, twice\",300,1,Pitch\n");
+        assert_eq!(entry(&mut archive, "badge_ownerships.csv"), "\
+            id,team_id,badge_id,acquisition_date\n\
+            9,1,5,2023-05-01\n");
+    }
+
+    #[test]
+    fn json_is_the_teams_serialization() {
+        let dataset = dataset();
+        let exported:serde_json::Value = serde_json::from_slice(&dataset.to_json().unwrap()).unwrap();
+        assert_eq!(exported, serde_json::to_value(&dataset.teams).unwrap());
+        let rocket = &exported[0];
+        assert_eq!(rocket["labels"], json!([{ "id": 3, "name": "Fintech" }, { "id": 4, "name": "AI" }]));
+        assert_eq!(rocket["badges"], json!([{
+            "id": 9,
+            "acquisition_date": "2023-05-01",
+            "badge": { "id": 5, "name": "Demo day", "description": "Pitched, twice", "points": 300, "category": { "id": 1, "name": "Pitch" } },
+        }]));
+        assert_eq!(rocket["persons"][0]["name"], "Ana");
+        assert_eq!(exported[1]["logo_url"], "https://comet.dev/logo.png");
+    }
+
+    #[test]
+    fn xlsx_has_a_worksheet_per_sheet() {
+        let mut workbook = unzip(dataset().to_xlsx().unwrap());
+        let workbook = entry(&mut workbook, "xl/workbook.xml");
+        let sheets:Vec<&str> = workbook.split("<sheet name=\"").skip(1).map(|sheet| sheet.split('"').next().unwrap()).collect();
+        assert_eq!(sheets, ["teams", "persons", "badges", "badge_ownerships"]);
+    }
+}
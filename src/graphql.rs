@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_graphql::{ComplexObject, Context, EmptySubscription, Enum, ErrorExtensions, InputObject, MaybeUndefined, Object, Schema, SimpleObject};
+use async_graphql::dataloader::{DataLoader, Loader};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use crate::*;
+
+// GraphQL view of the same tables served by the REST handlers. Relationships are
+// resolved through a per request `DataLoader`, so asking for the persons of every
+// team runs one query instead of one per team. Mutations go through `operations`.
+pub type ApiSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+pub fn schema(state: AppState) -> ApiSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(state)
+        .finish()
+}
+
+// Has to be added to every request, its cache only lives as long as the request
+pub fn loader(pool: &sqlx::postgres::PgPool) -> DataLoader<PgLoader> {
+    DataLoader::new(PgLoader { pool: pool.clone(), #[cfg(test)] loads: Default::default() }, actix_web::rt::spawn)
+}
+
+// Dates are returned as `YYYY-MM-DD`
+const TEAM_COLUMNS: &str = "id, rank, score, stage, name, logo_url, banner_url, description, to_char(creation_date, 'YYYY-MM-DD') AS creation_date, location";
+const PERSON_COLUMNS: &str = "id, team_id, name, career, to_char(graduation_date, 'YYYY-MM-DD') AS graduation_date, picture_url, portafolio_url";
+const BADGE_COLUMNS: &str = "id, name, description, points, category AS category_id";
+const OWNERSHIP_COLUMNS: &str = "id, team_id, badge_id, to_char(acquisition_date, 'YYYY-MM-DD') AS acquisition_date";
+
+// Page size used when `first` is not given, and the biggest one allowed
+const MAX_PAGE: i64 = 100;
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "Team")]
+pub struct TeamNode {
+    pub id: i64,
+    pub rank: Option<i32>,
+    pub score: i64,
+    pub stage: i32,
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub banner_url: Option<String>,
+    pub description: String,
+    pub creation_date: String,
+    pub location: String,
+}
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "Person")]
+pub struct PersonNode {
+    pub id: i64,
+    pub team_id: i64,
+    pub name: String,
+    pub career: String,
+    pub graduation_date: String,
+    pub picture_url: Option<String>,
+    pub portafolio_url: Option<String>,
+}
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "Badge")]
+pub struct BadgeNode {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub points: i64,
+    pub category_id: i64,
+}
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "Category")]
+pub struct CategoryNode {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "Label")]
+pub struct LabelNode {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(SimpleObject, FromRow, Clone, Debug)]
+#[graphql(complex, name = "BadgeOwnership")]
+pub struct BadgeOwnershipNode {
+    pub id: i64,
+    pub team_id: i64,
+    pub badge_id: i64,
+    pub acquisition_date: String,
+}
+
+// Row of a many to many table, `id` is the object linked to `owner`
+#[derive(FromRow, Clone, Debug)]
+pub struct Link {
+    pub owner: i64,
+    pub id: i64,
+}
+
+// Loader keys, one per kind of lookup
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TeamId(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PersonId(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BadgeId(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CategoryId(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LabelId(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PersonsOfTeam(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LabelsOfTeam(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BadgesOfTeam(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OwnersOfBadge(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TeamsOfLabel(pub i64);
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BadgesOfCategory(pub i64);
+
+pub struct PgLoader {
+    pool: sqlx::postgres::PgPool,
+    // Batches loaded, tests check with it that lookups are grouped
+    #[cfg(test)]
+    loads: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+type LoadResult<K, V> = Result<HashMap<K, V>, Arc<sqlx::Error>>;
+
+impl PgLoader {
+    async fn by_id<T>(&self, table: &str, columns: &str, ids: Vec<i64>) -> Result<HashMap<i64, T>, Arc<sqlx::Error>>
+    where T: for<'r> FromRow<'r, sqlx::postgres::PgRow> + Send + Unpin + HasId {
+        #[cfg(test)]
+        self.loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let rows = sqlx::query_as::<Postgres, T>(&format!("SELECT {} FROM {} WHERE id = ANY($1)", columns, table))
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|row| (row.id(), row)).collect())
+    }
+
+    // Every owner gets an entry, even when nothing points to it
+    async fn grouped<T>(&self, query: &str, owners: Vec<i64>, owner: impl Fn(&T) -> i64) -> Result<HashMap<i64, Vec<T>>, Arc<sqlx::Error>>
+    where T: for<'r> FromRow<'r, sqlx::postgres::PgRow> + Send + Unpin {
+        #[cfg(test)]
+        self.loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let rows = sqlx::query_as::<Postgres, T>(query)
+            .bind(&owners)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut groups:HashMap<i64, Vec<T>> = owners.into_iter().map(|id| (id, vec![])).collect();
+        for row in rows {
+            groups.entry(owner(&row)).or_default().push(row);
+        }
+        Ok(groups)
+    }
+}
+
+pub trait HasId {
+    fn id(&self) -> i64;
+}
+
+macro_rules! has_id {
+    ($($node:ty),*) => { $(impl HasId for $node { fn id(&self) -> i64 { self.id } })* };
+}
+has_id!(TeamNode, PersonNode, BadgeNode, CategoryNode, LabelNode);
+
+// Loads nodes by id into a map keyed by the loader key
+macro_rules! load_by_id {
+    ($key:ident, $node:ty, $table:expr, $columns:expr) => {
+        impl Loader<$key> for PgLoader {
+            type Value = $node;
+            type Error = Arc<sqlx::Error>;
+            async fn load(&self, keys: &[$key]) -> LoadResult<$key, $node> {
+                let nodes = self.by_id::<$node>($table, $columns, keys.iter().map(|key| key.0).collect()).await?;
+                Ok(nodes.into_iter().map(|(id, node)| ($key(id), node)).collect())
+            }
+        }
+    };
+}
+load_by_id!(TeamId, TeamNode, "teams", TEAM_COLUMNS);
+load_by_id!(PersonId, PersonNode, "persons", PERSON_COLUMNS);
+load_by_id!(BadgeId, BadgeNode, "badges", BADGE_COLUMNS);
+load_by_id!(CategoryId, CategoryNode, "badge_categories", "id, name");
+load_by_id!(LabelId, LabelNode, "labels", "id, name");
+
+// Loads the rows owned by each key, `$owner` is the column pointing to it
+macro_rules! load_grouped {
+    ($key:ident, $node:ty, $query:expr, $owner:ident) => {
+        impl Loader<$key> for PgLoader {
+            type Value = Vec<$node>;
+            type Error = Arc<sqlx::Error>;
+            async fn load(&self, keys: &[$key]) -> LoadResult<$key, Vec<$node>> {
+                let groups = self.grouped::<$node>(&$query, keys.iter().map(|key| key.0).collect(), |row| row.$owner).await?;
+                Ok(groups.into_iter().map(|(id, rows)| ($key(id), rows)).collect())
+            }
+        }
+    };
+}
+load_grouped!(PersonsOfTeam, PersonNode, format!("SELECT {} FROM persons WHERE team_id = ANY($1) ORDER BY id", PERSON_COLUMNS), team_id);
+load_grouped!(BadgesOfTeam, BadgeOwnershipNode, format!("SELECT {} FROM badge_ownerships WHERE team_id = ANY($1) ORDER BY id", OWNERSHIP_COLUMNS), team_id);
+load_grouped!(OwnersOfBadge, BadgeOwnershipNode, format!("SELECT {} FROM badge_ownerships WHERE badge_id = ANY($1) ORDER BY id", OWNERSHIP_COLUMNS), badge_id);
+load_grouped!(BadgesOfCategory, BadgeNode, format!("SELECT {} FROM badges WHERE category = ANY($1) ORDER BY id", BADGE_COLUMNS), category_id);
+load_grouped!(LabelsOfTeam, Link, String::from("SELECT team_id AS owner, label_id AS id FROM label_ownerships WHERE team_id = ANY($1) ORDER BY id"), owner);
+load_grouped!(TeamsOfLabel, Link, String::from("SELECT label_id AS owner, team_id AS id FROM label_ownerships WHERE label_id = ANY($1) ORDER BY id"), owner);
+
+fn loader_of<'a>(ctx: &Context<'a>) -> &'a DataLoader<PgLoader> {
+    ctx.data_unchecked::<DataLoader<PgLoader>>()
+}
+
+// Keeps the order of `ids`, dropping the ones that don't exist
+async fn load_many<K, T>(ctx: &Context<'_>, ids: Vec<i64>, key: fn(i64) -> K) -> async_graphql::Result<Vec<T>>
+where K: Copy + Eq + std::hash::Hash + Send + Sync + 'static, PgLoader: Loader<K, Value = T, Error = Arc<sqlx::Error>>, T: Clone + Send + Sync + 'static {
+    let mut nodes = loader_of(ctx).load_many(ids.iter().map(|id| key(*id))).await?;
+    Ok(ids.into_iter().filter_map(|id| nodes.remove(&key(id))).collect())
+}
+
+#[ComplexObject]
+impl TeamNode {
+    async fn labels(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<LabelNode>> {
+        let links = loader_of(ctx).load_one(LabelsOfTeam(self.id)).await?.unwrap_or_default();
+        load_many(ctx, links.iter().map(|link| link.id).collect(), LabelId).await
+    }
+    async fn persons(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<PersonNode>> {
+        Ok(loader_of(ctx).load_one(PersonsOfTeam(self.id)).await?.unwrap_or_default())
+    }
+    async fn badges(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<BadgeOwnershipNode>> {
+        Ok(loader_of(ctx).load_one(BadgesOfTeam(self.id)).await?.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl PersonNode {
+    async fn team(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<TeamNode>> {
+        Ok(loader_of(ctx).load_one(TeamId(self.team_id)).await?)
+    }
+}
+
+#[ComplexObject]
+impl BadgeNode {
+    async fn category(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<CategoryNode>> {
+        Ok(loader_of(ctx).load_one(CategoryId(self.category_id)).await?)
+    }
+    async fn owners(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<BadgeOwnershipNode>> {
+        Ok(loader_of(ctx).load_one(OwnersOfBadge(self.id)).await?.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl CategoryNode {
+    async fn badges(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<BadgeNode>> {
+        Ok(loader_of(ctx).load_one(BadgesOfCategory(self.id)).await?.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl LabelNode {
+    async fn teams(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TeamNode>> {
+        let links = loader_of(ctx).load_one(TeamsOfLabel(self.id)).await?.unwrap_or_default();
+        load_many(ctx, links.iter().map(|link| link.id).collect(), TeamId).await
+    }
+}
+
+#[ComplexObject]
+impl BadgeOwnershipNode {
+    async fn team(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<TeamNode>> {
+        Ok(loader_of(ctx).load_one(TeamId(self.team_id)).await?)
+    }
+    async fn badge(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<BadgeNode>> {
+        Ok(loader_of(ctx).load_one(BadgeId(self.badge_id)).await?)
+    }
+}
+
+// Runs `query` adding the ordering and the page limits
+async fn page<T>(ctx: &Context<'_>, mut query: QueryBuilder<'_, Postgres>, order: &str, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<T>>
+where T: for<'r> FromRow<'r, sqlx::postgres::PgRow> + Send + Unpin {
+    let first = first.unwrap_or(MAX_PAGE).clamp(0, MAX_PAGE);
+    let offset = offset.unwrap_or(0).max(0);
+    query.push(" ORDER BY ").push(order)
+        .push(" LIMIT ").push_bind(first)
+        .push(" OFFSET ").push_bind(offset);
+    let state = ctx.data_unchecked::<AppState>();
+    let pool = state.repo.pool().ok_or(crate::repository::UNSUPPORTED)?;
+    Ok(query.build_query_as::<T>().fetch_all(pool).await?)
+}
+
+// Every given field has to match, `name` is a case insensitive substring
+#[derive(InputObject, Default)]
+pub struct TeamFilter {
+    pub stage: Option<i32>,
+    pub min_score: Option<i64>,
+    pub label_id: Option<i64>,
+    pub name: Option<String>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // Sorted like `/teams`, by score and then by name
+    async fn teams(&self, ctx: &Context<'_>, #[graphql(default)] filter: TeamFilter, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<TeamNode>> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM teams WHERE TRUE", TEAM_COLUMNS));
+        if let Some(stage) = filter.stage { query.push(" AND stage = ").push_bind(stage); }
+        if let Some(min_score) = filter.min_score { query.push(" AND score >= ").push_bind(min_score); }
+        if let Some(label_id) = filter.label_id {
+            query.push(" AND id IN (SELECT team_id FROM label_ownerships WHERE label_id = ").push_bind(label_id).push(")");
+        }
+        if let Some(name) = filter.name { query.push(" AND name ILIKE ").push_bind(format!("%{}%", name)); }
+        page(ctx, query, "score DESC, name", first, offset).await
+    }
+    async fn team(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<TeamNode>> {
+        Ok(loader_of(ctx).load_one(TeamId(id)).await?)
+    }
+    async fn persons(&self, ctx: &Context<'_>, team_id: Option<i64>, name: Option<String>, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<PersonNode>> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM persons WHERE TRUE", PERSON_COLUMNS));
+        if let Some(team_id) = team_id { query.push(" AND team_id = ").push_bind(team_id); }
+        if let Some(name) = name { query.push(" AND name ILIKE ").push_bind(format!("%{}%", name)); }
+        page(ctx, query, "id", first, offset).await
+    }
+    async fn person(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<PersonNode>> {
+        Ok(loader_of(ctx).load_one(PersonId(id)).await?)
+    }
+    async fn badges(&self, ctx: &Context<'_>, category_id: Option<i64>, min_points: Option<i64>, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<BadgeNode>> {
+        let mut query = QueryBuilder::new(format!("SELECT {} FROM badges WHERE TRUE", BADGE_COLUMNS));
+        if let Some(category_id) = category_id { query.push(" AND category = ").push_bind(category_id); }
+        if let Some(min_points) = min_points { query.push(" AND points >= ").push_bind(min_points); }
+        page(ctx, query, "id", first, offset).await
+    }
+    async fn badge(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<BadgeNode>> {
+        Ok(loader_of(ctx).load_one(BadgeId(id)).await?)
+    }
+    async fn categories(&self, ctx: &Context<'_>, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<CategoryNode>> {
+        page(ctx, QueryBuilder::new("SELECT id, name FROM badge_categories"), "id", first, offset).await
+    }
+    async fn category(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<CategoryNode>> {
+        Ok(loader_of(ctx).load_one(CategoryId(id)).await?)
+    }
+    async fn labels(&self, ctx: &Context<'_>, first: Option<i64>, offset: Option<i64>) -> async_graphql::Result<Vec<LabelNode>> {
+        page(ctx, QueryBuilder::new("SELECT id, name FROM labels"), "id", first, offset).await
+    }
+    async fn label(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<LabelNode>> {
+        Ok(loader_of(ctx).load_one(LabelId(id)).await?)
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteKind {
+    Label,
+    Badge,
+    Category,
+    Person,
+    Team,
+}
+
+impl DeleteKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeleteKind::Label => "label",
+            DeleteKind::Badge => "badge",
+            DeleteKind::Category => "category",
+            DeleteKind::Person => "person",
+            DeleteKind::Team => "team",
+        }
+    }
+}
+
+// Urls can be cleared by passing `null`, leaving a field out keeps it
+#[derive(InputObject)]
+pub struct EditTeamInput {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub stage: Option<i32>,
+    pub creation_date: Option<String>,
+    pub location: Option<String>,
+    pub logo_url: MaybeUndefined<String>,
+    pub banner_url: MaybeUndefined<String>,
+}
+
+#[derive(InputObject)]
+pub struct EditPersonInput {
+    pub name: Option<String>,
+    pub team_id: Option<i64>,
+    pub career: Option<String>,
+    pub graduation_date: Option<String>,
+    pub picture_url: MaybeUndefined<String>,
+    pub portafolio_url: MaybeUndefined<String>,
+}
+
+#[derive(InputObject)]
+pub struct EditBadgeInput {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub points: Option<i64>,
+    pub category: Option<i64>,
+}
+
+#[derive(InputObject)]
+pub struct EditNameInput {
+    pub name: Option<String>,
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a AppState {
+    ctx.data_unchecked::<AppState>()
+}
+
+// Same rules as the REST payloads, the failing fields go in the `errors` extension
+fn invalid(failure: validation::ValidationFailure) -> async_graphql::Error {
+    let errors = async_graphql::Value::from_json(serde_json::to_value(&failure.errors).unwrap()).unwrap();
+    async_graphql::Error::new("INVALID FIELDS").extend_with(|_, extensions| extensions.set("errors", errors))
+}
+
+fn checked<T: validator::Validate>(input: &T) -> async_graphql::Result<()> {
+    validation::check(input).map_err(invalid)
+}
+
+async fn edit(ctx: &Context<'_>, id: i64, body: EditBody) -> async_graphql::Result<bool> {
+    body.check().map_err(invalid)?;
+    operations::edit(state(ctx), id, body).await?;
+    Ok(true)
+}
+
+// Runs a create or add mutation at most once per `Idempotency-Key`
+async fn once<I, T, F>(ctx: &Context<'_>, input: I, run: impl FnOnce(I) -> F) -> async_graphql::Result<T>
+where I: serde::Serialize, T: serde::Serialize + serde::de::DeserializeOwned, F: std::future::Future<Output = async_graphql::Result<T>> {
+    let Some(key) = ctx.data_opt::<idempotency::GraphqlKey>() else { return run(input).await };
+    let field = ctx.path_node.map(|node| node.to_string()).unwrap_or_default();
+    let body = serde_json::to_vec(&input).unwrap();
+    state(ctx).idempotency.once(&key.0, &field, &body, run(input)).await
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn create_team(&self, ctx: &Context<'_>, input: CreateTeam) -> async_graphql::Result<i64> {
+        checked(&input)?;
+        once(ctx, input, |input| async move { Ok(operations::create_team(state(ctx), input).await?) }).await
+    }
+    async fn add_label(&self, ctx: &Context<'_>, input: CreateLabelOwnership) -> async_graphql::Result<bool> {
+        once(ctx, input, |input| async move {
+            operations::add_label(state(ctx), input).await?;
+            Ok(true)
+        }).await
+    }
+    async fn add_badge(&self, ctx: &Context<'_>, input: CreateBadgeOwnership) -> async_graphql::Result<bool> {
+        checked(&input)?;
+        once(ctx, input, |input| async move {
+            operations::add_badge(state(ctx), input).await?;
+            Ok(true)
+        }).await
+    }
+    async fn award_badge(&self, ctx: &Context<'_>, input: BadgeAward) -> async_graphql::Result<AwardReport> {
+        checked(&input)?;
+        once(ctx, input, |input| async move { Ok(operations::award_badge(state(ctx), input).await?) }).await
+    }
+    async fn revoke_badge(&self, ctx: &Context<'_>, input: BadgeRevoke) -> async_graphql::Result<RevokeReport> {
+        checked(&input)?;
+        Ok(operations::revoke_badge(state(ctx), input).await?)
+    }
+    async fn add_person(&self, ctx: &Context<'_>, input: CreatePerson) -> async_graphql::Result<bool> {
+        checked(&input)?;
+        once(ctx, input, |input| async move {
+            operations::add_person(state(ctx), input).await?;
+            Ok(true)
+        }).await
+    }
+    async fn create_badge(&self, ctx: &Context<'_>, input: CreateBadge) -> async_graphql::Result<i64> {
+        checked(&input)?;
+        once(ctx, input, |input| async move { Ok(operations::create_badge(state(ctx), input).await?) }).await
+    }
+    async fn create_label(&self, ctx: &Context<'_>, input: CreateLabel) -> async_graphql::Result<i64> {
+        checked(&input)?;
+        once(ctx, input, |input| async move { Ok(operations::create_label(state(ctx), input).await?) }).await
+    }
+    async fn create_category(&self, ctx: &Context<'_>, input: CreateCategory) -> async_graphql::Result<i64> {
+        checked(&input)?;
+        once(ctx, input, |input| async move { Ok(operations::create_category(state(ctx), input).await?) }).await
+    }
+    async fn remove_label(&self, ctx: &Context<'_>, input: DeleteOwnedLabel) -> async_graphql::Result<bool> {
+        operations::remove_label(state(ctx), input).await?;
+        Ok(true)
+    }
+    async fn remove_badge(&self, ctx: &Context<'_>, input: DeleteOwnedBadge) -> async_graphql::Result<bool> {
+        operations::remove_badge(state(ctx), input).await?;
+        Ok(true)
+    }
+    async fn delete(&self, ctx: &Context<'_>, kind: DeleteKind, id: i64, #[graphql(default)] force: bool) -> async_graphql::Result<bool> {
+        operations::delete(state(ctx), kind.as_str(), id, force).await?;
+        Ok(true)
+    }
+    async fn edit_team(&self, ctx: &Context<'_>, id: i64, input: EditTeamInput) -> async_graphql::Result<bool> {
+        let team = EditTeam {
+            name: input.name.into(),
+            description: input.description.into(),
+            stage: input.stage.into(),
+            creation_date: input.creation_date.into(),
+            location: input.location.into(),
+            logo_url: input.logo_url.into(),
+            banner_url: input.banner_url.into(),
+        };
+        edit(ctx, id, EditBody::Team(team)).await
+    }
+    async fn edit_person(&self, ctx: &Context<'_>, id: i64, input: EditPersonInput) -> async_graphql::Result<bool> {
+        let person = EditPerson {
+            name: input.name.into(),
+            team_id: input.team_id.into(),
+            career: input.career.into(),
+            graduation_date: input.graduation_date.into(),
+            picture_url: input.picture_url.into(),
+            portafolio_url: input.portafolio_url.into(),
+        };
+        edit(ctx, id, EditBody::Person(person)).await
+    }
+    async fn edit_badge(&self, ctx: &Context<'_>, id: i64, input: EditBadgeInput) -> async_graphql::Result<bool> {
+        let badge = EditBadge {
+            name: input.name.into(),
+            description: input.description.into(),
+            points: input.points.into(),
+            category: input.category.into(),
+        };
+        edit(ctx, id, EditBody::Badge(badge)).await
+    }
+    async fn edit_label(&self, ctx: &Context<'_>, id: i64, input: EditNameInput) -> async_graphql::Result<bool> {
+        edit(ctx, id, EditBody::Label(EditLabel { name: input.name.into() })).await
+    }
+    async fn edit_category(&self, ctx: &Context<'_>, id: i64, input: EditNameInput) -> async_graphql::Result<bool> {
+        edit(ctx, id, EditBody::Category(EditCategory { name: input.name.into() })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use serde_json::{json, Value};
+    use crate::scratch::Scratch;
+
+    // The seed: Startup-tec (1) and Moneypool (2), both with the two badges and 900 points
+    async fn scratch(test: &str) -> (Scratch, AppState) {
+        let scratch = Scratch::new(test).await;
+        scratch.seed().await;
+        let state = AppState { cache: Arc::new(cache::TeamCache::new(true)), ..AppState::new(Arc::new(repository::PgRepository::new(scratch.pool.clone()))) };
+        (scratch, state)
+    }
+
+    // Runs `query` with a loader of its own, returning the data and the batches it loaded
+    async fn run(state: &AppState, query: &str) -> (Value, usize) {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let loader = PgLoader { pool: state.repo.pool().unwrap().clone(), loads: loads.clone() };
+        let request = async_graphql::Request::new(query).data(DataLoader::new(loader, actix_web::rt::spawn));
+        let response = schema(state.clone()).execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        (response.data.into_json().unwrap(), loads.load(Ordering::SeqCst))
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn nested_relationships_are_loaded_in_batches() {
+        let (scratch, state) = scratch("graphql_nested").await;
+        let (data, loads) = run(&state, "{ teams { name persons { name } labels { name } badges { acquisitionDate badge { name points category { name } } } } }").await;
+        let badges = json!([
+            { "acquisitionDate": "2023-01-23", "badge": { "name": "Logro 1", "points": 200, "category": { "name": "Stage 1" } } },
+            { "acquisitionDate": "2023-01-23", "badge": { "name": "Platica de Felipe Ivan", "points": 700, "category": { "name": "Talk" } } },
+        ]);
+        assert_eq!(data, json!({ "teams": [
+            { "name": "Moneypool", "persons": [{ "name": "Nacho" }], "labels": [{ "name": "Web3" }, { "name": "Edtec" }], "badges": badges },
+            { "name": "Startup-tec", "persons": [{ "name": "Felipe Ivan" }], "labels": [{ "name": "Edtec" }, { "name": "Videogame" }], "badges": badges },
+        ] }));
+        // One batch per relationship and depth: persons, label links, labels, ownerships, badges and categories
+        assert_eq!(loads, 6);
+
+        let (data, loads) = run(&state, "{ category(id: 1) { badges { name owners { team { name } } } } }").await;
+        assert_eq!(data, json!({ "category": { "badges": [
+            { "name": "Platica de Felipe Ivan", "owners": [{ "team": { "name": "Startup-tec" } }, { "team": { "name": "Moneypool" } }] },
+        ] } }));
+        // The teams of both owners come in a single batch
+        assert_eq!(loads, 4);
+        let (data, _) = run(&state, "{ label(id: 2) { teams { name labels { name } } } }").await;
+        assert_eq!(data, json!({ "label": { "teams": [
+            { "name": "Startup-tec", "labels": [{ "name": "Edtec" }, { "name": "Videogame" }] },
+            { "name": "Moneypool", "labels": [{ "name": "Web3" }, { "name": "Edtec" }] },
+        ] } }));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn teams_are_filtered_and_paged() {
+        let (scratch, state) = scratch("graphql_pages").await;
+        let names = |data: &Value| data["teams"].as_array().unwrap().iter().map(|team| team["name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        for (arguments, expected) in [
+            ("", vec!["Moneypool", "Startup-tec"]),
+            ("(filter: { name: \"MONEY\" })", vec!["Moneypool"]),
+            ("(filter: { labelId: 3 })", vec!["Startup-tec"]),
+            ("(filter: { stage: 1, minScore: 900 })", vec!["Moneypool", "Startup-tec"]),
+            ("(filter: { minScore: 901 })", vec![]),
+            ("(first: 1)", vec!["Moneypool"]),
+            ("(first: 1, offset: 1)", vec!["Startup-tec"]),
+            ("(offset: 2)", vec![]),
+            // Out of range limits are clamped
+            ("(first: 1000, offset: -1)", vec!["Moneypool", "Startup-tec"]),
+        ] {
+            let (data, _) = run(&state, &format!("{{ teams{} {{ name }} }}", arguments)).await;
+            assert_eq!(names(&data), expected, "{}", arguments);
+        }
+        let (data, _) = run(&state, "{ persons(teamId: 2) { name team { name } } badges(minPoints: 500) { name } }").await;
+        assert_eq!(data, json!({ "persons": [{ "name": "Nacho", "team": { "name": "Moneypool" } }], "badges": [{ "name": "Platica de Felipe Ivan" }] }));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn mutations_rerank_and_clear_the_cache() {
+        let (scratch, state) = scratch("graphql_mutations").await;
+        let standings = "{ teams { name score rank } }";
+        let (data, _) = run(&state, r#"mutation { createBadge(input: { name: "Demo day", description: "Pitched", points: 100, category: 1 }) }"#).await;
+        assert_eq!(data, json!({ "createBadge": 3 }));
+
+        state.cache.store_teams(&[], state.cache.generation());
+        run(&state, r#"mutation { addBadge(input: { teamId: 1, badgeId: 3, acquisitionDate: "2023-03-01" }) }"#).await;
+        assert!(!state.cache.stats().list_cached);
+        let (data, _) = run(&state, standings).await;
+        assert_eq!(data, json!({ "teams": [
+            { "name": "Startup-tec", "score": 1000, "rank": 1 },
+            { "name": "Moneypool", "score": 900, "rank": 2 },
+        ] }));
+
+        state.cache.store_teams(&[], state.cache.generation());
+        run(&state, "mutation { removeBadge(input: { teamId: 1, badgeId: 3 }) }").await;
+        assert!(!state.cache.stats().list_cached);
+        let (data, _) = run(&state, standings).await;
+        assert_eq!(data, json!({ "teams": [
+            { "name": "Moneypool", "score": 900, "rank": 1 },
+            { "name": "Startup-tec", "score": 900, "rank": 1 },
+        ] }));
+        scratch.drop().await;
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Where the server is in its life. Readiness only passes while serving, so
+// load balancers stop sending requests before a shutdown drains them.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Starting,
+    Serving,
+    Stopping,
+}
+
+pub struct Health {
+    phase: AtomicU8,
+}
+
+impl Default for Health {
+    fn default() -> Health {
+        Health::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health { phase: AtomicU8::new(Phase::Starting as u8) }
+    }
+
+    pub fn phase(&self) -> Phase {
+        match self.phase.load(Ordering::Relaxed) {
+            0 => Phase::Starting,
+            1 => Phase::Serving,
+            _ => Phase::Stopping,
+        }
+    }
+
+    pub fn serving(&self) {
+        self.phase.store(Phase::Serving as u8, Ordering::Relaxed);
+    }
+
+    pub fn stopping(&self) {
+        self.phase.store(Phase::Stopping as u8, Ordering::Relaxed);
+    }
+}
+
+// Body of `/readyz`. `database` is "OK" or why the database can't be used.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    pub phase: Phase,
+    pub database: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Version {
+    pub version: &'static str,
+    pub commit: &'static str,
+    // RFC 3339, UTC
+    pub build_time: String,
+}
+
+// Set by build.rs
+pub fn version() -> Version {
+    let built = env!("BUILD_TIMESTAMP").parse().ok()
+        .and_then(|timestamp| actix_web::cookie::time::OffsetDateTime::from_unix_timestamp(timestamp).ok())
+        .and_then(|time| time.format(&actix_web::cookie::time::format_description::well_known::Rfc3339).ok());
+    Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("GIT_COMMIT"),
+        build_time: built.unwrap_or_else(|| String::from("unknown")),
+    }
+}
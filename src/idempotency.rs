@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_web::{web, Error, HttpResponse};
+use actix_web::body::{to_bytes, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::AppState;
+
+pub const HEADER: &str = "Idempotency-Key";
+pub const REPLAYED: &str = "Idempotent-Replayed";
+const MAX_KEY: usize = 255;
+const IN_PROGRESS: &str = "REQUEST WITH THIS IDEMPOTENCY KEY IS IN PROGRESS";
+const MISMATCH: &str = "IDEMPOTENCY KEY WAS USED WITH A DIFFERENT REQUEST";
+const INVALID_KEY: &str = "INVALID IDEMPOTENCY KEY";
+
+// Key sent with a GraphQL request, added to its data by the `/graphql` handler
+pub struct GraphqlKey(pub String);
+
+// The key in `value` if it can be used
+pub fn valid_key(value: &HeaderValue) -> Result<String, &'static str> {
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY => Ok(String::from(key)),
+        _ => Err(INVALID_KEY),
+    }
+}
+
+// Responses of requests sent with an `Idempotency-Key`, so a retried create
+// returns the original result instead of creating a duplicate. Entries are
+// kept for `ttl` after the request started.
+pub struct IdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    created: Instant,
+    // Method, path and body of the request that used the key
+    fingerprint: u64,
+    response: Option<StoredResponse>,
+}
+
+#[derive(Clone)]
+struct StoredResponse {
+    status: StatusCode,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: web::Bytes,
+}
+
+enum Begin {
+    New,
+    Replay(StoredResponse),
+    InProgress,
+    Mismatch,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration) -> IdempotencyStore {
+        IdempotencyStore { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn begin(&self, key: &str, fingerprint: u64) -> Begin {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.created.elapsed() < self.ttl);
+        match entries.get(key) {
+            Some(entry) if entry.fingerprint != fingerprint => Begin::Mismatch,
+            Some(entry) => match &entry.response {
+                Some(response) => Begin::Replay(response.clone()),
+                None => Begin::InProgress,
+            },
+            None => {
+                entries.insert(String::from(key), Entry { created: Instant::now(), fingerprint, response: None });
+                Begin::New
+            },
+        }
+    }
+
+    fn finish(&self, key: &str, response: StoredResponse) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(key) {
+            entry.response = Some(response);
+        }
+    }
+
+    // The request failed before answering, so it can be retried with the same key
+    fn abort(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    // Runs a GraphQL mutation at most once per key. `field` tells apart the
+    // mutations of one request and `input` is compared like the body of a REST
+    // request. Only results are stored, a failed mutation can be retried.
+    pub async fn once<T, F>(&self, key: &str, field: &str, input: &[u8], run: F) -> async_graphql::Result<T>
+    where T: Serialize + DeserializeOwned, F: Future<Output = async_graphql::Result<T>> {
+        let key = format!("graphql {} {}", field, key);
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        field.hash(&mut hasher);
+        input.hash(&mut hasher);
+        match self.begin(&key, hasher.finish()) {
+            Begin::New => {},
+            Begin::Replay(stored) => return serde_json::from_slice(&stored.body).map_err(|err| async_graphql::Error::new(err.to_string())),
+            Begin::InProgress => return Err(async_graphql::Error::new(IN_PROGRESS)),
+            Begin::Mismatch => return Err(async_graphql::Error::new(MISMATCH)),
+        }
+        match run.await {
+            Ok(value) => {
+                let body = serde_json::to_vec(&value).unwrap();
+                self.finish(&key, StoredResponse { status: StatusCode::OK, headers: vec![], body: body.into() });
+                Ok(value)
+            },
+            Err(err) => {
+                self.abort(&key);
+                Err(err)
+            },
+        }
+    }
+}
+
+fn fingerprint(req: &ServiceRequest, body: &[u8]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    req.method().as_str().hash(&mut hasher);
+    req.path().hash(&mut hasher);
+    req.query_string().hash(&mut hasher);
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn reply(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(message)
+}
+
+// Middleware for the create and add handlers, used with `wrap = "from_fn(idempotent)"`.
+// Requests without the header go through untouched. Server errors are not stored.
+pub async fn idempotent(mut req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse, Error> {
+    let key = match req.headers().get(HEADER) {
+        None => return Ok(next.call(req).await?.map_into_boxed_body()),
+        Some(key) => match valid_key(key) {
+            Ok(key) => key,
+            Err(err) => return Ok(req.into_response(reply(StatusCode::BAD_REQUEST, err))),
+        },
+    };
+    let store = req.app_data::<web::Data<AppState>>().unwrap().idempotency.clone();
+    let body = req.extract::<web::Bytes>().await?;
+    let fingerprint = fingerprint(&req, &body);
+    req.set_payload(actix_web::dev::Payload::from(body));
+
+    match store.begin(&key, fingerprint) {
+        Begin::New => {},
+        Begin::Replay(stored) => {
+            let mut response = HttpResponse::build(stored.status);
+            for header in stored.headers {
+                response.append_header(header);
+            }
+            response.append_header((REPLAYED, "true"));
+            return Ok(req.into_response(response.body(stored.body)));
+        },
+        Begin::InProgress => return Ok(req.into_response(reply(StatusCode::CONFLICT, IN_PROGRESS))),
+        Begin::Mismatch => return Ok(req.into_response(reply(StatusCode::UNPROCESSABLE_ENTITY, MISMATCH))),
+    }
+
+    let response = match next.call(req).await {
+        Ok(response) => response,
+        Err(err) => {
+            store.abort(&key);
+            return Err(err);
+        },
+    };
+    let (req, response) = response.into_parts();
+    let status = response.status();
+    let headers:Vec<(HeaderName, HeaderValue)> = response.headers().iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+    let body = match to_bytes(response.into_body()).await {
+        Ok(body) => body,
+        Err(_) => {
+            store.abort(&key);
+            return Ok(ServiceResponse::new(req, reply(StatusCode::INTERNAL_SERVER_ERROR, "ERROR READING RESPONSE")));
+        },
+    };
+    if status.is_server_error() {
+        store.abort(&key);
+    } else {
+        store.finish(&key, StoredResponse { status, headers: headers.clone(), body: body.clone() });
+    }
+
+    let mut response = HttpResponse::build(status);
+    for header in headers {
+        response.append_header(header);
+    }
+    Ok(ServiceResponse::new(req, response.body(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use actix_web::{test, web, App, HttpResponse};
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use futures::channel::oneshot;
+    use crate::*;
+    use super::{idempotent, Begin, GraphqlKey, IdempotencyStore, HEADER, REPLAYED};
+
+    fn state(ttl: Duration) -> AppState {
+        AppState { idempotency: Arc::new(IdempotencyStore::new(ttl)), ..AppState::new(Arc::new(repository::MemoryRepository::new())) }
+    }
+
+    // Counts the calls of every handler
+    #[derive(Default)]
+    struct Calls {
+        created: AtomicUsize,
+        failed: AtomicUsize,
+        // Held by `/slow` until the test lets it answer
+        release: Mutex<Option<oneshot::Receiver<()>>>,
+    }
+
+    async fn create(calls: web::Data<Calls>) -> HttpResponse {
+        let call = calls.created.fetch_add(1, Ordering::SeqCst) + 1;
+        HttpResponse::Created().insert_header(("Location", format!("/things/{}", call))).json(call)
+    }
+
+    async fn fail(calls: web::Data<Calls>) -> HttpResponse {
+        calls.failed.fetch_add(1, Ordering::SeqCst);
+        HttpResponse::InternalServerError().json("ERROR ADDING TO DATABASE")
+    }
+
+    async fn slow(calls: web::Data<Calls>) -> HttpResponse {
+        let release = calls.release.lock().unwrap().take().unwrap();
+        release.await.unwrap();
+        HttpResponse::Ok().json("done")
+    }
+
+    fn request(uri: &str, key: &str, body: &str) -> test::TestRequest {
+        test::TestRequest::post().uri(uri).insert_header((HEADER, key)).set_payload(body.to_string())
+    }
+
+    macro_rules! app {
+        ($state:expr, $calls:expr) => {
+            test::init_service(App::new()
+                .app_data(web::Data::new($state))
+                .app_data($calls.clone())
+                .service(web::scope("").wrap(from_fn(idempotent))
+                    .route("/things", web::post().to(create))
+                    .route("/fail", web::post().to(fail))
+                    .route("/slow", web::post().to(slow))))
+            .await
+        };
+    }
+
+    #[actix_web::test]
+    async fn retries_get_the_first_response() {
+        let calls = web::Data::new(Calls::default());
+        let app = app!(state(Duration::from_secs(60)), calls);
+
+        let first = test::call_service(&app, request("/things", "a", "{}").to_request()).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get(REPLAYED).is_none());
+        let retry = test::call_service(&app, request("/things", "a", "{}").to_request()).await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers().get(REPLAYED).unwrap(), "true");
+        assert_eq!(retry.headers().get("Location").unwrap(), "/things/1");
+        assert_eq!(test::read_body(retry).await, "1");
+        assert_eq!(calls.created.load(Ordering::SeqCst), 1);
+
+        // Another key, or none, runs the handler again
+        let other = test::call_service(&app, request("/things", "b", "{}").to_request()).await;
+        assert_eq!(test::read_body(other).await, "2");
+        let without = test::call_service(&app, test::TestRequest::post().uri("/things").to_request()).await;
+        assert_eq!(test::read_body(without).await, "3");
+    }
+
+    #[actix_web::test]
+    async fn a_key_belongs_to_one_request() {
+        let calls = web::Data::new(Calls::default());
+        let app = app!(state(Duration::from_secs(60)), calls);
+        test::call_service(&app, request("/things", "a", r#"{"name": "one"}"#).to_request()).await;
+
+        for request in [request("/things", "a", r#"{"name": "two"}"#), request("/fail", "a", r#"{"name": "one"}"#), request("/things?x=1", "a", r#"{"name": "one"}"#)] {
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        for key in ["", &"k".repeat(256)] {
+            let response = test::call_service(&app, request("/things", key, "{}").to_request()).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(calls.created.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn a_key_in_use_is_refused() {
+        let calls = web::Data::new(Calls::default());
+        let (release, held) = oneshot::channel();
+        *calls.release.lock().unwrap() = Some(held);
+        let app = app!(state(Duration::from_secs(60)), calls);
+
+        // The first request waits in the handler while the second one arrives
+        let first = test::call_service(&app, request("/slow", "a", "{}").to_request());
+        let second = async {
+            let response = test::call_service(&app, request("/slow", "a", "{}").to_request()).await;
+            release.send(()).unwrap();
+            response
+        };
+        let (first, second) = futures::join!(first, second);
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::CONFLICT);
+        let retry = test::call_service(&app, request("/slow", "a", "{}").to_request()).await;
+        assert_eq!(retry.headers().get(REPLAYED).unwrap(), "true");
+    }
+
+    #[actix_web::test]
+    async fn server_errors_are_not_stored() {
+        let calls = web::Data::new(Calls::default());
+        let app = app!(state(Duration::from_secs(60)), calls);
+        for _ in 0..2 {
+            let response = test::call_service(&app, request("/fail", "a", "{}").to_request()).await;
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(response.headers().get(REPLAYED).is_none());
+        }
+        assert_eq!(calls.failed.load(Ordering::SeqCst), 2);
+    }
+
+    #[actix_web::test]
+    async fn keys_expire() {
+        let store = IdempotencyStore::new(Duration::from_millis(20));
+        assert!(matches!(store.begin("a", 1), Begin::New));
+        assert!(matches!(store.begin("a", 1), Begin::InProgress));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(store.begin("a", 2), Begin::New));
+    }
+
+    #[actix_web::test]
+    async fn graphql_mutations_run_once_per_key() {
+        let state = state(Duration::from_secs(60));
+        let schema = graphql::schema(state.clone());
+        let mutation = |name: &str, key: &str| async_graphql::Request::new(format!(r#"mutation {{ createLabel(input: {{ name: "{}" }}) }}"#, name))
+            .data(GraphqlKey(String::from(key)));
+
+        let first = schema.execute(mutation("Fintech", "a")).await;
+        let retry = schema.execute(mutation("Fintech", "a")).await;
+        assert!(first.errors.is_empty());
+        assert_eq!(first.data, retry.data);
+        assert_eq!(state.repo.labels().await.len(), 1);
+
+        let other = schema.execute(mutation("Edtech", "a")).await;
+        assert_eq!(other.errors[0].message, "IDEMPOTENCY KEY WAS USED WITH A DIFFERENT REQUEST");
+        // Failures are not stored, so the key can be used again
+        let duplicate = schema.execute(mutation("Fintech", "b")).await;
+        assert!(!duplicate.errors.is_empty());
+        assert!(schema.execute(mutation("Edtech", "b")).await.errors.is_empty());
+        assert_eq!(state.repo.labels().await.len(), 2);
+    }
+}
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use utoipa::ToSchema;
+use sqlx::FromRow;
+use validator::Validate;
+use crate::{validation, CreateBadgeOwnership, CreatePerson, CreateTeam, RawID, update_ranking};
+
+// CSV files to import. Every file is optional, but rows can only reference
+// teams, labels and badges that already exist or that are created by `teams`.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ImportFiles {
+    pub teams: Option<String>,
+    pub persons: Option<String>,
+    pub labels: Option<String>,
+    pub badges: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PersonRow {
+    team: String,
+    name: String,
+    career: String,
+    graduation_date: String,
+    picture_url: Option<String>,
+    portafolio_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelRow {
+    team: String,
+    label: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct BadgeRow {
+    team: String,
+    badge: String,
+    acquisition_date: String,
+}
+
+#[derive(FromRow, Debug)]
+struct Ownership {
+    team_id: i64,
+    owned_id: i64,
+}
+
+#[derive(FromRow, Debug)]
+struct NamedID {
+    id: i64,
+    name: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RowError {
+    pub file: String,
+    pub row: u64,
+    pub error: String,
+}
+
+impl RowError {
+    fn new(file: &str, row: u64, error: String) -> RowError {
+        RowError { file: String::from(file), row, error }
+    }
+}
+
+#[derive(Serialize, Debug, Default, ToSchema)]
+pub struct ImportReport {
+    pub teams: usize,
+    pub persons: usize,
+    pub labels: usize,
+    pub badges: usize,
+}
+
+// A team is either already in the database or created by this import
+#[derive(Debug, Clone, PartialEq)]
+enum TeamRef {
+    Existing(i64),
+    New(String),
+}
+
+// Resolves a cell holding either an id or a name
+struct Lookup {
+    names: HashMap<String, i64>,
+    ids: Vec<i64>,
+}
+
+impl Lookup {
+    fn from(rows: Vec<NamedID>) -> Lookup {
+        Lookup {
+            ids: rows.iter().map(|row| row.id).collect(),
+            names: rows.into_iter().map(|row| (row.name, row.id)).collect(),
+        }
+    }
+    fn resolve(&self, value: &str) -> Option<i64> {
+        let value = value.trim();
+        if let Ok(id) = value.parse::<i64>() {
+            if self.ids.contains(&id) { return Some(id); }
+        }
+        self.names.get(value).copied()
+    }
+}
+
+fn parse_rows<T: DeserializeOwned>(file: &str, csv: &Option<String>, errors: &mut Vec<RowError>) -> Vec<(u64, T)> {
+    let csv = match csv {
+        Some(csv) => csv,
+        None => return vec![],
+    };
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv.as_bytes());
+    let mut rows = vec![];
+    for (index, row) in reader.deserialize::<T>().enumerate() {
+        // Header is row 1
+        let line = index as u64 + 2;
+        match row {
+            Ok(row) => rows.push((line, row)),
+            Err(err) => {
+                let line = err.position().map(|p| p.line()).unwrap_or(line);
+                errors.push(RowError::new(file, line, format!("{}", err)));
+            }
+        }
+    }
+    rows
+}
+
+fn parse_date(file: &str, row: u64, date: &str, errors: &mut Vec<RowError>) -> Option<actix_web::cookie::time::Date> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    match actix_web::cookie::time::Date::parse(date, &format) {
+        Ok(date) => Some(date),
+        Err(_) => {
+            errors.push(RowError::new(file, row, format!("INVALID DATE '{}', EXPECTED YYYY-MM-DD", date)));
+            None
+        }
+    }
+}
+
+// The (team, label) or (team, badge) pairs already in `table`
+async fn ownerships(table: &str, column: &str, pool: &sqlx::postgres::PgPool) -> Vec<(i64, i64)> {
+    sqlx::query_as::<sqlx::postgres::Postgres, Ownership>(&format!("SELECT team_id, {} AS owned_id FROM {}", column, table))
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|ownership| (ownership.team_id, ownership.owned_id))
+        .collect()
+}
+
+// Runs the rules of the create payloads on a row. Dates that don't parse are
+// already reported by `parse_date`.
+fn check<T: Validate>(file: &str, row: u64, value: &T, errors: &mut Vec<RowError>) {
+    let Err(failure) = validation::check(value) else { return };
+    for error in failure.errors.into_iter().filter(|error| error.code != "date") {
+        errors.push(RowError::new(file, row, format!("{} {}", error.field.to_uppercase(), error.message.to_uppercase())));
+    }
+}
+
+async fn lookup(table: &str, pool: &sqlx::postgres::PgPool) -> Lookup {
+    let rows = sqlx::query_as::<sqlx::postgres::Postgres, NamedID>(&format!("SELECT id, name FROM {}", table))
+        .fetch_all(pool)
+        .await
+        .unwrap();
+    Lookup::from(rows)
+}
+
+// Validates every row of every file and, if there are no errors, inserts
+// everything in a single transaction. Scores of the teams that got badges are
+// recomputed inside the transaction and the ranking once it is committed.
+pub async fn import(files: ImportFiles, pool: &sqlx::postgres::PgPool) -> Result<ImportReport, Vec<RowError>> {
+    let mut errors = vec![];
+    // Team rows have the columns of `CreateTeam`
+    let team_rows: Vec<(u64, CreateTeam)> = parse_rows("teams", &files.teams, &mut errors);
+    let person_rows: Vec<(u64, PersonRow)> = parse_rows("persons", &files.persons, &mut errors);
+    let label_rows: Vec<(u64, LabelRow)> = parse_rows("labels", &files.labels, &mut errors);
+    let badge_rows: Vec<(u64, BadgeRow)> = parse_rows("badges", &files.badges, &mut errors);
+
+    let teams = lookup("teams", pool).await;
+    let labels = lookup("labels", pool).await;
+    let badges = lookup("badges", pool).await;
+    let owned_labels = ownerships("label_ownerships", "label_id", pool).await;
+    let owned_badges = ownerships("badge_ownerships", "badge_id", pool).await;
+
+    // Validate teams
+    let mut new_teams = vec![];
+    for (row, team) in &team_rows {
+        let date = parse_date("teams", *row, &team.creation_date, &mut errors);
+        check("teams", *row, team, &mut errors);
+        if teams.names.contains_key(&team.name) {
+            errors.push(RowError::new("teams", *row, format!("TEAM '{}' ALREADY EXISTS", team.name)));
+        } else if !team.name.is_empty() && new_teams.iter().any(|(_, new, _): &(u64, &CreateTeam, _)| new.name == team.name) {
+            errors.push(RowError::new("teams", *row, format!("TEAM '{}' IS REPEATED IN FILE", team.name)));
+        }
+        if let Some(date) = date {
+            new_teams.push((*row, team, date));
+        }
+    }
+    let resolve_team = |file: &str, row: u64, value: &str, errors: &mut Vec<RowError>| -> Option<TeamRef> {
+        if let Some(id) = teams.resolve(value) { return Some(TeamRef::Existing(id)); }
+        if new_teams.iter().any(|(_, new, _)| new.name == value.trim()) {
+            return Some(TeamRef::New(String::from(value.trim())));
+        }
+        errors.push(RowError::new(file, row, format!("TEAM '{}' DOES NOT EXIST", value)));
+        None
+    };
+
+    // Validate persons
+    let mut persons = vec![];
+    for (row, person) in &person_rows {
+        let team = resolve_team("persons", *row, &person.team, &mut errors);
+        let date = parse_date("persons", *row, &person.graduation_date, &mut errors);
+        // The team is checked above, and its id is not known yet for new teams
+        let create = CreatePerson {
+            team_id: 0,
+            name: person.name.clone(),
+            career: person.career.clone(),
+            graduation_date: person.graduation_date.clone(),
+            picture_url: person.picture_url.clone(),
+            portafolio_url: person.portafolio_url.clone(),
+        };
+        check("persons", *row, &create, &mut errors);
+        if let (Some(team), Some(date)) = (team, date) {
+            persons.push((*row, team, person, date));
+        }
+    }
+
+    // Validate label ownerships. A team can't get a label it already has, in
+    // the database or in an earlier row, and the same goes for badges.
+    let mut label_ownerships = vec![];
+    for (row, label_row) in &label_rows {
+        let team = resolve_team("labels", *row, &label_row.team, &mut errors);
+        let label = labels.resolve(&label_row.label);
+        if label.is_none() {
+            errors.push(RowError::new("labels", *row, format!("LABEL '{}' DOES NOT EXIST", label_row.label)));
+        }
+        if let (Some(team), Some(label)) = (team, label) {
+            if matches!(team, TeamRef::Existing(id) if owned_labels.contains(&(id, label))) {
+                errors.push(RowError::new("labels", *row, format!("TEAM '{}' ALREADY HAS LABEL '{}'", label_row.team, label_row.label)));
+            } else if label_ownerships.iter().any(|(_, other, other_label)| *other == team && *other_label == label) {
+                errors.push(RowError::new("labels", *row, format!("LABEL '{}' IS REPEATED FOR TEAM '{}' IN FILE", label_row.label, label_row.team)));
+            } else {
+                label_ownerships.push((*row, team, label));
+            }
+        }
+    }
+
+    // Validate badge ownerships
+    let mut badge_ownerships = vec![];
+    for (row, badge_row) in &badge_rows {
+        let team = resolve_team("badges", *row, &badge_row.team, &mut errors);
+        let badge = badges.resolve(&badge_row.badge);
+        if badge.is_none() {
+            errors.push(RowError::new("badges", *row, format!("BADGE '{}' DOES NOT EXIST", badge_row.badge)));
+        }
+        let date = parse_date("badges", *row, &badge_row.acquisition_date, &mut errors);
+        let create = CreateBadgeOwnership { team_id: 0, badge_id: 0, acquisition_date: badge_row.acquisition_date.clone() };
+        check("badges", *row, &create, &mut errors);
+        if let (Some(team), Some(badge), Some(date)) = (team, badge, date) {
+            if matches!(team, TeamRef::Existing(id) if owned_badges.contains(&(id, badge))) {
+                errors.push(RowError::new("badges", *row, format!("TEAM '{}' ALREADY HAS BADGE '{}'", badge_row.team, badge_row.badge)));
+            } else if badge_ownerships.iter().any(|(_, other, other_badge, _)| *other == team && *other_badge == badge) {
+                errors.push(RowError::new("badges", *row, format!("BADGE '{}' IS REPEATED FOR TEAM '{}' IN FILE", badge_row.badge, badge_row.team)));
+            } else {
+                badge_ownerships.push((*row, team, badge, date));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by(|a, b| a.file.cmp(&b.file).then(a.row.cmp(&b.row)));
+        return Err(errors);
+    }
+
+    // Apply
+    let mut tx = pool.begin().await.map_err(|err| vec![RowError::new("", 0, format!("{}", err))])?;
+    let mut created: HashMap<String, i64> = HashMap::new();
+    for (row, team, date) in &new_teams {
+        let id = sqlx::query_as::<sqlx::postgres::Postgres, RawID>("INSERT INTO teams (score, stage, name, description, creation_date, location, logo_url, banner_url) VALUES (0, $1, $2, $3, $4, $5, $6, $7) RETURNING id")
+            .bind(team.stage)
+            .bind(&team.name)
+            .bind(&team.description)
+            .bind(date)
+            .bind(&team.location)
+            .bind(&team.logo_url)
+            .bind(&team.banner_url)
+            .fetch_one(&mut tx)
+            .await
+            .map_err(|err| vec![RowError::new("teams", *row, format!("ERROR ADDING TO DATABASE: {}", err))])?
+            .id;
+        created.insert(team.name.clone(), id);
+    }
+    let team_id = |team: &TeamRef| match team {
+        TeamRef::Existing(id) => *id,
+        TeamRef::New(name) => created[name],
+    };
+
+    for (row, team, person, date) in &persons {
+        sqlx::query("INSERT INTO persons (team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(team_id(team))
+            .bind(&person.name)
+            .bind(&person.career)
+            .bind(date)
+            .bind(&person.picture_url)
+            .bind(&person.portafolio_url)
+            .execute(&mut tx)
+            .await
+            .map_err(|err| vec![RowError::new("persons", *row, format!("ERROR ADDING TO DATABASE: {}", err))])?;
+    }
+
+    for (row, team, label) in &label_ownerships {
+        sqlx::query("INSERT INTO label_ownerships (team_id, label_id) VALUES ($1, $2)")
+            .bind(team_id(team))
+            .bind(label)
+            .execute(&mut tx)
+            .await
+            .map_err(|err| vec![RowError::new("labels", *row, format!("ERROR ADDING TO DATABASE: {}", err))])?;
+    }
+
+    // Scores follow the new ownerships through the database triggers
+    let mut scored: Vec<i64> = vec![];
+    for (row, team, badge, date) in &badge_ownerships {
+        let id = team_id(team);
+        sqlx::query("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) VALUES ($1, $2, $3)")
+            .bind(id)
+            .bind(badge)
+            .bind(date)
+            .execute(&mut tx)
+            .await
+            .map_err(|err| vec![RowError::new("badges", *row, format!("ERROR ADDING TO DATABASE: {}", err))])?;
+        if !scored.contains(&id) { scored.push(id); }
+    }
+    tx.commit().await.map_err(|err| vec![RowError::new("", 0, format!("{}", err))])?;
+
+    if !scored.is_empty() || !new_teams.is_empty() {
+        update_ranking(pool.clone()).await;
+    }
+    Ok(ImportReport {
+        teams: new_teams.len(),
+        persons: persons.len(),
+        labels: label_ownerships.len(),
+        badges: badge_ownerships.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    const TEAMS: &str = "name,description,location,stage,creation_date,logo_url,banner_url";
+
+    #[test]
+    fn unreadable_rows_are_reported_with_their_line() {
+        let mut errors = vec![];
+        let csv = Some(format!("{}\nRocket,A team,Monterrey,1,2023-02-26,,\nComet,A team,Monterrey,two,2023-02-26,,\n", TEAMS));
+        let rows:Vec<(u64, CreateTeam)> = parse_rows("teams", &csv, &mut errors);
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].0, &rows[0].1.name[..], rows[0].1.logo_url.is_none()), (2, "Rocket", true));
+        assert_eq!((&errors[0].file[..], errors[0].row), ("teams", 3));
+
+        let rows:Vec<(u64, CreateTeam)> = parse_rows("teams", &None, &mut errors);
+        assert!(rows.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn cells_are_ids_or_names() {
+        let lookup = Lookup::from(vec![NamedID { id: 4, name: String::from("Rocket") }, NamedID { id: 7, name: String::from("12") }]);
+        assert_eq!(lookup.resolve("4"), Some(4));
+        assert_eq!(lookup.resolve(" Rocket "), Some(4));
+        // A number that is not an id can still be a name
+        assert_eq!(lookup.resolve("12"), Some(7));
+        assert_eq!(lookup.resolve("rocket"), None);
+        assert_eq!(lookup.resolve("5"), None);
+    }
+
+    // A database of its own with the label Web3 and the badge Demo day, worth 250 points
+    async fn scratch(test: &str) -> Scratch {
+        let scratch = Scratch::new(test).await;
+        scratch.execute("INSERT INTO labels (name) VALUES ('Web3'); \
+            INSERT INTO badge_categories (name) VALUES ('Talk'); \
+            INSERT INTO badges (name, description, points, category) VALUES ('Demo day', 'test', 250, 1)").await;
+        scratch
+    }
+
+    fn files(teams: &[&str], persons: &[&str], labels: &[&str], badges: &[&str]) -> ImportFiles {
+        let csv = |header: &str, rows: &[&str]| (!rows.is_empty()).then(|| format!("{}\n{}\n", header, rows.join("\n")));
+        ImportFiles {
+            teams: csv(TEAMS, teams),
+            persons: csv("team,name,career,graduation_date,picture_url,portafolio_url", persons),
+            labels: csv("team,label", labels),
+            badges: csv("team,badge,acquisition_date", badges),
+        }
+    }
+
+    // Score and number of persons, labels and badges of the team named `name`
+    async fn team(scratch: &Scratch, name: &str) -> Option<(i64, i64, i64, i64)> {
+        sqlx::query_as("SELECT score, \
+            (SELECT COUNT(*) FROM persons WHERE team_id = teams.id), \
+            (SELECT COUNT(*) FROM label_ownerships WHERE team_id = teams.id), \
+            (SELECT COUNT(*) FROM badge_ownerships WHERE team_id = teams.id) \
+            FROM teams WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&scratch.pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn imports_teams_and_their_rows() {
+        let scratch = scratch("import_rows").await;
+        let files = files(
+            &["Rocket,A team,Monterrey,1,2023-02-26,,"],
+            &["Rocket,Ana,ITC,2020-06-01,,https://ana.dev"],
+            &["Rocket,Web3"],
+            &["Rocket,Demo day,2023-05-01"],
+        );
+        let report = import(files, &scratch.pool).await.unwrap();
+        assert_eq!((report.teams, report.persons, report.labels, report.badges), (1, 1, 1, 1));
+        assert_eq!(team(&scratch, "Rocket").await, Some((250, 1, 1, 1)));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn every_row_error_is_reported_and_nothing_is_imported() {
+        let scratch = scratch("import_errors").await;
+        import(files(&["Taken,A team,Monterrey,1,2023-02-26,,"], &[], &[], &[]), &scratch.pool).await.unwrap();
+
+        let files = files(
+            &[
+                "Rocket,A team,Monterrey,1,2023-02-26,,",
+                "Comet,A team,Monterrey,1,26/02/2023,,",
+                "Taken,A team,Monterrey,1,2023-02-26,,",
+                "Rocket,Again,Monterrey,1,2023-02-26,,",
+                "Nova,A team,Monterrey,9,2999-01-01,ftp://nova.dev,",
+                ",A team,Monterrey,1,2023-02-26,,",
+            ],
+            &["Nobody,Ana,ITC,2020-06-01,,", "Rocket,Bea,ITC,2999-01-01,ftp://bea.dev,"],
+            &["Rocket,Missing"],
+            &["Rocket,Missing,2023-05-01", "Rocket,Demo day,tomorrow", "Rocket,Demo day,2999-01-01"],
+        );
+        let errors = import(files, &scratch.pool).await.unwrap_err();
+        let errors:Vec<(&str, u64, String)> = errors.iter().map(|error| (&error.file[..], error.row, error.error.clone())).collect();
+        assert_eq!(errors, [
+            ("badges", 2, String::from("BADGE 'Missing' DOES NOT EXIST")),
+            ("badges", 3, String::from("INVALID DATE 'tomorrow', EXPECTED YYYY-MM-DD")),
+            ("badges", 4, String::from("ACQUISITION_DATE CAN'T BE IN THE FUTURE")),
+            ("labels", 2, String::from("LABEL 'Missing' DOES NOT EXIST")),
+            ("persons", 2, String::from("TEAM 'Nobody' DOES NOT EXIST")),
+            ("persons", 3, String::from("GRADUATION_DATE CAN'T BE IN THE FUTURE")),
+            ("persons", 3, String::from("PICTURE_URL MUST BE A VALID HTTP OR HTTPS URL")),
+            ("teams", 3, String::from("INVALID DATE '26/02/2023', EXPECTED YYYY-MM-DD")),
+            ("teams", 4, String::from("TEAM 'Taken' ALREADY EXISTS")),
+            ("teams", 5, String::from("TEAM 'Rocket' IS REPEATED IN FILE")),
+            ("teams", 6, String::from("CREATION_DATE CAN'T BE IN THE FUTURE")),
+            ("teams", 6, String::from("LOGO_URL MUST BE A VALID HTTP OR HTTPS URL")),
+            ("teams", 6, String::from("STAGE MUST BE BETWEEN 1 AND 5")),
+            ("teams", 7, String::from("NAME MUST HAVE BETWEEN 1 AND 255 CHARACTERS")),
+        ]);
+        assert_eq!(team(&scratch, "Rocket").await, None);
+        assert_eq!(team(&scratch, "Taken").await, Some((0, 0, 0, 0)));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn labels_and_badges_a_team_already_has_are_reported() {
+        let scratch = scratch("import_owned").await;
+        import(files(&["Taken,A team,Monterrey,1,2023-02-26,,"], &[], &["Taken,Web3"], &["Taken,Demo day,2023-05-01"]), &scratch.pool).await.unwrap();
+
+        let files = files(
+            &["Rocket,A team,Monterrey,1,2023-02-26,,"],
+            &[],
+            &["Taken,Web3", "Rocket,Web3", "Rocket,1"],
+            &["1,Demo day,2023-05-01", "Rocket,Demo day,2023-05-01", "Rocket,Demo day,2023-05-02"],
+        );
+        let errors = import(files, &scratch.pool).await.unwrap_err();
+        let errors:Vec<(&str, u64, String)> = errors.iter().map(|error| (&error.file[..], error.row, error.error.clone())).collect();
+        assert_eq!(errors, [
+            ("badges", 2, String::from("TEAM '1' ALREADY HAS BADGE 'Demo day'")),
+            ("badges", 4, String::from("BADGE 'Demo day' IS REPEATED FOR TEAM 'Rocket' IN FILE")),
+            ("labels", 2, String::from("TEAM 'Taken' ALREADY HAS LABEL 'Web3'")),
+            ("labels", 4, String::from("LABEL '1' IS REPEATED FOR TEAM 'Rocket' IN FILE")),
+        ]);
+        assert_eq!(team(&scratch, "Rocket").await, None);
+        assert_eq!(team(&scratch, "Taken").await, Some((250, 0, 1, 1)));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn a_failed_insert_rolls_the_import_back() {
+        let scratch = scratch("import_rollback").await;
+        // Valid rows, but the database refuses the second badge
+        scratch.execute("INSERT INTO badges (name, description, points, category) VALUES ('Pitch', 'test', 100, 1); \
+            ALTER TABLE badge_ownerships ADD CONSTRAINT only_demo_day CHECK (badge_id = 1)").await;
+        let files = files(
+            &["Rocket,A team,Monterrey,1,2023-02-26,,"],
+            &["Rocket,Ana,ITC,2020-06-01,,"],
+            &["Rocket,Web3"],
+            &["Rocket,Demo day,2023-05-01", "Rocket,Pitch,2023-05-02"],
+        );
+        let errors = import(files, &scratch.pool).await.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((&errors[0].file[..], errors[0].row), ("badges", 3));
+        assert!(errors[0].error.starts_with("ERROR ADDING TO DATABASE"), "{}", errors[0].error);
+        assert_eq!(team(&scratch, "Rocket").await, None);
+        // Nothing written before the failing row was kept
+        let labelled:(i64,) = sqlx::query_as("SELECT COUNT(*) FROM label_ownerships JOIN labels ON labels.id = label_id WHERE labels.name = $1")
+            .bind("Web3")
+            .fetch_one(&scratch.pool)
+            .await
+            .unwrap();
+        assert_eq!(labelled.0, 0);
+        scratch.drop().await;
+    }
+}
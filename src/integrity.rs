@@ -0,0 +1,224 @@
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use crate::{ranking, update_ranking, RankTeam};
+
+// Checks the data for problems the schema doesn't prevent, or that appear when
+// the foreign keys or the score triggers are missing: rows pointing at rows that
+// don't exist, scores that aren't the sum of the team's badges, ranks that don't
+// follow the scores and names that only differ in case or spacing.
+//
+// `repair` fixes what can be fixed without guessing: orphaned ownerships are
+// deleted and scores and ranks are recomputed. Badges without a category,
+// persons without a team and duplicates are left for someone to look at.
+
+// A row whose `column` points at a row that doesn't exist
+#[derive(FromRow, Serialize, Debug, ToSchema)]
+pub struct Orphan {
+    pub table: String,
+    pub id: i64,
+    pub column: String,
+    pub missing_id: i64,
+}
+
+#[derive(FromRow, Serialize, Debug, ToSchema)]
+pub struct ScoreDrift {
+    pub team_id: i64,
+    pub name: String,
+    pub score: i64,
+    // Sum of the points of the team's badges
+    pub expected: i64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RankDrift {
+    pub team_id: i64,
+    pub rank: Option<i32>,
+    pub expected: Option<i32>,
+}
+
+// Rows of `table` whose names are the same ignoring case and spacing. Persons
+// are only compared with the persons of the same team.
+#[derive(FromRow, Serialize, Debug, ToSchema)]
+pub struct Duplicate {
+    pub table: String,
+    pub name: String,
+    pub ids: Vec<i64>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct IntegrityReport {
+    pub consistent: bool,
+    pub orphans: Vec<Orphan>,
+    pub scores: Vec<ScoreDrift>,
+    pub ranks: Vec<RankDrift>,
+    pub duplicates: Vec<Duplicate>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Repair {
+    pub deleted_ownerships: u64,
+    pub scores: u64,
+    pub ranks: usize,
+}
+
+// (table, column, referenced table) of every reference between tables
+const REFERENCES: [(&str, &str, &str); 6] = [
+    ("label_ownerships", "team_id", "teams"),
+    ("label_ownerships", "label_id", "labels"),
+    ("badge_ownerships", "team_id", "teams"),
+    ("badge_ownerships", "badge_id", "badges"),
+    ("badges", "category", "badge_categories"),
+    ("persons", "team_id", "teams"),
+];
+
+// Tables with names to compare, and the column the comparison is limited to
+const NAMED: [(&str, Option<&str>); 5] = [
+    ("teams", None),
+    ("badges", None),
+    ("labels", None),
+    ("badge_categories", None),
+    ("persons", Some("team_id")),
+];
+
+async fn orphans(pool: &sqlx::postgres::PgPool) -> Result<Vec<Orphan>, sqlx::Error> {
+    let mut orphans = vec![];
+    for (table, column, referenced) in REFERENCES {
+        let query = format!("SELECT '{0}' AS \"table\", {0}.id, '{1}' AS \"column\", {0}.{1} AS missing_id FROM {0} WHERE NOT EXISTS (SELECT 1 FROM {2} WHERE {2}.id = {0}.{1}) ORDER BY {0}.id", table, column, referenced);
+        orphans.extend(sqlx::query_as::<sqlx::postgres::Postgres, Orphan>(&query).fetch_all(pool).await?);
+    }
+    Ok(orphans)
+}
+
+async fn scores(pool: &sqlx::postgres::PgPool) -> Result<Vec<ScoreDrift>, sqlx::Error> {
+    sqlx::query_as::<sqlx::postgres::Postgres, ScoreDrift>("SELECT teams.id AS team_id, teams.name, teams.score, COALESCE(SUM(badges.points), 0)::BIGINT AS expected FROM teams LEFT JOIN badge_ownerships ON badge_ownerships.team_id = teams.id LEFT JOIN badges ON badges.id = badge_ownerships.badge_id GROUP BY teams.id HAVING teams.score <> COALESCE(SUM(badges.points), 0) ORDER BY teams.id")
+        .fetch_all(pool)
+        .await
+}
+
+// Ranks are checked against the stored scores, so a wrong score doesn't show up twice
+async fn ranks(pool: &sqlx::postgres::PgPool) -> Result<Vec<RankDrift>, sqlx::Error> {
+    let teams = sqlx::query_as::<sqlx::postgres::Postgres, RankTeam>("SELECT id, score, name, rank FROM teams")
+        .fetch_all(pool)
+        .await?;
+    let mut ranks:Vec<RankDrift> = ranking::rank_changes(&teams).into_iter().map(|(team_id, expected)| RankDrift {
+        team_id,
+        rank: teams.iter().find(|team| team.id == team_id).and_then(|team| team.rank),
+        expected,
+    }).collect();
+    ranks.sort_by_key(|drift| drift.team_id);
+    Ok(ranks)
+}
+
+async fn duplicates(pool: &sqlx::postgres::PgPool) -> Result<Vec<Duplicate>, sqlx::Error> {
+    let mut duplicates = vec![];
+    for (table, scope) in NAMED {
+        let normalized = "LOWER(REGEXP_REPLACE(TRIM(name), '\\s+', ' ', 'g'))";
+        let group = match scope {
+            Some(scope) => format!("{}, {}", scope, normalized),
+            None => String::from(normalized),
+        };
+        let query = format!("SELECT '{0}' AS \"table\", MIN(name) AS name, ARRAY_AGG(id ORDER BY id) AS ids FROM {0} GROUP BY {1} HAVING COUNT(*) > 1 ORDER BY MIN(id)", table, group);
+        duplicates.extend(sqlx::query_as::<sqlx::postgres::Postgres, Duplicate>(&query).fetch_all(pool).await?);
+    }
+    Ok(duplicates)
+}
+
+pub async fn check(pool: &sqlx::postgres::PgPool) -> Result<IntegrityReport, sqlx::Error> {
+    let orphans = orphans(pool).await?;
+    let scores = scores(pool).await?;
+    let ranks = ranks(pool).await?;
+    let duplicates = duplicates(pool).await?;
+    let consistent = orphans.is_empty() && scores.is_empty() && ranks.is_empty() && duplicates.is_empty();
+    Ok(IntegrityReport { consistent, orphans, scores, ranks, duplicates })
+}
+
+// Sets every score that isn't the sum of the team's badges, also used by `backup::restore`
+pub(crate) const REPAIR_SCORES: &str = "UPDATE teams SET score = expected.points FROM (SELECT teams.id, COALESCE(SUM(badges.points), 0)::BIGINT AS points FROM teams LEFT JOIN badge_ownerships ON badge_ownerships.team_id = teams.id LEFT JOIN badges ON badges.id = badge_ownerships.badge_id GROUP BY teams.id) AS expected WHERE teams.id = expected.id AND teams.score <> expected.points";
+
+// Deletes orphaned ownerships and recomputes every score and rank
+pub async fn repair(pool: &sqlx::postgres::PgPool) -> Result<Repair, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut deleted_ownerships = 0;
+    for (table, column, referenced) in REFERENCES.iter().filter(|(table, _, _)| table.ends_with("_ownerships")) {
+        let query = format!("DELETE FROM {0} WHERE NOT EXISTS (SELECT 1 FROM {2} WHERE {2}.id = {0}.{1})", table, column, referenced);
+        deleted_ownerships += sqlx::query(&query).execute(&mut tx).await?.rows_affected();
+    }
+    let scores = sqlx::query(REPAIR_SCORES)
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+
+    let ranks = ranks(pool).await?.len();
+    update_ranking(pool.clone()).await;
+    Ok(Repair { deleted_ownerships, scores, ranks })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scratch::Scratch;
+    use super::{check, repair, IntegrityReport};
+
+    // Every problem of a report as text, to compare whole reports
+    fn problems(report: &IntegrityReport) -> Vec<String> {
+        let orphans = report.orphans.iter().map(|orphan| format!("{} {} {} {}", orphan.table, orphan.id, orphan.column, orphan.missing_id));
+        let scores = report.scores.iter().map(|drift| format!("score {} {} {}", drift.team_id, drift.score, drift.expected));
+        let ranks = report.ranks.iter().map(|drift| format!("rank {} {:?} {:?}", drift.team_id, drift.rank, drift.expected));
+        let duplicates = report.duplicates.iter().map(|duplicate| format!("duplicate {} {:?}", duplicate.table, duplicate.ids));
+        orphans.chain(scores).chain(ranks).chain(duplicates).collect()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn problems_are_reported_and_repaired() {
+        let scratch = Scratch::new("integrity").await;
+        scratch.seed().await;
+        let report = check(&scratch.pool).await.unwrap();
+        assert!(report.consistent, "{:?}", problems(&report));
+
+        // Without the foreign keys, rows can point at rows that don't exist
+        scratch.execute("\
+            ALTER TABLE label_ownerships DROP CONSTRAINT label_ownerships_label_id_foreign; \
+            ALTER TABLE badge_ownerships DROP CONSTRAINT badge_ownerships_team_id_foreign; \
+            ALTER TABLE badges DROP CONSTRAINT badges_category_foreign; \
+            ALTER TABLE persons DROP CONSTRAINT persons_team_foreign; \
+            INSERT INTO label_ownerships (id, team_id, label_id) VALUES (50, 1, 98); \
+            INSERT INTO badge_ownerships (id, team_id, badge_id, acquisition_date) VALUES (60, 99, 1, '2023-01-23'); \
+            INSERT INTO badges (id, name, description, points, category) VALUES (70, 'Lost', 'test', 10, 97); \
+            INSERT INTO persons (id, team_id, name, career, graduation_date) VALUES (80, 96, 'Ana', 'ITC', '2023-01-01'); \
+            UPDATE teams SET score = 100 WHERE id = 1; \
+            UPDATE teams SET rank = 3 WHERE id = 2; \
+            INSERT INTO labels (id, name) VALUES (90, '  web3 '); \
+            INSERT INTO persons (id, team_id, name, career, graduation_date) VALUES (81, 2, 'NACHO', 'BGB', '2023-01-01'); \
+            INSERT INTO persons (id, team_id, name, career, graduation_date) VALUES (82, 1, 'Nacho', 'BGB', '2023-01-01');").await;
+        let report = check(&scratch.pool).await.unwrap();
+        assert!(!report.consistent);
+        assert_eq!(problems(&report), [
+            "label_ownerships 50 label_id 98",
+            "badge_ownerships 60 team_id 99",
+            "badges 70 category 97",
+            "persons 80 team_id 96",
+            "score 1 100 900",
+            // Ranks follow the stored scores, where Startup-tec has 100
+            "rank 1 Some(1) Some(2)",
+            "rank 2 Some(3) Some(1)",
+            "duplicate labels [1, 90]",
+            // Persons only repeat within a team
+            "duplicate persons [2, 81]",
+        ]);
+
+        // Once the score is fixed only Moneypool's rank is wrong
+        let repaired = repair(&scratch.pool).await.unwrap();
+        assert_eq!((repaired.deleted_ownerships, repaired.scores, repaired.ranks), (2, 1, 1));
+        // Badges without a category, persons without a team and duplicates are left alone
+        let report = check(&scratch.pool).await.unwrap();
+        assert_eq!(problems(&report), [
+            "badges 70 category 97",
+            "persons 80 team_id 96",
+            "duplicate labels [1, 90]",
+            "duplicate persons [2, 81]",
+        ]);
+        scratch.drop().await;
+    }
+}
@@ -1,13 +1,63 @@
 use sqlx::FromRow;
 use serde::{Serialize, Deserialize};
+use utoipa::{ToSchema, IntoParams};
+use async_graphql::{InputObject, SimpleObject};
+use validator::Validate;
+use patch::{Patch, Update};
+use validation::{MAX_NAME, MAX_URL, MAX_DESCRIPTION};
 use futures::join;
 
+pub mod cache;
+pub mod config;
+pub mod health;
+pub mod import;
+pub mod export;
+pub mod backup;
+pub mod search;
+pub mod operations;
+pub mod graphql;
+pub mod view;
+pub mod validation;
+pub mod patch;
+pub mod team_patch;
+pub mod idempotency;
+pub mod metrics;
+pub mod ranking;
+pub mod integrity;
+pub mod repository;
+pub mod routes;
+#[cfg(test)]
+mod scratch;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub repo: std::sync::Arc<dyn repository::Repository>,
+    pub cache: std::sync::Arc<cache::TeamCache>,
+    pub idempotency: std::sync::Arc<idempotency::IdempotencyStore>,
+    pub health: std::sync::Arc<health::Health>,
+    pub metrics: std::sync::Arc<metrics::Metrics>,
+}
+
+impl AppState {
+    // State without the team cache, for the admin commands and the tests. The
+    // server builds its own from the configuration.
+    pub fn new(repo: std::sync::Arc<dyn repository::Repository>) -> AppState {
+        AppState {
+            repo,
+            cache: std::sync::Arc::new(cache::TeamCache::new(false)),
+            idempotency: std::sync::Arc::new(idempotency::IdempotencyStore::new(std::time::Duration::from_secs(60))),
+            health: std::sync::Arc::new(health::Health::new()),
+            metrics: std::sync::Arc::new(metrics::Metrics::new(10)),
+        }
+    }
+}
+
 #[derive(FromRow, Debug, Serialize)]
 pub struct RawID {
     pub id: i64,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct Team {
     pub id: i64,
     pub rank: Option<i32>,
@@ -17,6 +67,7 @@ pub struct Team {
     pub logo_url: Option<String>,
     pub banner_url: Option<String>,
     pub description: String,
+    #[schema(format = Date)]
     pub creation_date: String,
     pub location: String,
     pub labels: Vec<Label>,
@@ -24,7 +75,7 @@ pub struct Team {
     pub badges: Vec<OwnedBadge>,
 }
 
-#[derive(FromRow, Debug)]
+#[derive(FromRow, Debug, Clone)]
 pub struct RawTeam {
     pub id: i64,
     pub rank: Option<i32>,
@@ -56,14 +107,22 @@ impl RawTeam {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreateTeam {
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub name: String,
+    #[validate(length(min = 1, max = "MAX_DESCRIPTION"))]
     pub description: String,
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub location: String,
+    #[validate(custom(function = "validation::stage"))]
     pub stage: i32,
+    #[schema(format = Date)]
+    #[validate(custom(function = "validation::past_date"))]
     pub creation_date: String,
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::http_url"))]
     pub banner_url: Option<String>,
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::http_url"))]
     pub logo_url: Option<String>,
 }
 
@@ -91,7 +150,11 @@ impl Team {
             badges: vec![],
         }
     }
-    pub async fn from(raw_team: RawTeam, pool: &sqlx::postgres::PgPool) -> Team {
+    pub async fn from(raw_team: RawTeam, repo: &dyn repository::Repository) -> Team {
+        Team::load(raw_team, repo, &view::TeamView::full()).await
+    }
+    // Only runs the queries of the relations in `view`, the others stay empty
+    pub async fn load(raw_team: RawTeam, repo: &dyn repository::Repository, view: &view::TeamView) -> Team {
         let mut team = Team::new();
         team.id = raw_team.id;
         team.rank = raw_team.rank;
@@ -108,83 +171,49 @@ impl Team {
         team.creation_date = raw_team.creation_date.to_string();
         team.location = String::from(&raw_team.location);
 
-        let labels = team.load_labels(pool.clone());
-        let badges = team.load_badges(pool.clone());
-        let persons = team.load_persons(pool.clone());
+        let labels = async { if view.labels { repo.team_labels(team.id).await } else { vec![] } };
+        let badges = async { if view.badges { repo.team_badges(team.id).await } else { vec![] } };
+        let persons = async { if view.persons { repo.team_persons(team.id).await } else { vec![] } };
         let (labels, badges, persons) = join!(labels, badges, persons);
         team.labels = labels;
         team.badges = badges;
         team.persons = persons;
         team
     }
-    pub async fn load_labels(&self, pool: sqlx::postgres::PgPool) -> Vec<Label> {
-        let label_ownerships = sqlx::query_as::<sqlx::postgres::Postgres, LabelOwnership>( "SELECT * FROM label_ownerships WHERE team_id = $1")
-            .bind(self.id)
-            .fetch_all(&pool)
-            .await
-            .unwrap();
-        if label_ownerships.is_empty()  { return vec![] };
-        let label_id_values:Vec<i64> = label_ownerships.iter().map(|l| l.label_id).collect();
-        let labels = sqlx::query_as::<sqlx::postgres::Postgres, Label>("SELECT * FROM labels WHERE id = ANY($1)")
-            .bind(&label_id_values[..])
-            .fetch_all(&pool)
-            .await
-            .unwrap();
-        labels
-    }
-    pub async fn load_badges(&self, pool: sqlx::postgres::PgPool) -> Vec<OwnedBadge> {
-        let badge_ownerships = sqlx::query_as::<sqlx::postgres::Postgres, BadgeOwnership>("SELECT * FROM badge_ownerships WHERE team_id = $1")
-            .bind(self.id)
-            .fetch_all(&pool)
-            .await
-            .unwrap();
-        if badge_ownerships.is_empty()  { return vec![] };
-        let mut badges:Vec<OwnedBadge> = vec![];
-        for badge_ownership in badge_ownerships {
-            let raw_badge = sqlx::query_as::<sqlx::postgres::Postgres, RawBadge>("SELECT * FROM badges WHERE id = $1")
-                .bind(badge_ownership.badge_id)
-                .fetch_one(&pool)
-                .await
-                .unwrap();
-            let category = sqlx::query_as::<sqlx::postgres::Postgres, Category>("SELECT * FROM badge_categories WHERE id = $1")
-                .bind(raw_badge.category)
-                .fetch_one(&pool)
-                .await
-                .unwrap();
-            let owned_badge = OwnedBadge::from(raw_badge, category, &badge_ownership);
-            badges.push(owned_badge);
-        }
-        badges
-    }
-    pub async fn load_persons(& self, pool: sqlx::postgres::PgPool) -> Vec<Person> {
-        let raw_persons = sqlx::query_as::<sqlx::postgres::Postgres, RawPerson>("SELECT * FROM persons WHERE team_id = $1")
-            .bind(self.id)
-            .fetch_all(&pool)
-            .await
-            .unwrap();
-        let persons:Vec<Person> = raw_persons.iter().map(Person::from).collect();
-        persons
+    // The order of `/teams`: highest score first, ties broken by name
+    pub fn sort(teams: &mut [Team]) {
+        teams.sort_by(|a, b| {
+            if a.score == b.score { a.name.cmp(&b.name) }
+            else { b.score.cmp(&a.score) }
+        });
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct Person {
     pub id: i64,
     pub team_id: i64,
     pub name: String,
     pub career: String,
+    #[schema(format = Date)]
     pub graduation_date: String,
     pub picture_url: Option<String>,
     pub portafolio_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreatePerson {
     pub team_id: i64,
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub name: String,
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub career: String,
+    #[schema(format = Date)]
+    #[validate(custom(function = "validation::past_date"))]
     pub graduation_date: String,
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::http_url"))]
     pub picture_url: Option<String>,
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::http_url"))]
     pub portafolio_url: Option<String>,
 }
 
@@ -223,7 +252,7 @@ impl Person {
     }
 }
 
-#[derive(FromRow, Debug)]
+#[derive(FromRow, Debug, Clone)]
 pub struct RawPerson {
     pub id: i64,
     pub team_id: i64,
@@ -234,26 +263,30 @@ pub struct RawPerson {
     pub portafolio_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreateCategory {
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub name: String,
 }
 
-#[derive(FromRow, Serialize, Debug)]
+#[derive(FromRow, Serialize, Debug, Clone, ToSchema)]
 pub struct Category {
     pub id: i64,
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreateBadge {
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub name: String,
+    #[validate(length(min = 1, max = "MAX_DESCRIPTION"))]
     pub description: String,
+    #[validate(range(min = 0))]
     pub points: i64,
     pub category: i64,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct Badge {
     pub id: i64,
     pub name: String,
@@ -274,7 +307,7 @@ impl Badge {
     }
 }
 
-#[derive(FromRow, Debug, Serialize)]
+#[derive(FromRow, Debug, Clone, Serialize, ToSchema)]
 pub struct RawBadge {
     pub id: i64,
     pub name: String,
@@ -283,14 +316,47 @@ pub struct RawBadge {
     pub category: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreateBadgeOwnership {
     pub team_id: i64,
     pub badge_id: i64,
+    #[schema(format = Date)]
+    #[validate(custom(function = "validation::past_date"))]
     pub acquisition_date: String,
 }
 
-#[derive(FromRow, Debug)]
+// Awards one badge to many teams at once
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
+pub struct BadgeAward {
+    pub badge_id: i64,
+    #[schema(format = Date)]
+    #[validate(custom(function = "validation::past_date"))]
+    pub acquisition_date: String,
+    #[validate(custom(function = "validation::batch"))]
+    pub team_ids: Vec<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
+pub struct BadgeRevoke {
+    pub badge_id: i64,
+    #[validate(custom(function = "validation::batch"))]
+    pub team_ids: Vec<i64>,
+}
+
+// `duplicates` already had the badge and were left as they were
+#[derive(Debug, Serialize, Deserialize, ToSchema, SimpleObject)]
+pub struct AwardReport {
+    pub awarded: Vec<i64>,
+    pub duplicates: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema, SimpleObject)]
+pub struct RevokeReport {
+    pub revoked: Vec<i64>,
+    pub not_owned: Vec<i64>,
+}
+
+#[derive(FromRow, Debug, Clone)]
 pub struct BadgeOwnership {
     pub id: i64,
     pub team_id: i64,
@@ -298,15 +364,16 @@ pub struct BadgeOwnership {
     pub acquisition_date: actix_web::cookie::time::Date,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject)]
 pub struct DeleteOwnedBadge {
     pub team_id: i64,
     pub badge_id: i64,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, ToSchema)]
 pub struct OwnedBadge {
     id: i64,
+    #[schema(format = Date)]
     acquisition_date: String,
     badge: Badge,
 }
@@ -322,154 +389,164 @@ impl OwnedBadge {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject)]
 pub struct CreateLabelOwnership {
     pub team_id: i64,
     pub label_id: i64,
 }
 
-#[derive(FromRow, Debug)]
+#[derive(FromRow, Debug, Clone)]
 pub struct LabelOwnership {
     pub id: i64,
     pub team_id: i64,
     pub label_id: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject)]
 pub struct DeleteOwnedLabel {
     pub team_id: i64,
     pub label_id: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, InputObject, Validate)]
 pub struct CreateLabel {
+    #[validate(length(min = 1, max = "MAX_NAME"))]
     pub name: String,
 }
 
-#[derive(FromRow, Serialize, Debug)]
+#[derive(FromRow, Serialize, Debug, Clone, ToSchema)]
 pub struct Label {
     pub id: i64,
     pub name: String,
 }
 
-#[derive(Deserialize, Debug)]
+// Edit payloads use JSON Merge Patch semantics, see `patch::Patch`
+#[derive(Deserialize, Debug, Default, ToSchema, Validate)]
+#[serde(default)]
 pub struct EditTeam {
-    pub name: Option<String>,
-    pub description: Option<String>,
-    pub stage: Option<i32>,
-    pub creation_date: Option<String>,
-    pub logo_url: Option<String>,
-    pub banner_url: Option<String>,
-    pub location: Option<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub name: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_DESCRIPTION"), custom(function = "validation::required"))]
+    pub description: Patch<String>,
+    #[schema(value_type = Option<i32>)]
+    #[validate(custom(function = "validation::patch_stage"), custom(function = "validation::required"))]
+    pub stage: Patch<i32>,
+    #[schema(value_type = Option<String>, format = Date)]
+    #[validate(custom(function = "validation::patch_past_date"), custom(function = "validation::required"))]
+    pub creation_date: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::patch_url"))]
+    pub logo_url: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::patch_url"))]
+    pub banner_url: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub location: Patch<String>,
 }
 
 impl EditTeam {
-    pub fn query(self) -> String {
-        let mut query = String::from("UPDATE teams SET ");
-        if let Some(name) = &self.name { query.push_str(&format!("name = '{}', ", name)); }
-        if let Some(description) = &self.description { query.push_str(&format!("description = '{}', ", description)); }
-        if let Some(stage) = &self.stage { query.push_str(&format!("stage = {}, ", stage)); }
-        if let Some(creation_date) = &self.creation_date { query.push_str(&format!("creation_date = '{}', ", creation_date)); }
-        if let Some(location) = &self.location { query.push_str(&format!("location = '{}', ", location)); }
-        let null = String::from("null");
-        match self.logo_url {
-            Some(logo_url) if logo_url == null =>  query.push_str("logo_url = null, "),
-            Some(logo_url) =>  query.push_str(&format!("logo_url = '{}', ", logo_url)),
-            None => {},
-        }
-        match self.banner_url {
-            Some(banner_url) if banner_url == null =>  query.push_str("banner_url = null, "),
-            Some(banner_url) =>  query.push_str(&format!("banner_url = '{}', ", banner_url)),
-            None => {},
-        }
-        query.truncate(query.len() - 2);
-        query.push_str(" WHERE id = $1");
-        query
+    pub fn update(&self) -> Result<Update, String> {
+        Ok(Update::new("teams")
+            .text("name", &self.name)
+            .text("description", &self.description)
+            .integer("stage", &self.stage)
+            .date("creation_date", &self.creation_date)?
+            .text("location", &self.location)
+            .text("logo_url", &self.logo_url)
+            .text("banner_url", &self.banner_url))
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, ToSchema, Validate)]
+#[serde(default)]
 pub struct EditLabel {
-    pub name: Option<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub name: Patch<String>,
 }
 
 impl EditLabel {
-    pub fn query(self) -> String {
-        let mut query = String::from("UPDATE labels SET ");
-        if let Some(name) = &self.name { query.push_str(&format!("name = '{}', ", name)); }
-        query.truncate(query.len() - 2);
-        query.push_str(" WHERE id = $1");
-        query
+    pub fn update(&self) -> Update {
+        Update::new("labels").text("name", &self.name)
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, ToSchema, Validate)]
+#[serde(default)]
 pub struct EditBadge {
-    pub name: Option<String>,
-    pub description: Option<String>,
-    pub points: Option<i64>,
-    pub category: Option<i64>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub name: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_DESCRIPTION"), custom(function = "validation::required"))]
+    pub description: Patch<String>,
+    #[schema(value_type = Option<i64>)]
+    #[validate(range(min = 0), custom(function = "validation::required"))]
+    pub points: Patch<i64>,
+    #[schema(value_type = Option<i64>)]
+    #[validate(custom(function = "validation::required"))]
+    pub category: Patch<i64>,
 }
 
 impl EditBadge {
-    pub fn query(self) -> String {
-        let mut query = String::from("UPDATE badges SET ");
-        if let Some(name) = &self.name { query.push_str(&format!("name = '{}', ", name)); }
-        if let Some(description) = &self.description { query.push_str(&format!("description = '{}', ", description)); }
-        if let Some(points) = &self.points { query.push_str(&format!("points = {}, ", points)); }
-        if let Some(category) = &self.category { query.push_str(&format!("category = {}, ", category)); }
-        query.truncate(query.len() - 2);
-        query.push_str(" WHERE id = $1");
-        query
+    pub fn update(&self) -> Update {
+        Update::new("badges")
+            .text("name", &self.name)
+            .text("description", &self.description)
+            .big_int("points", &self.points)
+            .big_int("category", &self.category)
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, ToSchema, Validate)]
+#[serde(default)]
 pub struct EditCategory {
-    pub name: Option<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub name: Patch<String>,
 }
 
 impl EditCategory {
-    pub fn query(self) -> String {
-        let mut query = String::from("UPDATE badge_categories SET ");
-        if let Some(name) = &self.name { query.push_str(&format!("name = '{}', ", name)); }
-        query.truncate(query.len() - 2);
-        query.push_str(" WHERE id = $1");
-        query
+    pub fn update(&self) -> Update {
+        Update::new("badge_categories").text("name", &self.name)
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default, ToSchema, Validate)]
+#[serde(default)]
 pub struct EditPerson {
-    pub name: Option<String>,
-    pub team_id: Option<i64>,
-    pub career: Option<String>,
-    pub graduation_date: Option<String>,
-    pub picture_url: Option<String>,
-    pub portafolio_url: Option<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub name: Patch<String>,
+    #[schema(value_type = Option<i64>)]
+    #[validate(custom(function = "validation::required"))]
+    pub team_id: Patch<i64>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(min = 1, max = "MAX_NAME"), custom(function = "validation::required"))]
+    pub career: Patch<String>,
+    #[schema(value_type = Option<String>, format = Date)]
+    #[validate(custom(function = "validation::patch_past_date"), custom(function = "validation::required"))]
+    pub graduation_date: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::patch_url"))]
+    pub picture_url: Patch<String>,
+    #[schema(value_type = Option<String>)]
+    #[validate(length(max = "MAX_URL"), custom(function = "validation::patch_url"))]
+    pub portafolio_url: Patch<String>,
 }
 
 impl EditPerson {
-    pub fn query(self) -> String {
-        let mut query = String::from("UPDATE persons SET ");
-        if let Some(name) = &self.name { query.push_str(&format!("name = '{}', ", name)); }
-        if let Some(team_id) = &self.team_id { query.push_str(&format!("team_id = {}, ", team_id)); }
-        if let Some(career) = &self.career { query.push_str(&format!("career = '{}', ", career)); }
-        if let Some(graduation_date) = &self.graduation_date { query.push_str(&format!("graduation_date = '{}', ", graduation_date)); }
-        let null = String::from("null");
-        match self.picture_url {
-            Some(picture_url) if picture_url == null =>  query.push_str("picture_url = null, "),
-            Some(picture_url) =>  query.push_str(&format!("picture_url = '{}', ", picture_url)),
-            None => {},
-        }
-        match self.portafolio_url {
-            Some(portafolio_url) if portafolio_url == null =>  query.push_str("portafolio_url = null, "),
-            Some(portafolio_url) =>  query.push_str(&format!("portafolio_url = '{}', ", portafolio_url)),
-            None => {},
-        }
-        query.truncate(query.len() - 2);
-        query.push_str(" WHERE id = $1");
-        query
+    pub fn update(&self) -> Result<Update, String> {
+        Ok(Update::new("persons")
+            .text("name", &self.name)
+            .big_int("team_id", &self.team_id)
+            .text("career", &self.career)
+            .date("graduation_date", &self.graduation_date)?
+            .text("picture_url", &self.picture_url)
+            .text("portafolio_url", &self.portafolio_url))
     }
 }
 
@@ -478,6 +555,11 @@ pub struct BadgeID {
     pub badge_id: i64,
 }
 
+#[derive(FromRow, Debug, Serialize)]
+pub struct PersonTeam {
+    pub team_id: i64,
+}
+
 #[derive(FromRow, Debug, Serialize)]
 pub struct BadgePoints {
     pub points: i64,
@@ -504,7 +586,21 @@ pub async fn update_score(id: i64, pool: sqlx::postgres::PgPool) {
         .unwrap();
 }
 
-#[derive(FromRow, Debug, Serialize)]
+// Recomputes the score of every team and then the ranking
+pub async fn update_scores(pool: sqlx::postgres::PgPool) {
+    let ids = sqlx::query_as::<sqlx::postgres::Postgres, RawID>("SELECT id FROM teams")
+        .fetch_all(&pool.clone())
+        .await
+        .unwrap();
+    let mut updates = vec![];
+    for id in ids {
+        updates.push(update_score(id.id, pool.clone()));
+    }
+    futures::future::join_all(updates).await;
+    update_ranking(pool.clone()).await;
+}
+
+#[derive(FromRow, Debug, Clone, Serialize)]
 pub struct RankTeam {
     pub id: i64,
     pub score: i64,
@@ -512,49 +608,89 @@ pub struct RankTeam {
     pub rank: Option<i32>,
 }
 
-impl RankTeam {
-    pub async fn rank_db(&self, pool: sqlx::postgres::PgPool) {
-        sqlx::query("UPDATE teams SET rank = $1 WHERE id = $2")
-            .bind(self.rank)
-            .bind(self.id)
-            .execute(&pool.clone()).await.unwrap();
-    }
-}
-        
-
+// Recomputes the ranking and writes the ranks that changed
 pub async fn update_ranking(pool: sqlx::postgres::PgPool) {
-    let mut teams = sqlx::query_as::<sqlx::postgres::Postgres, RankTeam>("SELECT id, score, name, rank FROM teams")
+    let teams = sqlx::query_as::<sqlx::postgres::Postgres, RankTeam>("SELECT id, score, name, rank FROM teams")
         .fetch_all(&pool.clone())
         .await
         .unwrap();
-    teams.sort_by(|a, b| {
-        if a.score == b.score { a.name.cmp(&b.name) }
-        else { b.score.cmp(&a.score) }
-    });
-    let (mut rank, mut score):(Option<i32>, i64) = (None, 0);
-    let mut futures = vec![];
-    for team in &mut teams {
-        if score != team.score { score = team.score; 
-            rank = match rank {
-                None => Some(1),
-                Some(rank) => Some(rank + 1),
-            };
-        }
-        if score == 0 { team.rank = None; }
-        else { team.rank = rank; }
-        futures.push(team.rank_db(pool.clone()));
-    }
-    futures::future::join_all(futures).await;
+    ranking::write_ranks(&pool, &ranking::rank_changes(&teams)).await.unwrap();
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DeleteQuery {
     pub kind: String,
     pub id: i64,
     pub force: Option<bool>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DeleteOwnershipQuery {
     pub kind: String,
 }
+
+// Bodies accepted by `/edit`, depending on `kind`
+#[derive(ToSchema)]
+pub enum EditBody {
+    Team(EditTeam),
+    Person(EditPerson),
+    Badge(EditBadge),
+    Label(EditLabel),
+    Category(EditCategory),
+}
+
+impl EditBody {
+    // Reads the body of an edit of `kind`
+    pub fn parse(kind: &str, json: &str) -> Result<EditBody, String> {
+        let body = match kind {
+            "category" => serde_json::from_str(json).map(EditBody::Category),
+            "label" => serde_json::from_str(json).map(EditBody::Label),
+            "badge" => serde_json::from_str(json).map(EditBody::Badge),
+            "person" => serde_json::from_str(json).map(EditBody::Person),
+            "team" => serde_json::from_str(json).map(EditBody::Team),
+            _ => return Err(String::from("TYPE IS NOT AVAILABLE FOR DELETION")),
+        };
+        body.map_err(|err| format!("ERROR PARSING JSON: {}", err))
+    }
+
+    // Runs the field rules, then refuses an edit that sets nothing
+    pub fn check(&self) -> Result<(), validation::ValidationFailure> {
+        match self {
+            EditBody::Team(team) => validation::check(team),
+            EditBody::Person(person) => validation::check(person),
+            EditBody::Badge(badge) => validation::check(badge),
+            EditBody::Label(label) => validation::check(label),
+            EditBody::Category(category) => validation::check(category),
+        }?;
+        if self.update().is_ok_and(|update| update.is_empty()) {
+            return Err(validation::empty_edit());
+        }
+        Ok(())
+    }
+
+    // The columns the edit sets, for the SQL backends
+    pub fn update(&self) -> Result<Update, String> {
+        match self {
+            EditBody::Team(team) => team.update(),
+            EditBody::Person(person) => person.update(),
+            EditBody::Badge(badge) => Ok(badge.update()),
+            EditBody::Label(label) => Ok(label.update()),
+            EditBody::Category(category) => Ok(category.update()),
+        }
+    }
+}
+
+// Bodies accepted by `/delete_ownership`, depending on `kind`. Only used for the API documentation.
+#[derive(ToSchema)]
+pub enum OwnershipBody {
+    Label(DeleteOwnedLabel),
+    Badge(DeleteOwnedBadge),
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+}
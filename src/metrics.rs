@@ -0,0 +1,112 @@
+use std::time::Instant;
+use actix_web::{web, Error};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use prometheus::{Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder};
+use crate::AppState;
+
+// Prometheus metrics of one server. Request metrics are recorded by `track`,
+// the database gauges are read on every scrape.
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_seconds: HistogramVec,
+    errors: IntCounterVec,
+    ranking_seconds: HistogramVec,
+    awarded: IntCounter,
+    pool: IntGaugeVec,
+    pool_max: IntGauge,
+    totals: IntGaugeVec,
+}
+
+impl Metrics {
+    // `max_connections` is the configured pool size
+    pub fn new(max_connections: u32) -> Metrics {
+        let registry = Registry::new_custom(Some(String::from("starterspace")), None).unwrap();
+        let metrics = Metrics {
+            requests: IntCounterVec::new(Opts::new("http_requests_total", "Requests by route, method and status"), &["method", "route", "status"]).unwrap(),
+            request_seconds: HistogramVec::new(HistogramOpts::new("http_request_duration_seconds", "Time to answer a request"), &["method", "route"]).unwrap(),
+            errors: IntCounterVec::new(Opts::new("http_errors_total", "Responses with a 4xx or 5xx status"), &["status"]).unwrap(),
+            ranking_seconds: HistogramVec::new(HistogramOpts::new("ranking_duration_seconds", "Time spent recomputing scores and ranks"), &["operation"]).unwrap(),
+            awarded: IntCounter::new("badges_awarded_total", "Badges given to teams since the server started").unwrap(),
+            pool: IntGaugeVec::new(Opts::new("db_pool_connections", "Connections of the database pool"), &["state"]).unwrap(),
+            pool_max: IntGauge::new("db_pool_max_connections", "Configured size of the database pool").unwrap(),
+            totals: IntGaugeVec::new(Opts::new("rows", "Rows in the database"), &["table"]).unwrap(),
+            registry,
+        };
+        metrics.pool_max.set(max_connections as i64);
+        let collectors:Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.requests.clone()),
+            Box::new(metrics.request_seconds.clone()),
+            Box::new(metrics.errors.clone()),
+            Box::new(metrics.ranking_seconds.clone()),
+            Box::new(metrics.awarded.clone()),
+            Box::new(metrics.pool.clone()),
+            Box::new(metrics.pool_max.clone()),
+            Box::new(metrics.totals.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector).unwrap();
+        }
+        metrics
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        self.requests.with_label_values(&[method, route, &status.to_string()]).inc();
+        self.request_seconds.with_label_values(&[method, route]).observe(seconds);
+        if status >= 400 {
+            self.errors.with_label_values(&[&status.to_string()]).inc();
+        }
+    }
+
+    // Times `operation`, one of update_ranking or recompute_scores
+    pub fn ranking_timer(&self, operation: &str) -> Histogram {
+        self.ranking_seconds.with_label_values(&[operation])
+    }
+
+    pub fn awarded(&self, badges: usize) {
+        self.awarded.inc_by(badges as u64);
+    }
+
+    // Reads the pool and row gauges from `state`, then renders every metric in
+    // the Prometheus text format
+    pub async fn render(&self, state: &AppState) -> String {
+        if let Some(usage) = state.repo.pool_usage() {
+            self.pool.with_label_values(&["open"]).set(usage.size as i64);
+            self.pool.with_label_values(&["idle"]).set(usage.idle as i64);
+            self.pool.with_label_values(&["in_use"]).set(usage.size as i64 - usage.idle as i64);
+        }
+        // A scrape still answers when the database doesn't, the gauges keep their last values
+        if let Ok(totals) = state.repo.totals().await {
+            self.totals.with_label_values(&["teams"]).set(totals.teams);
+            self.totals.with_label_values(&["badges"]).set(totals.badges);
+            self.totals.with_label_values(&["badge_ownerships"]).set(totals.badge_ownerships);
+            self.totals.with_label_values(&["badge_ownerships_dated_today"]).set(totals.dated_today);
+        }
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+// Middleware counting and timing every request, wrapped around the whole app.
+// Routes are labelled with their pattern, like `/team/{id}`, so ids don't make
+// new series. Requests that match no route share the `unmatched` label.
+pub async fn track(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse, Error> {
+    let start = Instant::now();
+    let metrics = req.app_data::<web::Data<AppState>>().map(|state| state.metrics.clone());
+    let method = req.method().to_string();
+    // Routing happens inside `next`, the pattern is only known afterwards
+    let response = next.call(req).await;
+    if let Some(metrics) = metrics {
+        // Errors returned by handlers become responses with their status
+        let (route, status) = match &response {
+            Ok(response) => (response.request().match_pattern(), response.status()),
+            Err(err) => (None, err.as_response_error().status_code()),
+        };
+        let route = route.unwrap_or_else(|| String::from("unmatched"));
+        metrics.observe_request(&method, &route, status.as_u16(), start.elapsed().as_secs_f64());
+    }
+    response.map(ServiceResponse::map_into_boxed_body)
+}
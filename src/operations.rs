@@ -0,0 +1,185 @@
+use crate::*;
+
+// Writes shared by the REST handlers and the GraphQL mutations. Each one keeps
+// rankings and the team cache up to date, the repository keeps the scores.
+// Errors are the messages returned to the client.
+
+pub async fn create_team(state: &AppState, team: CreateTeam) -> Result<i64, String> {
+    let id = state.repo.create_team(team).await?;
+    state.cache.invalidate_list();
+    Ok(id)
+}
+
+pub async fn add_label(state: &AppState, label_ownership: CreateLabelOwnership) -> Result<(), String> {
+    let team_id = label_ownership.team_id;
+    state.repo.add_label(label_ownership).await?;
+    state.cache.invalidate_team(team_id);
+    Ok(())
+}
+
+pub async fn add_badge(state: &AppState, badge_ownership: CreateBadgeOwnership) -> Result<(), String> {
+    state.repo.add_badge(badge_ownership).await?;
+    state.metrics.awarded(1);
+    rerank(state).await;
+    state.cache.invalidate_all();
+    Ok(())
+}
+
+pub async fn add_person(state: &AppState, person: CreatePerson) -> Result<(), String> {
+    let team_id = person.team_id;
+    state.repo.add_person(person).await?;
+    state.cache.invalidate_team(team_id);
+    Ok(())
+}
+
+pub async fn create_badge(state: &AppState, badge: CreateBadge) -> Result<i64, String> {
+    state.repo.create_badge(badge).await
+}
+
+pub async fn create_label(state: &AppState, label: CreateLabel) -> Result<i64, String> {
+    state.repo.create_label(label).await
+}
+
+pub async fn create_category(state: &AppState, category: CreateCategory) -> Result<i64, String> {
+    state.repo.create_category(category).await
+}
+
+pub async fn remove_label(state: &AppState, label_ownership: DeleteOwnedLabel) -> Result<(), String> {
+    let team_id = label_ownership.team_id;
+    state.repo.remove_label(label_ownership).await?;
+    state.cache.invalidate_team(team_id);
+    Ok(())
+}
+
+pub async fn remove_badge(state: &AppState, badge_ownership: DeleteOwnedBadge) -> Result<(), String> {
+    state.repo.remove_badge(badge_ownership).await?;
+    rerank(state).await;
+    state.cache.invalidate_all();
+    Ok(())
+}
+
+// Teams in `requested` that are not in `changed`
+fn unchanged(requested: Vec<i64>, changed: &[i64]) -> Vec<i64> {
+    let mut unchanged:Vec<i64> = requested.into_iter().filter(|id| !changed.contains(id)).collect();
+    unchanged.sort();
+    unchanged.dedup();
+    unchanged
+}
+
+// Inserts every ownership at once, skipping the teams that already have the
+// badge, and updates the ranking once.
+pub async fn award_badge(state: &AppState, award: BadgeAward) -> Result<AwardReport, String> {
+    let awarded = state.repo.award_badge(&award).await?;
+    state.metrics.awarded(awarded.len());
+    if !awarded.is_empty() {
+        rerank(state).await;
+        state.cache.invalidate_all();
+    }
+    let duplicates = unchanged(award.team_ids, &awarded);
+    Ok(AwardReport { awarded, duplicates })
+}
+
+pub async fn revoke_badge(state: &AppState, revoke: BadgeRevoke) -> Result<RevokeReport, String> {
+    let revoked = state.repo.revoke_badge(&revoke).await?;
+    if !revoked.is_empty() {
+        rerank(state).await;
+        state.cache.invalidate_all();
+    }
+    let not_owned = unchanged(revoke.team_ids, &revoked);
+    Ok(RevokeReport { revoked, not_owned })
+}
+
+// `kind` is one of label, badge, category, person or team. With `force` every
+// object linked to it is deleted too.
+pub async fn delete(state: &AppState, kind: &str, id: i64, force: bool) -> Result<(), String> {
+    // Deleting a person only changes its own team, everything else can touch every team
+    let person_team = match kind {
+        "person" => state.repo.person_team(id).await,
+        _ => None,
+    };
+    state.repo.delete(kind, id, force).await?;
+    rerank(state).await;
+    match person_team {
+        Some(tid) => state.cache.invalidate_team(tid),
+        None => state.cache.invalidate_all(),
+    }
+    Ok(())
+}
+
+pub async fn edit(state: &AppState, id: i64, body: EditBody) -> Result<(), String> {
+    // New points move the owners' scores, and so the ranking
+    let mut points_changed = false;
+    let mut person_teams = vec![];
+    match &body {
+        EditBody::Badge(badge) => points_changed = badge.points.value().is_some(),
+        // The person may be moved, so both the old and the new team change
+        EditBody::Person(person) => person_teams = vec![state.repo.person_team(id).await, person.team_id.value().copied()],
+        _ => {},
+    }
+
+    state.repo.edit(id, body).await?;
+    if points_changed {
+        rerank(state).await;
+    }
+    if person_teams.is_empty() {
+        state.cache.invalidate_all();
+    } else {
+        for tid in person_teams.into_iter().flatten() {
+            state.cache.invalidate_team(tid);
+        }
+    }
+    Ok(())
+}
+
+// Recomputes every score from the badges, then the ranking
+pub async fn update_rankings(state: &AppState) {
+    let timer = state.metrics.ranking_timer("recompute_scores").start_timer();
+    state.repo.recompute_scores().await;
+    timer.observe_duration();
+    rerank(state).await;
+    state.cache.invalidate_all();
+}
+
+// Writes the ranks that changed, timed for `/metrics`
+pub async fn rerank(state: &AppState) {
+    let timer = state.metrics.ranking_timer("update_ranking").start_timer();
+    state.repo.update_ranking().await;
+    timer.observe_duration();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::scratch::Scratch;
+
+    async fn scores(scratch: &Scratch) -> Vec<(i64, i64)> {
+        sqlx::query_as("SELECT id, score FROM teams ORDER BY id").fetch_all(&scratch.pool).await.unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn batches_report_duplicates_and_not_owned_on_postgres() {
+        let scratch = Scratch::new("batches").await;
+        scratch.seed().await;
+        // Both sample teams own both sample badges, Rocket owns none
+        scratch.execute("INSERT INTO teams (score, stage, name, description, creation_date, location) VALUES (0, 1, 'Rocket', 'A team', '2023-02-26', 'Monterrey, Mexico')").await;
+        let state = AppState::new(Arc::new(crate::repository::PgRepository::new(scratch.pool.clone())));
+
+        let award = BadgeAward { badge_id: 1, acquisition_date: String::from("2023-05-01"), team_ids: vec![3, 1, 3, 2] };
+        let report = award_badge(&state, award).await.unwrap();
+        assert_eq!((report.awarded, report.duplicates), (vec![3], vec![1, 2]));
+        assert_eq!(scores(&scratch).await, [(1, 900), (2, 900), (3, 200)]);
+
+        let revoke = BadgeRevoke { badge_id: 2, team_ids: vec![2, 3, 2] };
+        let report = revoke_badge(&state, revoke).await.unwrap();
+        assert_eq!((report.revoked, report.not_owned), (vec![2], vec![3]));
+        assert_eq!(scores(&scratch).await, [(1, 900), (2, 200), (3, 200)]);
+
+        // Nothing changes when every team is a duplicate
+        let award = BadgeAward { badge_id: 1, acquisition_date: String::from("2023-05-01"), team_ids: vec![1, 2, 3] };
+        let report = award_badge(&state, award).await.unwrap();
+        assert_eq!((report.awarded, report.duplicates), (vec![], vec![1, 2, 3]));
+        scratch.drop().await;
+    }
+}
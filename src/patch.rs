@@ -0,0 +1,227 @@
+use actix_web::cookie::time::Date;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{Database, Encode, QueryBuilder, Type};
+
+// A field of an edit payload, following JSON Merge Patch (RFC 7396): a missing
+// member leaves the column as it is, `null` clears it and any other value sets it.
+// Fields need `#[serde(default)]` so missing members become `Absent`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Patch<T> {
+    #[default]
+    Absent,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Patch::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+// A column set by an edit, bound as a parameter of the UPDATE. `None` clears it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Text(Option<String>),
+    Integer(Option<i32>),
+    BigInt(Option<i64>),
+    Date(Option<Date>),
+}
+
+// The columns an edit payload sets, in the order they are written
+#[derive(Debug, PartialEq)]
+pub struct Update {
+    pub table: &'static str,
+    pub columns: Vec<(&'static str, Column)>,
+}
+
+impl Update {
+    pub fn new(table: &'static str) -> Update {
+        Update { table, columns: vec![] }
+    }
+
+    fn set<T: Clone>(mut self, column: &'static str, value: &Patch<T>, wrap: fn(Option<T>) -> Column) -> Update {
+        match value {
+            Patch::Absent => {},
+            Patch::Null => self.columns.push((column, wrap(None))),
+            Patch::Value(value) => self.columns.push((column, wrap(Some(value.clone())))),
+        }
+        self
+    }
+
+    pub fn text(self, column: &'static str, value: &Patch<String>) -> Update {
+        self.set(column, value, Column::Text)
+    }
+
+    pub fn integer(self, column: &'static str, value: &Patch<i32>) -> Update {
+        self.set(column, value, Column::Integer)
+    }
+
+    pub fn big_int(self, column: &'static str, value: &Patch<i64>) -> Update {
+        self.set(column, value, Column::BigInt)
+    }
+
+    // Dates come as YYYY-MM-DD text and are bound as dates
+    pub fn date(self, column: &'static str, value: &Patch<String>) -> Result<Update, String> {
+        let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+        let value = match value {
+            Patch::Absent => Patch::Absent,
+            Patch::Null => Patch::Null,
+            Patch::Value(value) => Patch::Value(Date::parse(value, &format).map_err(|_| format!("INVALID DATE {}", value))?),
+        };
+        Ok(self.set(column, &value, Column::Date))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    // `UPDATE table SET column = ?, ... WHERE id = ?` with every value bound,
+    // using the placeholders of `DB`
+    pub fn query<'args, DB: Database>(self, id: i64) -> QueryBuilder<'args, DB>
+    where
+        Option<String>: Encode<'args, DB> + Type<DB>,
+        Option<i32>: Encode<'args, DB> + Type<DB>,
+        Option<i64>: Encode<'args, DB> + Type<DB>,
+        Option<Date>: Encode<'args, DB> + Type<DB>,
+        i64: Encode<'args, DB> + Type<DB>,
+    {
+        let mut query = QueryBuilder::new(format!("UPDATE {} SET ", self.table));
+        let mut columns = query.separated(", ");
+        for (column, value) in self.columns {
+            columns.push(format!("{} = ", column));
+            match value {
+                Column::Text(value) => columns.push_bind_unseparated(value),
+                Column::Integer(value) => columns.push_bind_unseparated(value),
+                Column::BigInt(value) => columns.push_bind_unseparated(value),
+                Column::Date(value) => columns.push_bind_unseparated(value),
+            };
+        }
+        query.push(" WHERE id = ").push_bind(id);
+        query
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Only called when the member is present
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Patch::Value(value),
+            None => Patch::Null,
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value().serialize(serializer)
+    }
+}
+
+impl<T> From<async_graphql::MaybeUndefined<T>> for Patch<T> {
+    fn from(value: async_graphql::MaybeUndefined<T>) -> Self {
+        match value {
+            async_graphql::MaybeUndefined::Undefined => Patch::Absent,
+            async_graphql::MaybeUndefined::Null => Patch::Null,
+            async_graphql::MaybeUndefined::Value(value) => Patch::Value(value),
+        }
+    }
+}
+
+// GraphQL doesn't tell a missing argument from `null` for `Option` fields
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => Patch::Value(value),
+            None => Patch::Absent,
+        }
+    }
+}
+
+impl<T: validator::ValidateLength<u64>> validator::ValidateLength<u64> for Patch<T> {
+    fn length(&self) -> Option<u64> {
+        self.value().and_then(|value| value.length())
+    }
+}
+
+impl<T: validator::ValidateRange<T> + Copy> validator::ValidateRange<T> for Patch<T> {
+    fn greater_than(&self, max: T) -> Option<bool> {
+        self.value().and_then(|value| value.greater_than(max))
+    }
+
+    fn less_than(&self, min: T) -> Option<bool> {
+        self.value().and_then(|value| value.less_than(min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::time::{Date, Month};
+    use sqlx::Postgres;
+    use crate::{EditBody, EditPerson, EditTeam, validation};
+    use super::{Column, Patch};
+
+    #[test]
+    fn missing_null_and_value_are_different() {
+        let team:EditTeam = serde_json::from_str(r#"{"logo_url": null, "banner_url": "https://example.com/banner.png"}"#).unwrap();
+        assert_eq!(team.name, Patch::Absent);
+        assert_eq!(team.logo_url, Patch::Null);
+        assert_eq!(team.banner_url, Patch::Value(String::from("https://example.com/banner.png")));
+    }
+
+    #[test]
+    fn null_clears_and_missing_keeps() {
+        let team:EditTeam = serde_json::from_str(r#"{"name": "Moneypool", "logo_url": null}"#).unwrap();
+        let update = team.update().unwrap();
+        assert_eq!(update.columns, [("name", Column::Text(Some(String::from("Moneypool")))), ("logo_url", Column::Text(None))]);
+        assert_eq!(update.query::<Postgres>(1).sql(), "UPDATE teams SET name = $1, logo_url = $2 WHERE id = $3");
+
+        let person:EditPerson = serde_json::from_str(r#"{"picture_url": null, "portafolio_url": "https://example.com"}"#).unwrap();
+        assert_eq!(person.update().unwrap().query::<Postgres>(1).sql(), "UPDATE persons SET picture_url = $1, portafolio_url = $2 WHERE id = $3");
+    }
+
+    #[test]
+    fn null_string_is_a_value() {
+        let team:EditTeam = serde_json::from_str(r#"{"logo_url": "null"}"#).unwrap();
+        assert_eq!(team.update().unwrap().columns, [("logo_url", Column::Text(Some(String::from("null"))))]);
+    }
+
+    #[test]
+    fn values_are_bound_not_pasted() {
+        let team:EditTeam = serde_json::from_str(r#"{"name": "O'Brien', score = 9999 --", "stage": 2, "creation_date": "2023-01-31"}"#).unwrap();
+        let update = team.update().unwrap();
+        assert_eq!(update.columns[0], ("name", Column::Text(Some(String::from("O'Brien', score = 9999 --")))));
+        assert_eq!(update.columns[1], ("stage", Column::Integer(Some(2))));
+        assert_eq!(update.columns[2].1, Column::Date(Some(Date::from_calendar_date(2023, Month::January, 31).unwrap())));
+        assert_eq!(update.query::<Postgres>(4).sql(), "UPDATE teams SET name = $1, stage = $2, creation_date = $3 WHERE id = $4");
+    }
+
+    #[test]
+    fn edits_must_set_something() {
+        let body = EditBody::parse("team", "{}").unwrap();
+        let failure = body.check().unwrap_err();
+        assert_eq!(failure.errors.len(), 1);
+        assert_eq!((&failure.errors[0].field[..], &failure.errors[0].code[..]), ("body", "empty"));
+        assert!(EditBody::parse("label", r#"{"name": "Fintech"}"#).unwrap().check().is_ok());
+    }
+
+    #[test]
+    fn required_columns_cant_be_null() {
+        let team:EditTeam = serde_json::from_str(r#"{"name": null, "stage": null}"#).unwrap();
+        let failure = validation::check(&team).unwrap_err();
+        let fields:Vec<&str> = failure.errors.iter().map(|error| &error.field[..]).collect();
+        assert_eq!(fields, ["name", "stage"]);
+        assert!(failure.errors.iter().all(|error| error.code == "null"));
+    }
+
+    #[test]
+    fn limits_apply_to_values() {
+        let team:EditTeam = serde_json::from_str(r#"{"stage": 9, "logo_url": "not a url", "banner_url": null}"#).unwrap();
+        let failure = validation::check(&team).unwrap_err();
+        let fields:Vec<&str> = failure.errors.iter().map(|error| &error.field[..]).collect();
+        assert_eq!(fields, ["logo_url", "stage"]);
+    }
+}
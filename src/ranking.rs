@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use sqlx::{Executor, Postgres, QueryBuilder};
+use crate::RankTeam;
+
+// Maintenance of `teams.score` and `teams.rank`. Scores are kept by triggers in
+// the database, which move the owners' scores by a badge's points whenever it
+// is given, taken away or changes points. Ranks are computed here and only the
+// ones that end up different are written back. `update_scores` is still there
+// to recompute everything from scratch.
+
+// Teams sorted by score and then by name. Teams with the same score share a
+// rank, and teams without points have none.
+pub fn ranking(teams: &[RankTeam]) -> Vec<(i64, Option<i32>)> {
+    let mut order:Vec<&RankTeam> = teams.iter().collect();
+    order.sort_by(|a, b| {
+        if a.score == b.score { a.name.cmp(&b.name) }
+        else { b.score.cmp(&a.score) }
+    });
+    let (mut rank, mut score):(Option<i32>, i64) = (None, 0);
+    order.into_iter().map(|team| {
+        if score != team.score {
+            score = team.score;
+            rank = Some(rank.map_or(1, |rank| rank + 1));
+        }
+        (team.id, if score == 0 { None } else { rank })
+    }).collect()
+}
+
+// The teams whose stored rank is not the one they should have
+pub fn rank_changes(teams: &[RankTeam]) -> Vec<(i64, Option<i32>)> {
+    let stored:HashMap<i64, Option<i32>> = teams.iter().map(|team| (team.id, team.rank)).collect();
+    ranking(teams).into_iter().filter(|(id, rank)| stored[id] != *rank).collect()
+}
+
+// Writes every change in a single `UPDATE ... FROM (VALUES ...)`
+pub async fn write_ranks<'c, E: Executor<'c, Database = Postgres>>(executor: E, changes: &[(i64, Option<i32>)]) -> Result<(), sqlx::Error> {
+    if changes.is_empty() { return Ok(()); }
+    let mut query = QueryBuilder::<Postgres>::new("UPDATE teams SET rank = changes.rank FROM (");
+    query.push_values(changes, |mut row, (id, rank)| {
+        row.push_bind(*id).push_bind(*rank);
+    });
+    query.push(") AS changes (id, rank) WHERE teams.id = changes.id");
+    query.build().execute(executor).await?;
+    Ok(())
+}
+
+// Triggers that keep `teams.score` equal to the points of each team's badges
+const SCORE_TRIGGERS: &str = include_str!("../database_information/psql-score-triggers.sql");
+
+// Creates or replaces the score triggers, run when the server starts
+pub async fn install_score_triggers(pool: &sqlx::postgres::PgPool) -> Result<(), sqlx::Error> {
+    pool.execute(SCORE_TRIGGERS).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use proptest::prelude::*;
+    use crate::RankTeam;
+    use super::{ranking, rank_changes};
+
+    fn teams() -> impl Strategy<Value = Vec<RankTeam>> {
+        // Few distinct scores so there are ties, and ranks that are often stale
+        prop::collection::vec((0..6i64, prop::option::of(1..8i32)), 0..40).prop_map(|teams| {
+            teams.into_iter().enumerate().map(|(i, (score, rank))| RankTeam {
+                id: i as i64 + 1,
+                score: score * 100,
+                name: format!("team {:02}", (i * 7) % 40),
+                rank,
+            }).collect()
+        })
+    }
+
+    proptest! {
+        // Writing only the changed ranks leaves every team with the rank the full
+        // recomputation gives it
+        #[test]
+        fn changes_match_full_recomputation(mut teams in teams()) {
+            let full:HashMap<i64, Option<i32>> = ranking(&teams).into_iter().collect();
+            let changes = rank_changes(&teams);
+            for (id, rank) in &changes {
+                let team = teams.iter_mut().find(|team| team.id == *id).unwrap();
+                prop_assert_ne!(team.rank, *rank);
+                team.rank = *rank;
+            }
+            for team in &teams {
+                prop_assert_eq!(team.rank, full[&team.id]);
+            }
+            prop_assert!(rank_changes(&teams).is_empty());
+        }
+
+        // A team's rank is one more than the number of distinct higher scores
+        #[test]
+        fn ranks_follow_scores(teams in teams()) {
+            let full:HashMap<i64, Option<i32>> = ranking(&teams).into_iter().collect();
+            prop_assert_eq!(full.len(), teams.len());
+            for team in &teams {
+                let mut higher:Vec<i64> = teams.iter().map(|other| other.score).filter(|score| *score > team.score).collect();
+                higher.sort();
+                higher.dedup();
+                let expected = if team.score == 0 { None } else { Some(higher.len() as i32 + 1) };
+                prop_assert_eq!(full[&team.id], expected);
+            }
+        }
+    }
+
+    const TEAMS: usize = 4;
+    const BADGES: usize = 3;
+
+    #[derive(Debug, Clone)]
+    enum Change {
+        Add(usize, usize),
+        Remove(usize, usize),
+        Award(usize, Vec<usize>),
+        Revoke(usize, Vec<usize>),
+        Points(usize, i64),
+    }
+
+    fn changes() -> impl Strategy<Value = Vec<Change>> {
+        let team = 0..TEAMS;
+        let badge = 0..BADGES;
+        let teams = prop::collection::vec(0..TEAMS, 1..TEAMS);
+        prop::collection::vec(prop_oneof![
+            (badge.clone(), team.clone()).prop_map(|(badge, team)| Change::Add(badge, team)),
+            (badge.clone(), team).prop_map(|(badge, team)| Change::Remove(badge, team)),
+            (badge.clone(), teams.clone()).prop_map(|(badge, teams)| Change::Award(badge, teams)),
+            (badge.clone(), teams).prop_map(|(badge, teams)| Change::Revoke(badge, teams)),
+            (badge, 0..500i64).prop_map(|(badge, points)| Change::Points(badge, points)),
+        ], 1..12)
+    }
+
+    async fn apply(state: &crate::AppState, change: Change, teams: &[i64], badges: &[i64]) {
+        use crate::*;
+        match change {
+            Change::Add(badge, team) => {
+                // Fails when the team already has the badge, which changes nothing
+                let _ = operations::add_badge(state, CreateBadgeOwnership { team_id: teams[team], badge_id: badges[badge], acquisition_date: String::from("2023-01-01") }).await;
+            },
+            Change::Remove(badge, team) => operations::remove_badge(state, DeleteOwnedBadge { team_id: teams[team], badge_id: badges[badge] }).await.unwrap(),
+            Change::Award(badge, ids) => {
+                let team_ids = ids.into_iter().map(|team| teams[team]).collect();
+                operations::award_badge(state, BadgeAward { badge_id: badges[badge], acquisition_date: String::from("2023-01-01"), team_ids }).await.unwrap();
+            },
+            Change::Revoke(badge, ids) => {
+                let team_ids = ids.into_iter().map(|team| teams[team]).collect();
+                operations::revoke_badge(state, BadgeRevoke { badge_id: badges[badge], team_ids }).await.unwrap();
+            },
+            Change::Points(badge, points) => {
+                let body = EditBody::Badge(EditBadge { points: crate::patch::Patch::Value(points), ..Default::default() });
+                operations::edit(state, badges[badge], body).await.unwrap();
+            },
+        }
+    }
+
+    // Checks the stored scores of `teams` against their summed badge points and
+    // every stored rank against the full ranking
+    async fn check(state: &crate::AppState, teams: &[i64]) -> Result<(), TestCaseError> {
+        let repo = state.repo.as_ref();
+        let stored:Vec<RankTeam> = repo.teams().await.into_iter()
+            .map(|team| RankTeam { id: team.id, score: team.score, name: team.name, rank: team.rank })
+            .collect();
+        for id in teams {
+            let owned = serde_json::to_value(repo.team_badges(*id).await).unwrap();
+            let sum:i64 = owned.as_array().unwrap().iter().map(|owned| owned["badge"]["points"].as_i64().unwrap()).sum();
+            prop_assert_eq!(stored.iter().find(|team| team.id == *id).unwrap().score, sum);
+        }
+        prop_assert!(rank_changes(&stored).is_empty());
+        Ok(())
+    }
+
+    // Runs random badge changes through `operations` on teams and badges made
+    // for the test, and compares the result with a full recomputation after
+    // every change. The backend is thrown away afterwards, so nothing is cleaned up.
+    fn run_changes(runtime: &actix_web::rt::Runtime, state: &crate::AppState, cases: u32) -> Result<(), String> {
+        use crate::*;
+        let (teams, badges) = runtime.block_on(async {
+            let category = operations::create_category(state, CreateCategory { name: String::from("ranking test") }).await.unwrap();
+            let mut badges = vec![];
+            for i in 0..BADGES {
+                let badge = CreateBadge { name: format!("ranking test badge {}", i), description: String::from("test"), points: 100 * (i as i64 + 1), category };
+                badges.push(operations::create_badge(state, badge).await.unwrap());
+            }
+            let mut teams = vec![];
+            for i in 0..TEAMS {
+                let team = CreateTeam { name: format!("ranking test team {}", i), description: String::from("test"), location: String::from("test"), stage: 1, creation_date: String::from("2023-01-01"), banner_url: None, logo_url: None };
+                teams.push(operations::create_team(state, team).await.unwrap());
+            }
+            operations::update_rankings(state).await;
+            (teams, badges)
+        });
+
+        proptest::test_runner::TestRunner::new(ProptestConfig::with_cases(cases)).run(&changes(), |changes| {
+            runtime.block_on(async {
+                for change in changes {
+                    apply(state, change, &teams, &badges).await;
+                    check(state, &teams).await?;
+                }
+                // Every case starts from teams without badges
+                for badge_id in &badges {
+                    operations::revoke_badge(state, BadgeRevoke { badge_id: *badge_id, team_ids: teams.clone() }).await.unwrap();
+                }
+                Ok(())
+            })
+        }).map_err(|err| err.to_string())
+    }
+
+    // On the in-memory backend, seeded with the sample teams so the new ones
+    // are ranked among others. MemoryRepository moves the scores itself, so
+    // this only checks its copy of the triggers, not the triggers.
+    #[test]
+    fn operations_match_full_recomputation() {
+        let repo = crate::repository::MemoryRepository::new();
+        repo.seed(include_str!("../database_information/psql-temp-values.sql")).unwrap();
+        run_changes(&actix_web::rt::Runtime::new().unwrap(), &crate::AppState::new(std::sync::Arc::new(repo)), 64).unwrap();
+    }
+
+    // The same on Postgres, where the score triggers keep the scores
+    #[test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    fn operations_match_full_recomputation_on_postgres() {
+        let runtime = actix_web::rt::Runtime::new().unwrap();
+        let scratch = runtime.block_on(async {
+            let scratch = crate::scratch::Scratch::new("ranking").await;
+            scratch.seed().await;
+            scratch
+        });
+        let state = crate::AppState::new(std::sync::Arc::new(crate::repository::PgRepository::new(scratch.pool.clone())));
+        let result = run_changes(&runtime, &state, 32);
+        runtime.block_on(scratch.drop());
+        result.unwrap();
+    }
+}
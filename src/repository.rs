@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use crate::*;
+
+mod postgres;
+mod memory;
+mod seed;
+#[cfg(feature = "mysql")]
+mod mysql;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use postgres::PgRepository;
+pub use memory::MemoryRepository;
+#[cfg(feature = "mysql")]
+pub use mysql::MySqlRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRepository;
+
+// Row counts for the gauges in `/metrics`. `dated_today` counts the badge
+// ownerships whose acquisition date, as given by the client, is the
+// database's current date. Ownerships added today with another date are not in it.
+#[derive(sqlx::FromRow, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Totals {
+    pub teams: i64,
+    pub badges: i64,
+    pub badge_ownerships: i64,
+    pub dated_today: i64,
+}
+
+// Open and idle connections of a backend's pool
+#[derive(Debug, Clone, Copy)]
+pub struct PoolUsage {
+    pub size: u32,
+    pub idle: usize,
+}
+
+// Same for every SQL backend
+const TOTALS: &str = "SELECT \
+    (SELECT COUNT(*) FROM teams) AS teams, \
+    (SELECT COUNT(*) FROM badges) AS badges, \
+    (SELECT COUNT(*) FROM badge_ownerships) AS badge_ownerships, \
+    (SELECT COUNT(*) FROM badge_ownerships WHERE acquisition_date = CURRENT_DATE) AS dated_today";
+
+// Sent when a feature needs the Postgres pool and the backend has none
+pub const UNSUPPORTED: &str = "NOT AVAILABLE WITH THIS DATABASE";
+
+// Picks the backend from the scheme of the database url. Postgres gets its
+// score triggers and search indexes installed and SQLite its migrations run.
+// MySQL and SQLite need their cargo features.
+pub async fn connect(database: &config::DatabaseConfig) -> Result<std::sync::Arc<dyn Repository>, String> {
+    let database_url = &database.url[..];
+    let scheme = database_url.split(':').next().unwrap_or_default();
+    match scheme {
+        "postgres" | "postgresql" => {
+            let pool = pool_options(database).connect(database_url).await.map_err(|err| err.to_string())?;
+            ranking::install_score_triggers(&pool).await.map_err(|err| err.to_string())?;
+            search::install_search_indexes(&pool).await.map_err(|err| format!("Unable to install the search indexes of database_information/psql-search.sql: {}", err))?;
+            Ok(std::sync::Arc::new(PgRepository::new(pool)))
+        },
+        #[cfg(feature = "mysql")]
+        "mysql" | "mariadb" => {
+            let pool = pool_options(database).connect(database_url).await.map_err(|err| err.to_string())?;
+            Ok(std::sync::Arc::new(MySqlRepository::new(pool)))
+        },
+        #[cfg(not(feature = "mysql"))]
+        "mysql" | "mariadb" => Err(String::from("MySQL needs the mysql feature, build with --features mysql")),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => SqliteRepository::connect(database_url, pool_options(database)).await.map(|repo| std::sync::Arc::new(repo) as std::sync::Arc<dyn Repository>).map_err(|err| err.to_string()),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => Err(String::from("SQLite needs the sqlite feature, build with --features sqlite")),
+        _ => Err(format!("Unknown database scheme {}", scheme)),
+    }
+}
+
+// Pool size and timeouts from the configuration, the same for every backend
+pub fn pool_options<DB: sqlx::Database>(database: &config::DatabaseConfig) -> sqlx::pool::PoolOptions<DB> {
+    let idle_timeout = (database.idle_timeout > 0).then(|| std::time::Duration::from_secs(database.idle_timeout));
+    sqlx::pool::PoolOptions::new()
+        .max_connections(database.max_connections)
+        .min_connections(database.min_connections)
+        .acquire_timeout(std::time::Duration::from_secs(database.acquire_timeout))
+        .idle_timeout(idle_timeout)
+}
+
+// `column IN (ids)`, or a false condition when there are no ids. Stands in for
+// Postgres' `column = ANY($1)` on the other databases.
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+fn push_in<'a, DB: sqlx::Database>(query: &mut sqlx::QueryBuilder<'a, DB>, column: &str, ids: &[i64])
+where i64: sqlx::Encode<'a, DB> + sqlx::Type<DB> {
+    if ids.is_empty() {
+        query.push("FALSE");
+        return;
+    }
+    query.push(column).push(" IN (");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    separated.push_unseparated(")");
+}
+
+// Storage used by the handlers and `operations`. Writes return the message sent
+// to the client when they fail, reads panic on database errors like the
+// queries they replaced did. Scores follow badge changes inside the backend,
+// ranks are left to `update_ranking`.
+#[async_trait]
+pub trait Repository: Send + Sync {
+    // The Postgres pool, for the features that only run on Postgres: search,
+    // the GraphQL loader, JSON Patch, import, export, backups and the integrity
+    // check. They query it directly, the trait only covers the CRUD core
+    fn pool(&self) -> Option<&sqlx::postgres::PgPool> {
+        None
+    }
+
+    // None for backends without a connection pool
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        None
+    }
+
+    async fn teams(&self) -> Vec<RawTeam>;
+    async fn team(&self, id: i64) -> Option<RawTeam>;
+    async fn labels(&self) -> Vec<Label>;
+    async fn badges(&self) -> Vec<RawBadge>;
+    async fn categories(&self) -> Vec<Category>;
+    async fn team_labels(&self, team_id: i64) -> Vec<Label>;
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge>;
+    async fn team_persons(&self, team_id: i64) -> Vec<Person>;
+    async fn person_team(&self, id: i64) -> Option<i64>;
+
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String>;
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String>;
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String>;
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String>;
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String>;
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String>;
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String>;
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String>;
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String>;
+    // Award and revoke return the teams that changed, sorted
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String>;
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String>;
+    // `kind` is one of label, badge, category, person or team. With `force`
+    // every object linked to it is deleted too.
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String>;
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String>;
+    // Runs a fixture file of INSERT statements, like `psql-temp-values.sql`
+    async fn seed(&self, sql: &str) -> Result<(), String>;
+    async fn totals(&self) -> Result<Totals, String>;
+    // Whether the database answers and has the schema this backend needs
+    async fn ready(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn rank_teams(&self) -> Vec<RankTeam>;
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]);
+    // Sets every team's score to the points of its badges
+    async fn recompute_scores(&self);
+
+    // Recomputes the ranking and writes the ranks that changed
+    async fn update_ranking(&self) {
+        let teams = self.rank_teams().await;
+        self.write_ranks(&ranking::rank_changes(&teams)).await;
+    }
+}
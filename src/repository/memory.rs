@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use async_trait::async_trait;
+use crate::*;
+use crate::patch::Patch;
+use super::{Repository, Totals};
+use super::seed::{self, Value};
+
+// Keeps every table in memory, for running the handlers without a database.
+// Names are unique and references are checked like the Postgres schema does,
+// and scores follow badge changes like the score triggers do.
+#[derive(Default)]
+pub struct MemoryRepository {
+    tables: RwLock<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    teams: Vec<RawTeam>,
+    persons: Vec<RawPerson>,
+    badges: Vec<RawBadge>,
+    categories: Vec<Category>,
+    labels: Vec<Label>,
+    label_ownerships: Vec<LabelOwnership>,
+    badge_ownerships: Vec<BadgeOwnership>,
+    // Last id given in each table, like the BIGSERIAL sequences
+    sequences: HashMap<&'static str, i64>,
+}
+
+impl Tables {
+    fn next_id(&mut self, table: &'static str) -> i64 {
+        let id = self.sequences.entry(table).or_insert(0);
+        *id += 1;
+        *id
+    }
+
+    fn team_exists(&self, id: i64) -> bool {
+        self.teams.iter().any(|team| team.id == id)
+    }
+
+    fn points(&self, badge_id: i64) -> i64 {
+        self.badges.iter().find(|badge| badge.id == badge_id).map_or(0, |badge| badge.points)
+    }
+
+    fn add_points(&mut self, team_id: i64, points: i64) {
+        if let Some(team) = self.teams.iter_mut().find(|team| team.id == team_id) {
+            team.score += points;
+        }
+    }
+
+    // Removes the ownerships matching `remove`, taking their points from the teams
+    fn remove_badge_ownerships(&mut self, remove: impl Fn(&BadgeOwnership) -> bool) -> Vec<i64> {
+        let (removed, kept):(Vec<BadgeOwnership>, Vec<BadgeOwnership>) = self.badge_ownerships.drain(..).partition(|ownership| remove(ownership));
+        self.badge_ownerships = kept;
+        for ownership in &removed {
+            let points = self.points(ownership.badge_id);
+            self.add_points(ownership.team_id, -points);
+        }
+        removed.into_iter().map(|ownership| ownership.team_id).collect()
+    }
+
+    fn insert_badge_ownership(&mut self, team_id: i64, badge_id: i64, acquisition_date: &str) -> Result<(), String> {
+        let id = self.next_id("badge_ownerships");
+        self.badge_ownerships.push(BadgeOwnership { id, team_id, badge_id, acquisition_date: date(acquisition_date)? });
+        let points = self.points(badge_id);
+        self.add_points(team_id, points);
+        Ok(())
+    }
+}
+
+fn error(message: String) -> String {
+    format!("ERROR ADDING TO DATABASE: {}", message)
+}
+
+fn unique(table: &str, column: &str) -> String {
+    error(format!("duplicate key value violates unique constraint \"{}_{}_key\"", table, column))
+}
+
+fn foreign_key(table: &str, column: &str, id: i64) -> String {
+    error(format!("insert or update on table \"{}\" violates foreign key constraint: {} {} does not exist", table, column, id))
+}
+
+fn still_referenced(table: &str, referencing: &str) -> String {
+    error(format!("update or delete on table \"{}\" violates foreign key constraint on table \"{}\"", table, referencing))
+}
+
+fn date(date: &str) -> Result<actix_web::cookie::time::Date, String> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    actix_web::cookie::time::Date::parse(date, &format).map_err(|_| error(format!("invalid input syntax for type date: \"{}\"", date)))
+}
+
+// Applies a text or number field of an edit payload
+fn set<T: Clone>(column: &mut T, value: &Patch<T>) {
+    if let Patch::Value(value) = value { *column = value.clone(); }
+}
+
+fn set_optional<T: Clone>(column: &mut Option<T>, value: &Patch<T>) {
+    match value {
+        Patch::Absent => {},
+        Patch::Null => *column = None,
+        Patch::Value(value) => *column = Some(value.clone()),
+    }
+}
+
+impl MemoryRepository {
+    pub fn new() -> MemoryRepository {
+        MemoryRepository::default()
+    }
+
+    // Loads the rows of a seed file like `psql-temp-values.sql` as they are,
+    // so stored scores and ranks are kept even when they don't match the badges
+    pub fn seed(&self, sql: &str) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        for insert in seed::inserts(sql)? {
+            let text = |column: &str| match insert.get(column) {
+                Some(Value::Text(text)) => Ok(text.clone()),
+                _ => Err(format!("{} NEEDS TEXT IN {}", insert.table, column)),
+            };
+            let optional_text = |column: &str| match insert.get(column) {
+                Some(Value::Text(text)) => Ok(Some(text.clone())),
+                Some(Value::Null) | None => Ok(None),
+                _ => Err(format!("{} NEEDS TEXT IN {}", insert.table, column)),
+            };
+            let number = |column: &str| match insert.get(column) {
+                Some(Value::Number(number)) => Ok(*number),
+                _ => Err(format!("{} NEEDS A NUMBER IN {}", insert.table, column)),
+            };
+            let table = insert.table.as_str();
+            let id = match insert.get("id") {
+                Some(Value::Number(id)) => {
+                    let last = tables.sequences.entry(seed_table(table)?).or_insert(0);
+                    *last = (*last).max(*id);
+                    *id
+                },
+                _ => tables.next_id(seed_table(table)?),
+            };
+            match table {
+                "teams" => tables.teams.push(RawTeam {
+                    id,
+                    rank: match insert.get("rank") {
+                        Some(Value::Number(rank)) => Some(*rank as i32),
+                        _ => None,
+                    },
+                    score: number("score")?,
+                    stage: number("stage")? as i32,
+                    name: text("name")?,
+                    logo_url: optional_text("logo_url")?,
+                    banner_url: optional_text("banner_url")?,
+                    description: text("description")?,
+                    creation_date: date(&text("creation_date")?)?,
+                    location: text("location")?,
+                }),
+                "persons" => tables.persons.push(RawPerson {
+                    id,
+                    team_id: number("team_id")?,
+                    name: text("name")?,
+                    career: text("career")?,
+                    graduation_date: date(&text("graduation_date")?)?,
+                    picture_url: optional_text("picture_url")?,
+                    portafolio_url: optional_text("portafolio_url")?,
+                }),
+                "badges" => tables.badges.push(RawBadge { id, name: text("name")?, description: text("description")?, points: number("points")?, category: number("category")? }),
+                "badge_categories" => tables.categories.push(Category { id, name: text("name")? }),
+                "labels" => tables.labels.push(Label { id, name: text("name")? }),
+                "label_ownerships" => tables.label_ownerships.push(LabelOwnership { id, team_id: number("team_id")?, label_id: number("label_id")? }),
+                "badge_ownerships" => tables.badge_ownerships.push(BadgeOwnership {
+                    id,
+                    team_id: number("team_id")?,
+                    badge_id: number("badge_id")?,
+                    acquisition_date: date(&text("acquisition_date")?)?,
+                }),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+// The sequence name of a seeded table
+fn seed_table(table: &str) -> Result<&'static str, String> {
+    ["teams", "persons", "badges", "badge_categories", "labels", "label_ownerships", "badge_ownerships"]
+        .into_iter()
+        .find(|name| *name == table)
+        .ok_or_else(|| format!("UNKNOWN TABLE {}", table))
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn teams(&self) -> Vec<RawTeam> {
+        self.tables.read().unwrap().teams.clone()
+    }
+
+    async fn team(&self, id: i64) -> Option<RawTeam> {
+        self.tables.read().unwrap().teams.iter().find(|team| team.id == id).cloned()
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        self.tables.read().unwrap().labels.clone()
+    }
+
+    async fn badges(&self) -> Vec<RawBadge> {
+        self.tables.read().unwrap().badges.clone()
+    }
+
+    async fn categories(&self) -> Vec<Category> {
+        self.tables.read().unwrap().categories.clone()
+    }
+
+    async fn team_labels(&self, team_id: i64) -> Vec<Label> {
+        let tables = self.tables.read().unwrap();
+        tables.labels.iter()
+            .filter(|label| tables.label_ownerships.iter().any(|ownership| ownership.team_id == team_id && ownership.label_id == label.id))
+            .cloned()
+            .collect()
+    }
+
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge> {
+        let tables = self.tables.read().unwrap();
+        tables.badge_ownerships.iter().filter(|ownership| ownership.team_id == team_id).filter_map(|ownership| {
+            let badge = tables.badges.iter().find(|badge| badge.id == ownership.badge_id)?;
+            let category = tables.categories.iter().find(|category| category.id == badge.category)?;
+            Some(OwnedBadge::from(badge.clone(), category.clone(), ownership))
+        }).collect()
+    }
+
+    async fn team_persons(&self, team_id: i64) -> Vec<Person> {
+        self.tables.read().unwrap().persons.iter().filter(|person| person.team_id == team_id).map(Person::from).collect()
+    }
+
+    async fn person_team(&self, id: i64) -> Option<i64> {
+        self.tables.read().unwrap().persons.iter().find(|person| person.id == id).map(|person| person.team_id)
+    }
+
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String> {
+        let mut tables = self.tables.write().unwrap();
+        if tables.teams.iter().any(|other| other.name == team.name) { return Err(unique("teams", "name")); }
+        date(&team.creation_date)?;
+        let mut raw_team = RawTeam::from(team);
+        raw_team.id = tables.next_id("teams");
+        let id = raw_team.id;
+        tables.teams.push(raw_team);
+        Ok(id)
+    }
+
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String> {
+        let mut tables = self.tables.write().unwrap();
+        if tables.badges.iter().any(|other| other.name == badge.name) { return Err(unique("badges", "name")); }
+        if !tables.categories.iter().any(|category| category.id == badge.category) { return Err(foreign_key("badges", "category", badge.category)); }
+        let id = tables.next_id("badges");
+        tables.badges.push(RawBadge { id, name: badge.name, description: badge.description, points: badge.points, category: badge.category });
+        Ok(id)
+    }
+
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String> {
+        let mut tables = self.tables.write().unwrap();
+        if tables.labels.iter().any(|other| other.name == label.name) { return Err(unique("labels", "name")); }
+        let id = tables.next_id("labels");
+        tables.labels.push(Label { id, name: label.name });
+        Ok(id)
+    }
+
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String> {
+        let mut tables = self.tables.write().unwrap();
+        if tables.categories.iter().any(|other| other.name == category.name) { return Err(unique("badge_categories", "name")); }
+        let id = tables.next_id("badge_categories");
+        tables.categories.push(Category { id, name: category.name });
+        Ok(id)
+    }
+
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        let CreateLabelOwnership { team_id, label_id } = label_ownership;
+        if !tables.team_exists(team_id) { return Err(foreign_key("label_ownerships", "team_id", team_id)); }
+        if !tables.labels.iter().any(|label| label.id == label_id) { return Err(foreign_key("label_ownerships", "label_id", label_id)); }
+        if tables.label_ownerships.iter().any(|ownership| ownership.team_id == team_id && ownership.label_id == label_id) {
+            return Err(unique("label_ownerships", "team_id_label_id"));
+        }
+        let id = tables.next_id("label_ownerships");
+        tables.label_ownerships.push(LabelOwnership { id, team_id, label_id });
+        Ok(())
+    }
+
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        let CreateBadgeOwnership { team_id, badge_id, acquisition_date } = badge_ownership;
+        if !tables.team_exists(team_id) { return Err(foreign_key("badge_ownerships", "team_id", team_id)); }
+        if !tables.badges.iter().any(|badge| badge.id == badge_id) { return Err(foreign_key("badge_ownerships", "badge_id", badge_id)); }
+        if tables.badge_ownerships.iter().any(|ownership| ownership.team_id == team_id && ownership.badge_id == badge_id) {
+            return Err(unique("badge_ownerships", "team_id_badge_id"));
+        }
+        tables.insert_badge_ownership(team_id, badge_id, &acquisition_date)
+    }
+
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.team_exists(person.team_id) { return Err(foreign_key("persons", "team_id", person.team_id)); }
+        let id = tables.next_id("persons");
+        tables.persons.push(RawPerson {
+            id,
+            team_id: person.team_id,
+            name: person.name,
+            career: person.career,
+            graduation_date: date(&person.graduation_date)?,
+            picture_url: person.picture_url,
+            portafolio_url: person.portafolio_url,
+        });
+        Ok(())
+    }
+
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String> {
+        self.tables.write().unwrap().label_ownerships
+            .retain(|ownership| ownership.team_id != label_ownership.team_id || ownership.label_id != label_ownership.label_id);
+        Ok(())
+    }
+
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String> {
+        self.tables.write().unwrap()
+            .remove_badge_ownerships(|ownership| ownership.team_id == badge_ownership.team_id && ownership.badge_id == badge_ownership.badge_id);
+        Ok(())
+    }
+
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.badges.iter().any(|badge| badge.id == award.badge_id) { return Err(format!("BADGE {} DOES NOT EXIST", award.badge_id)); }
+        let mut missing:Vec<i64> = award.team_ids.iter().copied().filter(|id| !tables.team_exists(*id)).collect();
+        missing.sort();
+        missing.dedup();
+        if !missing.is_empty() { return Err(format!("TEAMS DO NOT EXIST: {:?}", missing)); }
+
+        let mut awarded = vec![];
+        for team_id in &award.team_ids {
+            let owned = tables.badge_ownerships.iter().any(|ownership| ownership.team_id == *team_id && ownership.badge_id == award.badge_id);
+            if owned || awarded.contains(team_id) { continue; }
+            tables.insert_badge_ownership(*team_id, award.badge_id, &award.acquisition_date)?;
+            awarded.push(*team_id);
+        }
+        awarded.sort();
+        Ok(awarded)
+    }
+
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String> {
+        let mut revoked = self.tables.write().unwrap()
+            .remove_badge_ownerships(|ownership| ownership.badge_id == revoke.badge_id && revoke.team_ids.contains(&ownership.team_id));
+        revoked.sort();
+        Ok(revoked)
+    }
+
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        match kind {
+            "label" => {
+                let used = tables.label_ownerships.iter().any(|ownership| ownership.label_id == id);
+                if used && !force { return Err(still_referenced("labels", "label_ownerships")); }
+                tables.label_ownerships.retain(|ownership| ownership.label_id != id);
+                tables.labels.retain(|label| label.id != id);
+            },
+            "badge" => {
+                let owned = tables.badge_ownerships.iter().any(|ownership| ownership.badge_id == id);
+                if owned && !force { return Err(still_referenced("badges", "badge_ownerships")); }
+                tables.remove_badge_ownerships(|ownership| ownership.badge_id == id);
+                tables.badges.retain(|badge| badge.id != id);
+            },
+            "category" => {
+                let badge_ids:Vec<i64> = tables.badges.iter().filter(|badge| badge.category == id).map(|badge| badge.id).collect();
+                if !badge_ids.is_empty() && !force { return Err(still_referenced("badge_categories", "badges")); }
+                let owned = tables.badge_ownerships.iter().any(|ownership| badge_ids.contains(&ownership.badge_id));
+                if owned && !force { return Err(still_referenced("badges", "badge_ownerships")); }
+                tables.remove_badge_ownerships(|ownership| badge_ids.contains(&ownership.badge_id));
+                tables.badges.retain(|badge| badge.category != id);
+                tables.categories.retain(|category| category.id != id);
+            },
+            "person" => tables.persons.retain(|person| person.id != id),
+            "team" => {
+                let linked = tables.label_ownerships.iter().any(|ownership| ownership.team_id == id)
+                    || tables.badge_ownerships.iter().any(|ownership| ownership.team_id == id)
+                    || tables.persons.iter().any(|person| person.team_id == id);
+                if linked && !force { return Err(still_referenced("teams", "label_ownerships")); }
+                tables.label_ownerships.retain(|ownership| ownership.team_id != id);
+                tables.remove_badge_ownerships(|ownership| ownership.team_id == id);
+                tables.persons.retain(|person| person.team_id != id);
+                tables.teams.retain(|team| team.id != id);
+            },
+            _ => return Err(String::from("TYPE IS NOT AVAILABLE FOR DELETION")),
+        }
+        Ok(())
+    }
+
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String> {
+        let mut tables = self.tables.write().unwrap();
+        match body {
+            EditBody::Team(edit) => {
+                if let Patch::Value(name) = &edit.name {
+                    if tables.teams.iter().any(|team| team.id != id && team.name == *name) { return Err(unique("teams", "name")); }
+                }
+                let creation_date = edit.creation_date.value().map(|value| date(value)).transpose()?;
+                if let Some(team) = tables.teams.iter_mut().find(|team| team.id == id) {
+                    set(&mut team.name, &edit.name);
+                    set(&mut team.description, &edit.description);
+                    set(&mut team.stage, &edit.stage);
+                    set(&mut team.location, &edit.location);
+                    set_optional(&mut team.logo_url, &edit.logo_url);
+                    set_optional(&mut team.banner_url, &edit.banner_url);
+                    if let Some(creation_date) = creation_date { team.creation_date = creation_date; }
+                }
+            },
+            EditBody::Person(edit) => {
+                if let Patch::Value(team_id) = edit.team_id {
+                    if !tables.team_exists(team_id) { return Err(foreign_key("persons", "team_id", team_id)); }
+                }
+                let graduation_date = edit.graduation_date.value().map(|value| date(value)).transpose()?;
+                if let Some(person) = tables.persons.iter_mut().find(|person| person.id == id) {
+                    set(&mut person.name, &edit.name);
+                    set(&mut person.team_id, &edit.team_id);
+                    set(&mut person.career, &edit.career);
+                    set_optional(&mut person.picture_url, &edit.picture_url);
+                    set_optional(&mut person.portafolio_url, &edit.portafolio_url);
+                    if let Some(graduation_date) = graduation_date { person.graduation_date = graduation_date; }
+                }
+            },
+            EditBody::Badge(edit) => {
+                if let Patch::Value(name) = &edit.name {
+                    if tables.badges.iter().any(|badge| badge.id != id && badge.name == *name) { return Err(unique("badges", "name")); }
+                }
+                if let Patch::Value(category) = edit.category {
+                    if !tables.categories.iter().any(|other| other.id == category) { return Err(foreign_key("badges", "category", category)); }
+                }
+                let old_points = tables.points(id);
+                if let Some(badge) = tables.badges.iter_mut().find(|badge| badge.id == id) {
+                    set(&mut badge.name, &edit.name);
+                    set(&mut badge.description, &edit.description);
+                    set(&mut badge.points, &edit.points);
+                    set(&mut badge.category, &edit.category);
+                }
+                let delta = tables.points(id) - old_points;
+                let owners:Vec<i64> = tables.badge_ownerships.iter().filter(|ownership| ownership.badge_id == id).map(|ownership| ownership.team_id).collect();
+                for team_id in owners {
+                    tables.add_points(team_id, delta);
+                }
+            },
+            EditBody::Label(edit) => {
+                if let Patch::Value(name) = &edit.name {
+                    if tables.labels.iter().any(|label| label.id != id && label.name == *name) { return Err(unique("labels", "name")); }
+                }
+                if let Some(label) = tables.labels.iter_mut().find(|label| label.id == id) {
+                    set(&mut label.name, &edit.name);
+                }
+            },
+            EditBody::Category(edit) => {
+                if let Patch::Value(name) = &edit.name {
+                    if tables.categories.iter().any(|category| category.id != id && category.name == *name) { return Err(unique("badge_categories", "name")); }
+                }
+                if let Some(category) = tables.categories.iter_mut().find(|category| category.id == id) {
+                    set(&mut category.name, &edit.name);
+                }
+            },
+        }
+        Ok(())
+    }
+
+    async fn seed(&self, sql: &str) -> Result<(), String> {
+        MemoryRepository::seed(self, sql)
+    }
+
+    async fn totals(&self) -> Result<Totals, String> {
+        let tables = self.tables.read().unwrap();
+        let today = actix_web::cookie::time::OffsetDateTime::now_utc().date();
+        Ok(Totals {
+            teams: tables.teams.len() as i64,
+            badges: tables.badges.len() as i64,
+            badge_ownerships: tables.badge_ownerships.len() as i64,
+            dated_today: tables.badge_ownerships.iter().filter(|ownership| ownership.acquisition_date == today).count() as i64,
+        })
+    }
+
+    async fn rank_teams(&self) -> Vec<RankTeam> {
+        self.tables.read().unwrap().teams.iter()
+            .map(|team| RankTeam { id: team.id, score: team.score, name: team.name.clone(), rank: team.rank })
+            .collect()
+    }
+
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]) {
+        let mut tables = self.tables.write().unwrap();
+        for (id, rank) in changes {
+            if let Some(team) = tables.teams.iter_mut().find(|team| team.id == *id) {
+                team.rank = *rank;
+            }
+        }
+    }
+
+    async fn recompute_scores(&self) {
+        let mut tables = self.tables.write().unwrap();
+        let mut scores:HashMap<i64, i64> = HashMap::new();
+        for ownership in &tables.badge_ownerships {
+            *scores.entry(ownership.team_id).or_default() += tables.points(ownership.badge_id);
+        }
+        for team in &mut tables.teams {
+            team.score = scores.get(&team.id).copied().unwrap_or(0);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use async_trait::async_trait;
+use sqlx::mysql::{MySql, MySqlPool};
+use sqlx::{Executor, QueryBuilder, Transaction};
+use crate::*;
+use super::{push_in, PoolUsage, Repository, Totals, TOTALS};
+
+// Tables created by mysql-create-tables.sql
+const TABLES: [&str; 7] = ["teams", "persons", "badges", "badge_categories", "labels", "label_ownerships", "badge_ownerships"];
+
+// The MySQL backend, for databases created with
+// `database_information/mysql-create-tables.sql`. MySQL has no score triggers,
+// so every write that moves points updates the owners' scores in the same
+// transaction, the way the Postgres triggers do.
+pub struct MySqlRepository {
+    pool: MySqlPool,
+}
+
+impl MySqlRepository {
+    pub fn new(pool: MySqlPool) -> MySqlRepository {
+        MySqlRepository { pool }
+    }
+
+    // Teams in `ids` that are not in the database
+    async fn missing_teams(&self, ids: &[i64]) -> Vec<i64> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT id FROM teams WHERE ");
+        push_in(&mut query, "id", ids);
+        let found = query.build_query_as::<RawID>()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut missing:Vec<i64> = ids.iter().copied().filter(|id| !found.iter().any(|team| team.id == *id)).collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+fn date(date: &str) -> Result<actix_web::cookie::time::Date, String> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    actix_web::cookie::time::Date::parse(date, &format).map_err(|_| format!("ERROR ADDING TO DATABASE: invalid input syntax for type date: \"{}\"", date))
+}
+
+fn error(err: sqlx::Error) -> String {
+    format!("ERROR ADDING TO DATABASE: {}", err)
+}
+
+// Takes the points of the badge ownerships matching `condition` (with one
+// `?` bound to `id`) from their teams. Has to run before they are deleted.
+async fn take_points(tx: &mut Transaction<'_, MySql>, condition: &str, id: i64) -> Result<(), sqlx::Error> {
+    let query = format!("UPDATE teams JOIN (\
+        SELECT badge_ownerships.team_id, SUM(badges.points) AS points FROM badge_ownerships \
+        JOIN badges ON badges.id = badge_ownerships.badge_id WHERE {} GROUP BY badge_ownerships.team_id\
+        ) AS lost ON lost.team_id = teams.id SET teams.score = teams.score - lost.points", condition);
+    sqlx::query(&query).bind(id).execute(tx).await?;
+    Ok(())
+}
+
+// Adds `points` to every team in `team_ids`
+async fn add_points<'c, E: Executor<'c, Database = MySql>>(executor: E, team_ids: &[i64], points: i64) -> Result<(), sqlx::Error> {
+    if team_ids.is_empty() { return Ok(()); }
+    let mut query = QueryBuilder::<MySql>::new("UPDATE teams SET score = score + ");
+    query.push_bind(points).push(" WHERE ");
+    push_in(&mut query, "id", team_ids);
+    query.build().execute(executor).await?;
+    Ok(())
+}
+
+async fn badge_points(tx: &mut Transaction<'_, MySql>, badge_id: i64) -> Result<Option<i64>, sqlx::Error> {
+    let points:Option<(i64,)> = sqlx::query_as("SELECT points FROM badges WHERE id = ?")
+        .bind(badge_id)
+        .fetch_optional(tx)
+        .await?;
+    Ok(points.map(|(points,)| points))
+}
+
+#[async_trait]
+impl Repository for MySqlRepository {
+    async fn teams(&self) -> Vec<RawTeam> {
+        sqlx::query_as::<MySql, RawTeam>("SELECT * FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team(&self, id: i64) -> Option<RawTeam> {
+        sqlx::query_as::<MySql, RawTeam>("SELECT * FROM teams WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        sqlx::query_as::<MySql, Label>("SELECT * FROM labels")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn badges(&self) -> Vec<RawBadge> {
+        sqlx::query_as::<MySql, RawBadge>("SELECT * FROM badges")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn categories(&self) -> Vec<Category> {
+        sqlx::query_as::<MySql, Category>("SELECT * FROM badge_categories")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_labels(&self, team_id: i64) -> Vec<Label> {
+        sqlx::query_as::<MySql, Label>("SELECT labels.* FROM labels JOIN label_ownerships ON label_ownerships.label_id = labels.id WHERE label_ownerships.team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge> {
+        let badge_ownerships = sqlx::query_as::<MySql, BadgeOwnership>("SELECT * FROM badge_ownerships WHERE team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut badges:Vec<OwnedBadge> = vec![];
+        for badge_ownership in badge_ownerships {
+            let raw_badge = sqlx::query_as::<MySql, RawBadge>("SELECT * FROM badges WHERE id = ?")
+                .bind(badge_ownership.badge_id)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            let category = sqlx::query_as::<MySql, Category>("SELECT * FROM badge_categories WHERE id = ?")
+                .bind(raw_badge.category)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            badges.push(OwnedBadge::from(raw_badge, category, &badge_ownership));
+        }
+        badges
+    }
+
+    async fn team_persons(&self, team_id: i64) -> Vec<Person> {
+        let raw_persons = sqlx::query_as::<MySql, RawPerson>("SELECT * FROM persons WHERE team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        raw_persons.iter().map(Person::from).collect()
+    }
+
+    async fn person_team(&self, id: i64) -> Option<i64> {
+        sqlx::query_as::<MySql, PersonTeam>("SELECT team_id FROM persons WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+            .map(|person| person.team_id)
+    }
+
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String> {
+        let raw_team = RawTeam::from(team);
+        let result = sqlx::query("INSERT INTO teams (score, stage, name, description, creation_date, location, logo_url, banner_url) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(raw_team.score)
+            .bind(raw_team.stage)
+            .bind(&raw_team.name)
+            .bind(&raw_team.description)
+            .bind(raw_team.creation_date)
+            .bind(&raw_team.location)
+            .bind(&raw_team.logo_url)
+            .bind(&raw_team.banner_url)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO badges (name, description, points, category) VALUES (?, ?, ?, ?)")
+            .bind(&badge.name)
+            .bind(&badge.description)
+            .bind(badge.points)
+            .bind(badge.category)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO labels (name) VALUES (?)")
+            .bind(&label.name)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO badge_categories (name) VALUES (?)")
+            .bind(&category.name)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_id() as i64)
+    }
+
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String> {
+        sqlx::query("INSERT INTO label_ownerships (team_id, label_id) VALUES (?, ?)")
+            .bind(label_ownership.team_id)
+            .bind(label_ownership.label_id)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        sqlx::query("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) VALUES (?, ?, ?)")
+            .bind(badge_ownership.team_id)
+            .bind(badge_ownership.badge_id)
+            .bind(date(&badge_ownership.acquisition_date)?)
+            .execute(&mut tx)
+            .await
+            .map_err(error)?;
+        let points = badge_points(&mut tx, badge_ownership.badge_id).await.map_err(error)?.unwrap_or(0);
+        add_points(&mut tx, &[badge_ownership.team_id], points).await.map_err(error)?;
+        tx.commit().await.map_err(error)
+    }
+
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String> {
+        sqlx::query("INSERT INTO persons (team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(person.team_id)
+            .bind(&person.name)
+            .bind(&person.career)
+            .bind(date(&person.graduation_date)?)
+            .bind(&person.picture_url)
+            .bind(&person.portafolio_url)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String> {
+        sqlx::query("DELETE FROM label_ownerships WHERE team_id = ? AND label_id = ?")
+            .bind(label_ownership.team_id)
+            .bind(label_ownership.label_id)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        let removed = sqlx::query("DELETE FROM badge_ownerships WHERE team_id = ? AND badge_id = ?")
+            .bind(badge_ownership.team_id)
+            .bind(badge_ownership.badge_id)
+            .execute(&mut tx)
+            .await
+            .map_err(error)?;
+        if removed.rows_affected() > 0 {
+            let points = badge_points(&mut tx, badge_ownership.badge_id).await.map_err(error)?.unwrap_or(0);
+            add_points(&mut tx, &[badge_ownership.team_id], -points).await.map_err(error)?;
+        }
+        tx.commit().await.map_err(error)
+    }
+
+    // Inserts the ownerships the teams don't have yet in one statement
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        let Some(points) = badge_points(&mut tx, award.badge_id).await.map_err(error)? else {
+            return Err(format!("BADGE {} DOES NOT EXIST", award.badge_id));
+        };
+        let missing = self.missing_teams(&award.team_ids).await;
+        if !missing.is_empty() { return Err(format!("TEAMS DO NOT EXIST: {:?}", missing)); }
+
+        let mut query = QueryBuilder::<MySql>::new("SELECT team_id FROM badge_ownerships WHERE badge_id = ");
+        query.push_bind(award.badge_id).push(" AND ");
+        push_in(&mut query, "team_id", &award.team_ids);
+        query.push(" FOR UPDATE");
+        let owners = query.build_query_as::<PersonTeam>().fetch_all(&mut tx).await.map_err(error)?;
+        let mut awarded:Vec<i64> = award.team_ids.iter().copied().filter(|id| !owners.iter().any(|owner| owner.team_id == *id)).collect();
+        awarded.sort();
+        awarded.dedup();
+        if awarded.is_empty() { return Ok(awarded); }
+
+        let acquisition_date = date(&award.acquisition_date)?;
+        let mut query = QueryBuilder::<MySql>::new("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) ");
+        query.push_values(&awarded, |mut row, team_id| {
+            row.push_bind(*team_id).push_bind(award.badge_id).push_bind(acquisition_date);
+        });
+        query.build().execute(&mut tx).await.map_err(error)?;
+        add_points(&mut tx, &awarded, points).await.map_err(error)?;
+        tx.commit().await.map_err(error)?;
+        Ok(awarded)
+    }
+
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        let mut query = QueryBuilder::<MySql>::new("SELECT team_id FROM badge_ownerships WHERE badge_id = ");
+        query.push_bind(revoke.badge_id).push(" AND ");
+        push_in(&mut query, "team_id", &revoke.team_ids);
+        query.push(" FOR UPDATE");
+        let owners = query.build_query_as::<PersonTeam>().fetch_all(&mut tx).await.map_err(error)?;
+        let mut revoked:Vec<i64> = owners.into_iter().map(|owner| owner.team_id).collect();
+        revoked.sort();
+        if revoked.is_empty() { return Ok(revoked); }
+
+        let mut query = QueryBuilder::<MySql>::new("DELETE FROM badge_ownerships WHERE badge_id = ");
+        query.push_bind(revoke.badge_id).push(" AND ");
+        push_in(&mut query, "team_id", &revoked);
+        query.build().execute(&mut tx).await.map_err(error)?;
+        let points = badge_points(&mut tx, revoke.badge_id).await.map_err(error)?.unwrap_or(0);
+        add_points(&mut tx, &revoked, -points).await.map_err(error)?;
+        tx.commit().await.map_err(error)?;
+        Ok(revoked)
+    }
+
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        let table = match kind {
+            // Delete label ownerships
+            "label" => {
+                if force {
+                    sqlx::query("DELETE FROM label_ownerships WHERE label_id = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "labels"
+            },
+
+            // Delete badge ownerships
+            "badge" => {
+                if force {
+                    take_points(&mut tx, "badge_ownerships.badge_id = ?", id).await.map_err(error)?;
+                    sqlx::query("DELETE FROM badge_ownerships WHERE badge_id = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "badges"
+            },
+
+            // Delete badges with category and badge ownerships
+            "category" => {
+                if force {
+                    take_points(&mut tx, "badges.category = ?", id).await.map_err(error)?;
+                    sqlx::query("DELETE badge_ownerships FROM badge_ownerships JOIN badges ON badges.id = badge_ownerships.badge_id WHERE badges.category = ?")
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await
+                        .map_err(error)?;
+                    sqlx::query("DELETE FROM badges WHERE category = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "badge_categories"
+            },
+
+            "person" => "persons",
+
+            // Delete badge and label ownerships and people
+            "team" => {
+                if force {
+                    for table in ["label_ownerships", "badge_ownerships", "persons"] {
+                        sqlx::query(&format!("DELETE FROM {} WHERE team_id = ?", table)).bind(id).execute(&mut tx).await.map_err(error)?;
+                    }
+                }
+                "teams"
+            },
+            _ => return Err(String::from("TYPE IS NOT AVAILABLE FOR DELETION")),
+        };
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(error)?;
+        tx.commit().await.map_err(error)
+    }
+
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        // New points move the owners' scores by the difference
+        let mut old_points = None;
+        if let EditBody::Badge(badge) = &body {
+            if badge.points.value().is_some() {
+                old_points = badge_points(&mut tx, id).await.map_err(error)?;
+            }
+        }
+        body.update()?.query::<MySql>(id)
+            .build()
+            .execute(&mut tx)
+            .await
+            .map_err(error)?;
+        if let Some(old_points) = old_points {
+            let points = badge_points(&mut tx, id).await.map_err(error)?.unwrap_or(old_points);
+            sqlx::query("UPDATE teams JOIN badge_ownerships ON badge_ownerships.team_id = teams.id SET teams.score = teams.score + ? WHERE badge_ownerships.badge_id = ?")
+                .bind(points - old_points)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .map_err(error)?;
+        }
+        tx.commit().await.map_err(error)
+    }
+
+    async fn seed(&self, sql: &str) -> Result<(), String> {
+        sqlx::Executor::execute(&self.pool, sql).await.map_err(error)?;
+        Ok(())
+    }
+
+    async fn ready(&self) -> Result<(), String> {
+        let mut query = QueryBuilder::<MySql>::new("SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name IN (");
+        let mut separated = query.separated(", ");
+        for table in TABLES {
+            separated.push_bind(table);
+        }
+        query.push(")");
+        let tables:(i64,) = query.build_query_as()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| format!("DATABASE IS NOT REACHABLE: {}", err))?;
+        if tables.0 < TABLES.len() as i64 { return Err(String::from("TABLES ARE MISSING, RUN mysql-create-tables.sql")); }
+        Ok(())
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        Some(PoolUsage { size: self.pool.size(), idle: self.pool.num_idle() })
+    }
+
+    async fn totals(&self) -> Result<Totals, String> {
+        sqlx::query_as::<MySql, Totals>(TOTALS)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    async fn rank_teams(&self) -> Vec<RankTeam> {
+        sqlx::query_as::<MySql, RankTeam>("SELECT id, score, name, `rank` FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    // One UPDATE with a CASE for every changed rank
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]) {
+        if changes.is_empty() { return; }
+        let mut query = QueryBuilder::<MySql>::new("UPDATE teams SET `rank` = CASE id");
+        for (id, rank) in changes {
+            query.push(" WHEN ").push_bind(*id).push(" THEN ").push_bind(*rank);
+        }
+        query.push(" END WHERE ");
+        let ids:Vec<i64> = changes.iter().map(|(id, _)| *id).collect();
+        push_in(&mut query, "id", &ids);
+        query.build().execute(&self.pool).await.unwrap();
+    }
+
+    async fn recompute_scores(&self) {
+        sqlx::query("UPDATE teams SET score = (\
+            SELECT COALESCE(SUM(badges.points), 0) FROM badge_ownerships \
+            JOIN badges ON badges.id = badge_ownerships.badge_id WHERE badge_ownerships.team_id = teams.id)")
+            .execute(&self.pool)
+            .await
+            .unwrap();
+    }
+}
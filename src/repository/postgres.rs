@@ -0,0 +1,426 @@
+use async_trait::async_trait;
+use futures::future;
+use sqlx::postgres::{PgPool, Postgres};
+use crate::*;
+use super::{PoolUsage, Repository, Totals, TOTALS};
+
+// The production backend. Scores are kept by the triggers in
+// `database_information/psql-score-triggers.sql`.
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> PgRepository {
+        PgRepository { pool }
+    }
+
+    // Teams in `ids` that are not in the database
+    async fn missing_teams(&self, ids: &[i64]) -> Vec<i64> {
+        let found = sqlx::query_as::<Postgres, RawID>("SELECT id FROM teams WHERE id = ANY($1)")
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut missing:Vec<i64> = ids.iter().copied().filter(|id| !found.iter().any(|team| team.id == *id)).collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    async fn delete_secondaries(&self, table: &str, field: &str, id: i64) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+        let query = format!("DELETE FROM {} WHERE {} = $1", table, field);
+
+        sqlx::query(&query)
+        .bind(id)
+        .execute(&self.pool)
+        .await
+    }
+}
+
+fn date(date: &str) -> Result<actix_web::cookie::time::Date, String> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    actix_web::cookie::time::Date::parse(date, &format).map_err(|_| format!("ERROR ADDING TO DATABASE: invalid input syntax for type date: \"{}\"", date))
+}
+
+#[async_trait]
+impl Repository for PgRepository {
+    fn pool(&self) -> Option<&PgPool> {
+        Some(&self.pool)
+    }
+
+    async fn teams(&self) -> Vec<RawTeam> {
+        sqlx::query_as::<Postgres, RawTeam>("SELECT * FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team(&self, id: i64) -> Option<RawTeam> {
+        sqlx::query_as::<Postgres, RawTeam>("SELECT * FROM teams WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        sqlx::query_as::<Postgres, Label>("SELECT * FROM labels")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn badges(&self) -> Vec<RawBadge> {
+        sqlx::query_as::<Postgres, RawBadge>("SELECT * FROM badges")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn categories(&self) -> Vec<Category> {
+        sqlx::query_as::<Postgres, Category>("SELECT * FROM badge_categories")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_labels(&self, team_id: i64) -> Vec<Label> {
+        let label_ownerships = sqlx::query_as::<Postgres, LabelOwnership>("SELECT * FROM label_ownerships WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        if label_ownerships.is_empty()  { return vec![] };
+        let label_id_values:Vec<i64> = label_ownerships.iter().map(|l| l.label_id).collect();
+        sqlx::query_as::<Postgres, Label>("SELECT * FROM labels WHERE id = ANY($1)")
+            .bind(&label_id_values[..])
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge> {
+        let badge_ownerships = sqlx::query_as::<Postgres, BadgeOwnership>("SELECT * FROM badge_ownerships WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut badges:Vec<OwnedBadge> = vec![];
+        for badge_ownership in badge_ownerships {
+            let raw_badge = sqlx::query_as::<Postgres, RawBadge>("SELECT * FROM badges WHERE id = $1")
+                .bind(badge_ownership.badge_id)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            let category = sqlx::query_as::<Postgres, Category>("SELECT * FROM badge_categories WHERE id = $1")
+                .bind(raw_badge.category)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            badges.push(OwnedBadge::from(raw_badge, category, &badge_ownership));
+        }
+        badges
+    }
+
+    async fn team_persons(&self, team_id: i64) -> Vec<Person> {
+        let raw_persons = sqlx::query_as::<Postgres, RawPerson>("SELECT * FROM persons WHERE team_id = $1")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        raw_persons.iter().map(Person::from).collect()
+    }
+
+    async fn person_team(&self, id: i64) -> Option<i64> {
+        sqlx::query_as::<Postgres, PersonTeam>("SELECT team_id FROM persons WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+            .map(|person| person.team_id)
+    }
+
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String> {
+        let raw_team = RawTeam::from(team);
+
+        if let Err(err) = sqlx::query("INSERT INTO teams (score, stage, name, description, creation_date, location, logo_url, banner_url) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+        .bind(raw_team.score)
+        .bind(raw_team.stage)
+        .bind(&raw_team.name)
+        .bind(&raw_team.description)
+        .bind(raw_team.creation_date)
+        .bind(&raw_team.location)
+        .bind(&raw_team.logo_url)
+        .bind(&raw_team.banner_url)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+
+        let id:i64 = sqlx::query_as::<Postgres, RawID>("SELECT id FROM teams WHERE name = $1")
+        .bind(&raw_team.name)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+        .id;
+        Ok(id)
+    }
+
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String> {
+        if let Err(err) = sqlx::query("INSERT INTO badges (name, description, points, category) VALUES ($1, $2, $3, $4)")
+        .bind(&badge.name)
+        .bind(&badge.description)
+        .bind(badge.points)
+        .bind(badge.category)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+
+        let id:i64 = sqlx::query_as::<Postgres, RawID>("SELECT id FROM badges WHERE name = $1")
+        .bind(&badge.name)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+        .id;
+        Ok(id)
+    }
+
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String> {
+        if let Err(err) = sqlx::query("INSERT INTO labels (name) VALUES ($1)")
+        .bind(&label.name)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+
+        let id:i64 = sqlx::query_as::<Postgres, RawID>("SELECT id FROM labels WHERE name = $1")
+        .bind(&label.name)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+        .id;
+        Ok(id)
+    }
+
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String> {
+        if let Err(err) = sqlx::query("INSERT INTO badge_categories (name) VALUES ($1)")
+        .bind(&category.name)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+
+        let id:i64 = sqlx::query_as::<Postgres, RawID>("SELECT id FROM badge_categories WHERE name = $1")
+        .bind(&category.name)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+        .id;
+        Ok(id)
+    }
+
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String> {
+        if let Err(err) = sqlx::query("INSERT INTO label_ownerships (team_id, label_id) VALUES ($1, $2)")
+        .bind(label_ownership.team_id)
+        .bind(label_ownership.label_id)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String> {
+        if let Err(err) = sqlx::query("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) VALUES ($1, $2, $3)")
+        .bind(badge_ownership.team_id)
+        .bind(badge_ownership.badge_id)
+        .bind(date(&badge_ownership.acquisition_date)?)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String> {
+        if let Err(err) = sqlx::query("INSERT INTO persons (team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES ($1, $2, $3, $4, $5, $6)")
+        .bind(person.team_id)
+        .bind(&person.name)
+        .bind(&person.career)
+        .bind(date(&person.graduation_date)?)
+        .bind(&person.picture_url)
+        .bind(&person.portafolio_url)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String> {
+        if let Err(err) = sqlx::query("DELETE FROM label_ownerships WHERE team_id = $1 AND label_id = $2")
+        .bind(label_ownership.team_id)
+        .bind(label_ownership.label_id)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String> {
+        if let Err(err) = sqlx::query("DELETE FROM badge_ownerships WHERE team_id = $1 AND badge_id = $2")
+        .bind(badge_ownership.team_id)
+        .bind(badge_ownership.badge_id)
+        .execute(&self.pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    // Inserts every ownership in one statement, skipping the teams that already have the badge
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String> {
+        let badge = sqlx::query_as::<Postgres, RawID>("SELECT id FROM badges WHERE id = $1")
+            .bind(award.badge_id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap();
+        if badge.is_none() { return Err(format!("BADGE {} DOES NOT EXIST", award.badge_id)); }
+        let missing = self.missing_teams(&award.team_ids).await;
+        if !missing.is_empty() { return Err(format!("TEAMS DO NOT EXIST: {:?}", missing)); }
+
+        let awarded = sqlx::query_as::<Postgres, PersonTeam>("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) SELECT DISTINCT UNNEST($1::BIGINT[]), $2, $3 ON CONFLICT (team_id, badge_id) DO NOTHING RETURNING team_id")
+            .bind(&award.team_ids)
+            .bind(award.badge_id)
+            .bind(date(&award.acquisition_date)?)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("ERROR ADDING TO DATABASE: {}", err))?;
+        let mut awarded:Vec<i64> = awarded.into_iter().map(|team| team.team_id).collect();
+        awarded.sort();
+        Ok(awarded)
+    }
+
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String> {
+        let revoked = sqlx::query_as::<Postgres, PersonTeam>("DELETE FROM badge_ownerships WHERE badge_id = $1 AND team_id = ANY($2) RETURNING team_id")
+            .bind(revoke.badge_id)
+            .bind(&revoke.team_ids)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| format!("ERROR ADDING TO DATABASE: {}", err))?;
+        let mut revoked:Vec<i64> = revoked.into_iter().map(|team| team.team_id).collect();
+        revoked.sort();
+        Ok(revoked)
+    }
+
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String> {
+        let pool = &self.pool;
+        let query = format!("DELETE FROM {} WHERE id = $1", match kind {
+            // Delete label ownerships
+            "label" => {
+                if force { self.delete_secondaries("label_ownerships", "label_id", id).await.unwrap(); }
+                "labels"
+            },
+
+            // Delete badge ownerships
+            "badge" => {
+                if force { self.delete_secondaries("badge_ownerships", "badge_id", id).await.unwrap(); }
+                "badges"
+            },
+
+            // delete badges with category and badge ownerhips
+            "category" => {
+                if force {
+                    let badge_ids = sqlx::query_as::<Postgres, RawID>("SELECT id FROM badges WHERE category = $1")
+                        .bind(id)
+                        .fetch_all(pool)
+                        .await
+                        .unwrap();
+                    let badge_ids:Vec<i64> = badge_ids.iter().map(|b| b.id).collect();
+                    sqlx::query("DELETE FROM badge_ownerships WHERE badge_id = ANY($1)")
+                        .bind(&badge_ids[..])
+                        .execute(pool)
+                        .await
+                        .unwrap();
+                    sqlx::query("DELETE FROM badges WHERE category = $1")
+                        .bind(id)
+                        .execute(pool)
+                        .await
+                        .unwrap();
+                }
+                "badge_categories"
+            },
+
+            "person" => "persons",
+
+            // Delete badge and label ownerhips and people
+            "team" => {
+                if force {
+                    let links = vec![
+                        self.delete_secondaries("label_ownerships", "team_id", id),
+                        self.delete_secondaries("badge_ownerships", "team_id", id),
+                        self.delete_secondaries("persons", "team_id", id),
+                    ];
+                    let links = future::join_all(links).await;
+                    for res in  links {
+                        res.unwrap();
+                    }
+                }
+                "teams"
+            },
+            _ => return Err(String::from("TYPE IS NOT AVAILABLE FOR DELETION")),
+        });
+        if let Err(err) = sqlx::query(&query)
+        .bind(id)
+        .execute(pool)
+        .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String> {
+        if let Err(err) = body.update()?.query::<Postgres>(id)
+            .build()
+            .execute(&self.pool)
+            .await
+        { return Err(format!("ERROR ADDING TO DATABASE: {}", err)) }
+        Ok(())
+    }
+
+    async fn seed(&self, sql: &str) -> Result<(), String> {
+        match sqlx::Executor::execute(&self.pool, sql).await {
+            Ok(_) => Ok(()),
+            Err(err) => Err(format!("ERROR ADDING TO DATABASE: {}", err)),
+        }
+    }
+
+    // The tables come from psql-create-tables.sql, the triggers are installed on start
+    async fn ready(&self) -> Result<(), String> {
+        let triggers:(i64,) = sqlx::query_as("SELECT COUNT(*) FROM pg_trigger WHERE tgname IN ('badge_ownerships_score', 'badges_score') AND NOT tgisinternal")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| format!("DATABASE IS NOT REACHABLE: {}", err))?;
+        if triggers.0 < 2 { return Err(String::from("SCORE TRIGGERS ARE NOT INSTALLED")); }
+        Ok(())
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        Some(PoolUsage { size: self.pool.size(), idle: self.pool.num_idle() })
+    }
+
+    async fn totals(&self) -> Result<Totals, String> {
+        sqlx::query_as::<Postgres, Totals>(TOTALS)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    async fn rank_teams(&self) -> Vec<RankTeam> {
+        sqlx::query_as::<Postgres, RankTeam>("SELECT id, score, name, rank FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]) {
+        ranking::write_ranks(&self.pool, changes).await.unwrap();
+    }
+
+    async fn recompute_scores(&self) {
+        update_scores(self.pool.clone()).await;
+    }
+}
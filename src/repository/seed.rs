@@ -0,0 +1,122 @@
+// Reads the `INSERT INTO table (columns) VALUES (values);` statements of the
+// seed files in `database_information`, so they can be loaded without
+// Postgres. Anything else in the file is an error.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Number(i64),
+    Null,
+}
+
+#[derive(Debug)]
+pub struct Insert {
+    pub table: String,
+    pub row: Vec<(String, Value)>,
+}
+
+impl Insert {
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.row.iter().find(|(name, _)| name == column).map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(char),
+}
+
+fn tokens(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' { break; }
+                }
+            },
+            '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => { chars.next(); text.push('\''); },
+                        Some('\'') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(String::from("UNTERMINATED STRING")),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            },
+            '(' | ')' | ',' | ';' => tokens.push(Token::Symbol(c)),
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "(),;'".contains(c) { break; }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+// A parenthesized, comma separated list
+fn list(tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>) -> Result<Vec<Token>, String> {
+    if tokens.next() != Some(Token::Symbol('(')) { return Err(String::from("EXPECTED (")); }
+    let mut items = vec![];
+    loop {
+        match tokens.next() {
+            Some(Token::Symbol(_)) | None => return Err(String::from("EXPECTED A VALUE")),
+            Some(item) => items.push(item),
+        }
+        match tokens.next() {
+            Some(Token::Symbol(',')) => {},
+            Some(Token::Symbol(')')) => return Ok(items),
+            _ => return Err(String::from("EXPECTED , OR )")),
+        }
+    }
+}
+
+fn keyword(tokens: &mut std::iter::Peekable<std::vec::IntoIter<Token>>, expected: &str) -> Result<(), String> {
+    match tokens.next() {
+        Some(Token::Word(word)) if word.eq_ignore_ascii_case(expected) => Ok(()),
+        _ => Err(format!("EXPECTED {}", expected)),
+    }
+}
+
+pub fn inserts(sql: &str) -> Result<Vec<Insert>, String> {
+    let mut tokens = tokens(sql)?.into_iter().peekable();
+    let mut inserts = vec![];
+    while tokens.peek().is_some() {
+        keyword(&mut tokens, "INSERT")?;
+        keyword(&mut tokens, "INTO")?;
+        let table = match tokens.next() {
+            Some(Token::Word(table)) => table,
+            _ => return Err(String::from("EXPECTED A TABLE")),
+        };
+        let columns = list(&mut tokens)?;
+        keyword(&mut tokens, "VALUES")?;
+        let values = list(&mut tokens)?;
+        if columns.len() != values.len() { return Err(format!("{} COLUMNS AND {} VALUES IN {}", columns.len(), values.len(), table)); }
+        let mut row = vec![];
+        for (column, value) in columns.into_iter().zip(values) {
+            let Token::Word(column) = column else { return Err(String::from("EXPECTED A COLUMN")) };
+            let value = match value {
+                Token::Text(text) => Value::Text(text),
+                Token::Word(word) if word.eq_ignore_ascii_case("NULL") => Value::Null,
+                Token::Word(word) => Value::Number(word.parse().map_err(|_| format!("INVALID VALUE {}", word))?),
+                Token::Symbol(_) => unreachable!(),
+            };
+            row.push((column, value));
+        }
+        if tokens.next() != Some(Token::Symbol(';')) { return Err(String::from("EXPECTED ;")); }
+        inserts.push(Insert { table, row });
+    }
+    Ok(inserts)
+}
@@ -0,0 +1,406 @@
+use std::str::FromStr;
+use async_trait::async_trait;
+use sqlx::migrate::Migrator;
+use sqlx::pool::PoolOptions;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePool};
+use sqlx::QueryBuilder;
+use crate::*;
+use super::{push_in, PoolUsage, Repository, Totals, TOTALS};
+
+// A single file backend for running the leaderboard on a laptop. The tables
+// and the score triggers come from `database_information/sqlite-migrations`,
+// dates are stored as YYYY-MM-DD text.
+static MIGRATOR: Migrator = sqlx::migrate!("./database_information/sqlite-migrations");
+
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    pub fn new(pool: SqlitePool) -> SqliteRepository {
+        SqliteRepository { pool }
+    }
+
+    // Opens the file in `database_url`, creating it when missing, and brings
+    // its tables up to date
+    pub async fn connect(database_url: &str, pool_options: PoolOptions<Sqlite>) -> Result<SqliteRepository, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let pool = pool_options.connect_with(options).await?;
+        MIGRATOR.run(&pool).await?;
+        Ok(SqliteRepository::new(pool))
+    }
+
+    // Teams in `ids` that are not in the database
+    async fn missing_teams(&self, ids: &[i64]) -> Vec<i64> {
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT id FROM teams WHERE ");
+        push_in(&mut query, "id", ids);
+        let found = query.build_query_as::<RawID>()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut missing:Vec<i64> = ids.iter().copied().filter(|id| !found.iter().any(|team| team.id == *id)).collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+fn date(date: &str) -> Result<actix_web::cookie::time::Date, String> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    actix_web::cookie::time::Date::parse(date, &format).map_err(|_| format!("ERROR ADDING TO DATABASE: invalid input syntax for type date: \"{}\"", date))
+}
+
+fn error(err: sqlx::Error) -> String {
+    format!("ERROR ADDING TO DATABASE: {}", err)
+}
+
+#[async_trait]
+impl Repository for SqliteRepository {
+    async fn teams(&self) -> Vec<RawTeam> {
+        sqlx::query_as::<Sqlite, RawTeam>("SELECT * FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team(&self, id: i64) -> Option<RawTeam> {
+        sqlx::query_as::<Sqlite, RawTeam>("SELECT * FROM teams WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn labels(&self) -> Vec<Label> {
+        sqlx::query_as::<Sqlite, Label>("SELECT * FROM labels")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn badges(&self) -> Vec<RawBadge> {
+        sqlx::query_as::<Sqlite, RawBadge>("SELECT * FROM badges")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn categories(&self) -> Vec<Category> {
+        sqlx::query_as::<Sqlite, Category>("SELECT * FROM badge_categories")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_labels(&self, team_id: i64) -> Vec<Label> {
+        sqlx::query_as::<Sqlite, Label>("SELECT labels.* FROM labels JOIN label_ownerships ON label_ownerships.label_id = labels.id WHERE label_ownerships.team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge> {
+        let badge_ownerships = sqlx::query_as::<Sqlite, BadgeOwnership>("SELECT * FROM badge_ownerships WHERE team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        let mut badges:Vec<OwnedBadge> = vec![];
+        for badge_ownership in badge_ownerships {
+            let raw_badge = sqlx::query_as::<Sqlite, RawBadge>("SELECT * FROM badges WHERE id = ?")
+                .bind(badge_ownership.badge_id)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            let category = sqlx::query_as::<Sqlite, Category>("SELECT * FROM badge_categories WHERE id = ?")
+                .bind(raw_badge.category)
+                .fetch_one(&self.pool)
+                .await
+                .unwrap();
+            badges.push(OwnedBadge::from(raw_badge, category, &badge_ownership));
+        }
+        badges
+    }
+
+    async fn team_persons(&self, team_id: i64) -> Vec<Person> {
+        let raw_persons = sqlx::query_as::<Sqlite, RawPerson>("SELECT * FROM persons WHERE team_id = ?")
+            .bind(team_id)
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+        raw_persons.iter().map(Person::from).collect()
+    }
+
+    async fn person_team(&self, id: i64) -> Option<i64> {
+        sqlx::query_as::<Sqlite, PersonTeam>("SELECT team_id FROM persons WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap()
+            .map(|person| person.team_id)
+    }
+
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String> {
+        let raw_team = RawTeam::from(team);
+        let result = sqlx::query("INSERT INTO teams (score, stage, name, description, creation_date, location, logo_url, banner_url) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(raw_team.score)
+            .bind(raw_team.stage)
+            .bind(&raw_team.name)
+            .bind(&raw_team.description)
+            .bind(raw_team.creation_date)
+            .bind(&raw_team.location)
+            .bind(&raw_team.logo_url)
+            .bind(&raw_team.banner_url)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_rowid())
+    }
+
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO badges (name, description, points, category) VALUES (?, ?, ?, ?)")
+            .bind(&badge.name)
+            .bind(&badge.description)
+            .bind(badge.points)
+            .bind(badge.category)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_rowid())
+    }
+
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO labels (name) VALUES (?)")
+            .bind(&label.name)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_rowid())
+    }
+
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String> {
+        let result = sqlx::query("INSERT INTO badge_categories (name) VALUES (?)")
+            .bind(&category.name)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(result.last_insert_rowid())
+    }
+
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String> {
+        sqlx::query("INSERT INTO label_ownerships (team_id, label_id) VALUES (?, ?)")
+            .bind(label_ownership.team_id)
+            .bind(label_ownership.label_id)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String> {
+        sqlx::query("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) VALUES (?, ?, ?)")
+            .bind(badge_ownership.team_id)
+            .bind(badge_ownership.badge_id)
+            .bind(date(&badge_ownership.acquisition_date)?)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String> {
+        sqlx::query("INSERT INTO persons (team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(person.team_id)
+            .bind(&person.name)
+            .bind(&person.career)
+            .bind(date(&person.graduation_date)?)
+            .bind(&person.picture_url)
+            .bind(&person.portafolio_url)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String> {
+        sqlx::query("DELETE FROM label_ownerships WHERE team_id = ? AND label_id = ?")
+            .bind(label_ownership.team_id)
+            .bind(label_ownership.label_id)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String> {
+        sqlx::query("DELETE FROM badge_ownerships WHERE team_id = ? AND badge_id = ?")
+            .bind(badge_ownership.team_id)
+            .bind(badge_ownership.badge_id)
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    // Inserts every ownership in one statement, skipping the teams that already have the badge
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String> {
+        let badge = sqlx::query_as::<Sqlite, RawID>("SELECT id FROM badges WHERE id = ?")
+            .bind(award.badge_id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap();
+        if badge.is_none() { return Err(format!("BADGE {} DOES NOT EXIST", award.badge_id)); }
+        let missing = self.missing_teams(&award.team_ids).await;
+        if !missing.is_empty() { return Err(format!("TEAMS DO NOT EXIST: {:?}", missing)); }
+
+        let mut team_ids = award.team_ids.clone();
+        team_ids.sort();
+        team_ids.dedup();
+        let acquisition_date = date(&award.acquisition_date)?;
+        let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) ");
+        query.push_values(&team_ids, |mut row, team_id| {
+            row.push_bind(*team_id).push_bind(award.badge_id).push_bind(acquisition_date);
+        });
+        query.push(" ON CONFLICT (team_id, badge_id) DO NOTHING RETURNING team_id");
+        let awarded = query.build_query_as::<PersonTeam>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(error)?;
+        let mut awarded:Vec<i64> = awarded.into_iter().map(|team| team.team_id).collect();
+        awarded.sort();
+        Ok(awarded)
+    }
+
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String> {
+        let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM badge_ownerships WHERE badge_id = ");
+        query.push_bind(revoke.badge_id).push(" AND ");
+        push_in(&mut query, "team_id", &revoke.team_ids);
+        query.push(" RETURNING team_id");
+        let revoked = query.build_query_as::<PersonTeam>()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(error)?;
+        let mut revoked:Vec<i64> = revoked.into_iter().map(|team| team.team_id).collect();
+        revoked.sort();
+        Ok(revoked)
+    }
+
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String> {
+        let mut tx = self.pool.begin().await.map_err(error)?;
+        let table = match kind {
+            // Delete label ownerships
+            "label" => {
+                if force {
+                    sqlx::query("DELETE FROM label_ownerships WHERE label_id = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "labels"
+            },
+
+            // Delete badge ownerships
+            "badge" => {
+                if force {
+                    sqlx::query("DELETE FROM badge_ownerships WHERE badge_id = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "badges"
+            },
+
+            // Delete badges with category and badge ownerships
+            "category" => {
+                if force {
+                    sqlx::query("DELETE FROM badge_ownerships WHERE badge_id IN (SELECT id FROM badges WHERE category = ?)")
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await
+                        .map_err(error)?;
+                    sqlx::query("DELETE FROM badges WHERE category = ?").bind(id).execute(&mut tx).await.map_err(error)?;
+                }
+                "badge_categories"
+            },
+
+            "person" => "persons",
+
+            // Delete badge and label ownerships and people
+            "team" => {
+                if force {
+                    for table in ["label_ownerships", "badge_ownerships", "persons"] {
+                        sqlx::query(&format!("DELETE FROM {} WHERE team_id = ?", table)).bind(id).execute(&mut tx).await.map_err(error)?;
+                    }
+                }
+                "teams"
+            },
+            _ => return Err(String::from("TYPE IS NOT AVAILABLE FOR DELETION")),
+        };
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table))
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .map_err(error)?;
+        tx.commit().await.map_err(error)
+    }
+
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String> {
+        body.update()?.query::<Sqlite>(id)
+            .build()
+            .execute(&self.pool)
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    async fn seed(&self, sql: &str) -> Result<(), String> {
+        sqlx::Executor::execute(&self.pool, sql).await.map_err(error)?;
+        Ok(())
+    }
+
+    async fn ready(&self) -> Result<(), String> {
+        let applied:(i64,) = sqlx::query_as("SELECT COUNT(*) FROM _sqlx_migrations WHERE success")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| format!("DATABASE IS NOT REACHABLE: {}", err))?;
+        if applied.0 < MIGRATOR.iter().count() as i64 { return Err(String::from("MIGRATIONS ARE NOT APPLIED")); }
+        Ok(())
+    }
+
+    fn pool_usage(&self) -> Option<PoolUsage> {
+        Some(PoolUsage { size: self.pool.size(), idle: self.pool.num_idle() })
+    }
+
+    async fn totals(&self) -> Result<Totals, String> {
+        sqlx::query_as::<Sqlite, Totals>(TOTALS)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    async fn rank_teams(&self) -> Vec<RankTeam> {
+        sqlx::query_as::<Sqlite, RankTeam>("SELECT id, score, name, rank FROM teams")
+            .fetch_all(&self.pool)
+            .await
+            .unwrap()
+    }
+
+    // One UPDATE with a CASE for every changed rank
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]) {
+        if changes.is_empty() { return; }
+        let mut query = QueryBuilder::<Sqlite>::new("UPDATE teams SET rank = CASE id");
+        for (id, rank) in changes {
+            query.push(" WHEN ").push_bind(*id).push(" THEN ").push_bind(*rank);
+        }
+        query.push(" END WHERE ");
+        let ids:Vec<i64> = changes.iter().map(|(id, _)| *id).collect();
+        push_in(&mut query, "id", &ids);
+        query.build().execute(&self.pool).await.unwrap();
+    }
+
+    async fn recompute_scores(&self) {
+        sqlx::query("UPDATE teams SET score = (\
+            SELECT COALESCE(SUM(badges.points), 0) FROM badge_ownerships \
+            JOIN badges ON badges.id = badge_ownerships.badge_id WHERE badge_ownerships.team_id = teams.id)")
+            .execute(&self.pool)
+            .await
+            .unwrap();
+    }
+}
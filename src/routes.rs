@@ -0,0 +1,848 @@
+use actix_web::{get, patch, post, web, HttpRequest, HttpResponse, Responder};
+// The HTTP handlers. `configure` registers all of them, the server and the
+// integration tests build their `App` with it.
+
+use crate::*;
+use crate::import::{ImportFiles, ImportReport, RowError};
+use crate::backup::{Backup, RestoreReport};
+use crate::search::{SearchQuery, SearchResult};
+use crate::cache::CacheStats;
+use crate::integrity::IntegrityReport;
+use crate::health::{self, Phase, Readiness, Version};
+use crate::view::{TeamQuery, TeamView};
+use crate::validation::{self, ValidationFailure};
+use crate::team_patch::{self, PatchError};
+use crate::idempotency::{self, idempotent};
+use actix_web::middleware::from_fn;
+use utoipa::OpenApi;
+use utoipa_redoc::{Redoc, Servable};
+use async_graphql::http::GraphiQLSource;
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use futures::*;
+
+#[utoipa::path(
+    params(TeamQuery),
+    responses(
+        (status = 200, description = "Teams sorted by score, with only the requested fields", body = Vec<Team>),
+        (status = 400, description = "Unknown field or include", body = String),
+    )
+)]
+#[get("/teams")]
+async fn teams(db: web::Data<AppState>, query: web::Query<TeamQuery>) -> impl Responder {
+    let view = match TeamView::from(&query) {
+        Ok(view) => view,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    let select = |teams: &[Team]| teams.iter().map(|team| view.select(team)).collect::<Vec<_>>();
+    if let Some(teams) = db.cache.teams() {
+        return HttpResponse::Ok().json(select(&teams))
+    }
+    let generation = db.cache.generation();
+    let raw_teams = db.repo.teams().await;
+
+    let mut teams = vec![];
+    for raw_team in raw_teams {
+        let team =  Team::load(raw_team, db.repo.as_ref(), &view);
+        teams.push(team);
+    }
+    let mut teams = future::join_all(teams).await;
+    Team::sort(&mut teams);
+    // Partial teams can't answer other views
+    if view.is_full() {
+        db.cache.store_teams(&teams, generation);
+    }
+    HttpResponse::Ok().json(select(&teams))
+}
+
+#[utoipa::path(responses((status = 200, description = "All labels", body = Vec<Label>)))]
+#[get("/labels")]
+async fn labels(db: web::Data<AppState>) -> impl Responder {
+    let labels = db.repo.labels().await;
+
+    HttpResponse::Ok().json(labels)
+}
+
+#[utoipa::path(responses((status = 200, description = "All badges", body = Vec<RawBadge>)))]
+#[get("/badges")]
+async fn badges(db: web::Data<AppState>) -> impl Responder {
+    let badges = db.repo.badges().await;
+
+    HttpResponse::Ok().json(badges)
+}
+
+#[utoipa::path(responses((status = 200, description = "All categories", body = Vec<Category>)))]
+#[get("/categories")]
+async fn categories(db: web::Data<AppState>) -> impl Responder {
+    let categories = db.repo.categories().await;
+
+    HttpResponse::Ok().json(categories)
+}
+
+#[utoipa::path(
+    params(("id" = i64, Path, description = "Team id"), TeamQuery),
+    responses(
+        (status = 200, description = "Team with `id`, with only the requested fields", body = Team),
+        (status = 400, description = "Unknown field or include", body = String),
+        (status = 404, description = "ID does not exist", body = String),
+    )
+)]
+#[get("/team/{id}")]
+async fn team_id(db: web::Data<AppState>, key: web::Path<i64>, query: web::Query<TeamQuery>) -> impl Responder {
+    let id = key.into_inner();
+    let view = match TeamView::from(&query) {
+        Ok(view) => view,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+    if let Some(team) = db.cache.team(id) {
+        return HttpResponse::Ok().json(view.select(&team))
+    }
+    let generation = db.cache.generation();
+    if let Some(raw_team) = db.repo.team(id).await {
+        let team =  Team::load(raw_team, db.repo.as_ref(), &view).await;
+        if view.is_full() {
+            db.cache.store_team(&team, generation);
+        }
+        return HttpResponse::Ok().json(view.select(&team))
+    };
+    HttpResponse::NotFound().json("ID does not exist")
+}
+
+// For the features that only run on Postgres
+fn unsupported() -> HttpResponse {
+    HttpResponse::NotImplemented().json(repository::UNSUPPORTED)
+}
+
+fn invalid(failure: ValidationFailure) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(failure)
+}
+
+#[utoipa::path(
+    request_body = CreateTeam,
+    responses(
+        (status = 200, description = "Id of the new team", body = i64),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/create/team", wrap = "from_fn(idempotent)")]
+async fn team_create(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let raw_team = String::from_utf8(bytes.to_vec()).unwrap();
+    let raw_team = serde_json::from_str(&raw_team);
+    let raw_team:CreateTeam = match raw_team {
+        Ok(raw_team) => raw_team,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&raw_team) { return invalid(failure) }
+
+    match operations::create_team(&db, raw_team).await {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    params(("id" = i64, Path, description = "Team id")),
+    request_body(content = Vec<Object>, content_type = "application/json-patch+json", description = "RFC 6902 operations on the team as returned by `/team/{id}`"),
+    responses(
+        (status = 200, description = "Patched team", body = Team),
+        (status = 400, description = "Invalid JSON, read only field or database error", body = String),
+        (status = 404, description = "ID does not exist", body = String),
+        (status = 409, description = "The operations can't be applied, nothing was changed", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[patch("/teams/{id}")]
+async fn patch_team(db: web::Data<AppState>, key: web::Path<i64>, bytes: web::Bytes) -> impl Responder {
+    let operations:json_patch::Patch = match serde_json::from_slice(&bytes) {
+        Ok(operations) => operations,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+    match team_patch::apply(&db, key.into_inner(), operations).await {
+        Ok(team) => HttpResponse::Ok().json(team),
+        Err(PatchError::NotFound) => HttpResponse::NotFound().json("ID does not exist"),
+        Err(PatchError::Conflict(err)) => HttpResponse::Conflict().json(err),
+        Err(PatchError::Validation(failure)) => invalid(failure),
+        Err(PatchError::Invalid(err)) | Err(PatchError::Database(err)) => HttpResponse::BadRequest().json(err),
+        Err(PatchError::Unsupported) => unsupported(),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateLabelOwnership,
+    responses(
+        (status = 200, description = "Label added", body = String),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+    )
+)]
+#[post("/add/label", wrap = "from_fn(idempotent)")]
+async fn add_label(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let label_ownership = String::from_utf8(bytes.to_vec()).unwrap();
+    let label_ownership = serde_json::from_str(&label_ownership);
+    let label_ownership:CreateLabelOwnership = match label_ownership {
+        Ok(label_ownership) => label_ownership,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    match operations::add_label(&db, label_ownership).await {
+        Ok(()) => HttpResponse::Ok().json("Success"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateBadgeOwnership,
+    responses(
+        (status = 200, description = "Badge added and rankings updated", body = String),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/add/badge", wrap = "from_fn(idempotent)")]
+async fn add_badge(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let badge_ownership = String::from_utf8(bytes.to_vec()).unwrap();
+    let badge_ownership = serde_json::from_str(&badge_ownership);
+    let badge_ownership:CreateBadgeOwnership = match badge_ownership {
+        Ok(badge_ownership) => badge_ownership,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&badge_ownership) { return invalid(failure) }
+
+    match operations::add_badge(&db, badge_ownership).await {
+        Ok(()) => HttpResponse::Ok().json("Success"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = BadgeAward,
+    responses(
+        (status = 200, description = "Teams that got the badge and teams that already had it", body = AwardReport),
+        (status = 400, description = "Invalid JSON, unknown badge or team, or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/award/badge", wrap = "from_fn(idempotent)")]
+async fn award_badge(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let award:BadgeAward = match serde_json::from_slice(&bytes) {
+        Ok(award) => award,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+    if let Err(failure) = validation::check(&award) { return invalid(failure) }
+
+    match operations::award_badge(&db, award).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = BadgeRevoke,
+    responses(
+        (status = 200, description = "Teams that lost the badge and teams that didn't have it", body = RevokeReport),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/revoke/badge")]
+async fn revoke_badge(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let revoke:BadgeRevoke = match serde_json::from_slice(&bytes) {
+        Ok(revoke) => revoke,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+    if let Err(failure) = validation::check(&revoke) { return invalid(failure) }
+
+    match operations::revoke_badge(&db, revoke).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreatePerson,
+    responses(
+        (status = 200, description = "Person added", body = String),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/add/person", wrap = "from_fn(idempotent)")]
+async fn add_person(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let person = String::from_utf8(bytes.to_vec()).unwrap();
+    let person = serde_json::from_str(&person);
+    let person:CreatePerson = match person {
+        Ok(person) => person,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&person) { return invalid(failure) }
+
+    match operations::add_person(&db, person).await {
+        Ok(()) => HttpResponse::Ok().json("Success"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateBadge,
+    responses(
+        (status = 200, description = "Id of the new badge", body = i64),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/create/badge", wrap = "from_fn(idempotent)")]
+async fn create_badge(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let badge = String::from_utf8(bytes.to_vec()).unwrap();
+    let badge = serde_json::from_str(&badge);
+    let badge:CreateBadge = match badge {
+        Ok(badge) => badge,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&badge) { return invalid(failure) }
+
+    match operations::create_badge(&db, badge).await {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateLabel,
+    responses(
+        (status = 200, description = "Id of the new label", body = i64),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/create/label", wrap = "from_fn(idempotent)")]
+async fn create_label(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let label = String::from_utf8(bytes.to_vec()).unwrap();
+    let label = serde_json::from_str(&label);
+    let label:CreateLabel = match label {
+        Ok(label) => label,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&label) { return invalid(failure) }
+
+    match operations::create_label(&db, label).await {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = CreateCategory,
+    responses(
+        (status = 200, description = "Id of the new category", body = i64),
+        (status = 400, description = "Invalid JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/create/category", wrap = "from_fn(idempotent)")]
+async fn create_category(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let category = String::from_utf8(bytes.to_vec()).unwrap();
+    let category = serde_json::from_str(&category);
+    let category:CreateCategory = match category {
+        Ok(category) => category,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+
+    if let Err(failure) = validation::check(&category) { return invalid(failure) }
+
+    match operations::create_category(&db, category).await {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    params(DeleteOwnershipQuery),
+    request_body = OwnershipBody,
+    responses(
+        (status = 200, description = "Link deleted", body = String),
+        (status = 400, description = "Invalid kind, JSON or database error", body = String),
+    )
+)]
+#[post("/delete_ownership")]
+async fn delete_ownership(db: web::Data<AppState>, bytes: web::Bytes,  info: web::Query<DeleteOwnershipQuery>) -> impl Responder {
+    let removed = match &info.kind[..] {
+        "label" => {
+            let label_ownership = String::from_utf8(bytes.to_vec()).unwrap();
+            let label_ownership = serde_json::from_str(&label_ownership);
+            let label_ownership:DeleteOwnedLabel = match label_ownership {
+                Ok(label_ownership) => label_ownership,
+                Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+            };
+            operations::remove_label(&db, label_ownership).await
+        },
+        "badge"=> {
+            let badge_ownership = String::from_utf8(bytes.to_vec()).unwrap();
+            let badge_ownership = serde_json::from_str(&badge_ownership);
+            let badge_ownership:DeleteOwnedBadge = match badge_ownership {
+                Ok(badge_ownership) => badge_ownership,
+                Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+            };
+            operations::remove_badge(&db, badge_ownership).await
+        },
+        _ => return HttpResponse::BadRequest().json("NO type FOUND")
+    };
+
+    match removed {
+        Ok(()) => HttpResponse::Ok().json("OK"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    params(DeleteQuery),
+    responses(
+        (status = 200, description = "Object deleted", body = String),
+        (status = 400, description = "Invalid kind or database error", body = String),
+    )
+)]
+#[post("/delete")]
+async fn delete(db: web::Data<AppState>, info: web::Query<DeleteQuery>) -> impl Responder {
+    let force = info.force.unwrap_or(false);
+    match operations::delete(&db, &info.kind, info.id, force).await {
+        Ok(()) => HttpResponse::Ok().json("Success"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    params(DeleteQuery),
+    request_body = EditBody,
+    responses(
+        (status = 200, description = "Object edited", body = String),
+        (status = 400, description = "Invalid kind, JSON or database error", body = String),
+        (status = 422, description = "Invalid fields", body = ValidationFailure),
+    )
+)]
+#[post("/edit")]
+async fn edit(db: web::Data<AppState>, info: web::Query<DeleteQuery>, bytes: web::Bytes) -> impl Responder {
+    let raw_json = String::from_utf8(bytes.to_vec()).unwrap();
+    let body = match EditBody::parse(&info.kind, &raw_json) {
+        Ok(body) => body,
+        Err(err) => return HttpResponse::BadRequest().json(err),
+    };
+
+    if let Err(failure) = body.check() { return invalid(failure) }
+
+    match operations::edit(&db, info.id, body).await {
+        Ok(()) => HttpResponse::Ok().json("Success"),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    request_body = ImportFiles,
+    responses(
+        (status = 200, description = "Everything was imported", body = ImportReport),
+        (status = 400, description = "Nothing was imported", body = Vec<RowError>),
+    )
+)]
+#[post("/import")]
+async fn import(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let files:ImportFiles = match serde_json::from_slice(&bytes) {
+        Ok(files) => files,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    match crate::import::import(files, pool).await {
+        Ok(report) => {
+            db.metrics.awarded(report.badges);
+            db.cache.invalidate_all();
+            HttpResponse::Ok().json(report)
+        },
+        Err(errors) => HttpResponse::BadRequest().json(errors),
+    }
+}
+
+#[utoipa::path(
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "Teams as JSON, a zip of CSV files or an XLSX workbook", body = Vec<Team>),
+        (status = 400, description = "Invalid format", body = String),
+    )
+)]
+#[get("/export")]
+async fn export(db: web::Data<AppState>, info: web::Query<ExportQuery>) -> impl Responder {
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    let dataset = match crate::export::load(pool).await {
+        Ok(dataset) => dataset,
+        Err(err) => return HttpResponse::InternalServerError().json(format!("ERROR READING DATABASE: {}", err)),
+    };
+    let (body, content_type, file) = match info.format.as_deref().unwrap_or("json") {
+        "json" => (dataset.to_json().map_err(|err| err.to_string()), "application/json", "starterspace.json"),
+        "csv" => (dataset.to_csv_zip(), "application/zip", "starterspace.zip"),
+        "xlsx" => (dataset.to_xlsx(), "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", "starterspace.xlsx"),
+        _ => return HttpResponse::BadRequest().json("FORMAT IS NOT AVAILABLE FOR EXPORT"),
+    };
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", file)))
+            .content_type(content_type)
+            .body(body),
+        Err(err) => HttpResponse::InternalServerError().json(format!("ERROR EXPORTING: {}", err)),
+    }
+}
+
+#[utoipa::path(responses((status = 200, description = "Backup of every table", body = Backup)))]
+#[get("/backup")]
+async fn create_backup(db: web::Data<AppState>) -> impl Responder {
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    match crate::backup::backup(pool).await {
+        Ok(backup) => HttpResponse::Ok()
+            .append_header(("Content-Disposition", "attachment; filename=\"starterspace-backup.json\""))
+            .json(backup),
+        Err(err) => HttpResponse::InternalServerError().json(format!("ERROR READING DATABASE: {}", err)),
+    }
+}
+
+#[utoipa::path(
+    request_body = Backup,
+    responses(
+        (status = 200, description = "Backup restored", body = RestoreReport),
+        (status = 400, description = "Invalid backup or database is not empty", body = String),
+    )
+)]
+#[post("/restore")]
+async fn restore_backup(db: web::Data<AppState>, bytes: web::Bytes) -> impl Responder {
+    let backup:Backup = match serde_json::from_slice(&bytes) {
+        Ok(backup) => backup,
+        Err(err) => return HttpResponse::BadRequest().json(format!("ERROR PARSING JSON: {}", err)),
+    };
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    let report = crate::backup::restore(backup, pool).await;
+    db.cache.invalidate_all();
+    match report {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::BadRequest().json(err),
+    }
+}
+
+#[utoipa::path(
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Results sorted by relevance", body = Vec<SearchResult>),
+        (status = 400, description = "Query is empty", body = String),
+    )
+)]
+#[get("/search")]
+async fn search(db: web::Data<AppState>, info: web::Query<SearchQuery>) -> impl Responder {
+    if info.q.trim().is_empty() {
+        return HttpResponse::BadRequest().json("QUERY IS EMPTY")
+    }
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    match crate::search::search(&info, pool).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(err) => HttpResponse::InternalServerError().json(format!("ERROR SEARCHING: {}", err)),
+    }
+}
+
+#[utoipa::path(responses((status = 200, description = "Team cache statistics", body = CacheStats)))]
+#[get("/cache/stats")]
+async fn cache_stats(db: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(db.cache.stats())
+}
+
+#[utoipa::path(responses((status = 200, description = "Scores and rankings updated", body = String)))]
+#[post("/update/rankings")]
+async fn update_rankings(db: web::Data<AppState>) -> impl Responder {
+    operations::update_rankings(&db).await;
+    HttpResponse::Ok().json("OK")
+}
+
+#[utoipa::path(responses(
+    (status = 200, description = "Orphaned rows, score and rank drift and duplicate names", body = IntegrityReport),
+    (status = 500, description = "Database error", body = String),
+))]
+#[get("/integrity")]
+async fn integrity(db: web::Data<AppState>) -> impl Responder {
+    let Some(pool) = db.repo.pool() else { return unsupported() };
+    match crate::integrity::check(pool).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::InternalServerError().json(format!("ERROR CHECKING INTEGRITY: {}", err)),
+    }
+}
+
+#[utoipa::path(responses((status = 200, description = "The process is up", body = String)))]
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json("OK")
+}
+
+#[utoipa::path(responses(
+    (status = 200, description = "Serving and the database is usable", body = Readiness),
+    (status = 503, description = "Starting, shutting down or the database is not usable", body = Readiness),
+))]
+#[get("/readyz")]
+async fn readyz(db: web::Data<AppState>) -> impl Responder {
+    let phase = db.health.phase();
+    // Probes give up after a few seconds, a database that doesn't answer by then is not ready
+    let database = match actix_web::rt::time::timeout(std::time::Duration::from_secs(2), db.repo.ready()).await {
+        Ok(Ok(())) => String::from("OK"),
+        Ok(Err(err)) => err,
+        Err(_) => String::from("DATABASE DID NOT ANSWER IN TIME"),
+    };
+    let ready = phase == Phase::Serving && database == "OK";
+    let readiness = Readiness { ready, phase, database };
+    if ready { HttpResponse::Ok().json(readiness) } else { HttpResponse::ServiceUnavailable().json(readiness) }
+}
+
+#[utoipa::path(responses((status = 200, description = "Version of the running build", body = Version)))]
+#[get("/version")]
+async fn version() -> impl Responder {
+    HttpResponse::Ok().json(health::version())
+}
+
+#[utoipa::path(responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain")))]
+#[get("/metrics")]
+async fn prometheus_metrics(db: web::Data<AppState>) -> impl Responder {
+    let body = db.metrics.render(&db).await;
+    HttpResponse::Ok().content_type(prometheus::TEXT_FORMAT).body(body)
+}
+
+#[get("/openapi.json")]
+async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[post("/graphql")]
+async fn graphql_request(db: web::Data<AppState>, schema: web::Data<graphql::ApiSchema>, req: HttpRequest, request: GraphQLRequest) -> GraphQLResponse {
+    let error = |message: &str| async_graphql::Response::from_errors(vec![async_graphql::ServerError::new(message, None)]).into();
+    let Some(pool) = db.repo.pool() else { return error(repository::UNSUPPORTED) };
+    let mut request = request.into_inner().data(graphql::loader(pool));
+    // Create and add mutations run once per key, like the REST handlers
+    if let Some(key) = req.headers().get(idempotency::HEADER) {
+        match idempotency::valid_key(key) {
+            Ok(key) => request = request.data(idempotency::GraphqlKey(key)),
+            Err(err) => return error(err),
+        }
+    }
+    schema.execute(request).await.into()
+}
+
+#[get("/graphql")]
+async fn graphiql() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "StarterSpace API"),
+    paths(
+        teams, labels, badges, categories, team_id,
+        team_create, patch_team, add_label, add_badge, award_badge, revoke_badge, add_person, create_badge, create_label, create_category,
+        delete_ownership, delete, edit, update_rankings, integrity,
+        import, export, create_backup, restore_backup, search, cache_stats,
+        healthz, readyz, version, prometheus_metrics,
+    )
+)]
+pub struct ApiDoc;
+
+// Every route, the state and the GraphQL schema are added by the caller
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    // Imports and restores can be bigger than the default limit
+    cfg.app_data(web::PayloadConfig::new(32 * 1024 * 1024))
+        .service(teams)
+        .service(labels)
+        .service(badges)
+        .service(categories)
+        .service(team_id)
+        .service(team_create)
+        .service(patch_team)
+        .service(add_label)
+        .service(add_badge)
+        .service(award_badge)
+        .service(revoke_badge)
+        .service(add_person)
+        .service(create_badge)
+        .service(create_label)
+        .service(create_category)
+        .service(delete_ownership)
+        .service(delete)
+        .service(edit)
+        .service(update_rankings)
+        .service(integrity)
+        .service(cache_stats)
+        .service(healthz)
+        .service(readyz)
+        .service(version)
+        .service(prometheus_metrics)
+        .service(openapi_json)
+        .service(Redoc::with_url("/docs", ApiDoc::openapi()))
+        .service(import)
+        .service(export)
+        .service(create_backup)
+        .service(restore_backup)
+        .service(search)
+        .service(graphql_request)
+        .service(graphiql);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+    use actix_web::{dev::{Service, ServiceResponse}, http::StatusCode, App};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use serde_json::{json, Value};
+
+    // Every route registered with `#[get]`, `#[post]` or `#[patch]` in this file, as (method, path)
+    fn routes() -> Vec<(String, String)> {
+        let mut routes = vec![];
+        for line in include_str!("routes.rs").lines() {
+            for method in ["get", "post", "patch"] {
+                let prefix = format!("#[{}(\"", method);
+                if let Some(path) = line.strip_prefix(&prefix).and_then(|rest| rest.split('"').next()) {
+                    routes.push((String::from(method), String::from(path)));
+                }
+            }
+        }
+        routes.sort();
+        routes
+    }
+
+    #[test]
+    fn openapi_matches_routes() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let mut documented = vec![];
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                documented.push((method.clone(), path.clone()));
+            }
+        }
+        documented.sort();
+
+        // The spec itself and GraphQL, which has its own schema, are not documented
+        let routes:Vec<(String, String)> = routes().into_iter().filter(|(_, path)| path != "/openapi.json" && path != "/graphql").collect();
+        assert_eq!(routes, documented);
+    }
+
+    // The app over the database of `scratch`, with GraphQL
+    async fn app(scratch: &Scratch) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+        let state = AppState::new(std::sync::Arc::new(repository::PgRepository::new(scratch.pool.clone())));
+        init_service(App::new()
+            .app_data(web::Data::new(graphql::schema(state.clone())))
+            .app_data(web::Data::new(state))
+            .configure(configure)).await
+    }
+
+    async fn send<S>(app: &S, request: TestRequest) -> (StatusCode, Value)
+    where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+        let response = call_service(app, request.to_request()).await;
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn get<S>(app: &S, uri: &str) -> (StatusCode, Value)
+    where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+        send(app, TestRequest::get().uri(uri)).await
+    }
+
+    async fn post<S>(app: &S, uri: &str, body: Value) -> (StatusCode, Value)
+    where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+        send(app, TestRequest::post().uri(uri).set_payload(body.to_string())).await
+    }
+
+    // (name, score) of every team, in the order of `/teams`
+    async fn scores<S>(app: &S) -> Vec<(String, i64)>
+    where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+        let (status, list) = get(app, "/teams?fields=name,score").await;
+        assert_eq!(status, StatusCode::OK);
+        list.as_array().unwrap().iter()
+            .map(|team| (team["name"].as_str().unwrap().to_string(), team["score"].as_i64().unwrap()))
+            .collect()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn search_export_integrity_and_graphql_read_the_database() {
+        let scratch = Scratch::new("routes_read").await;
+        scratch.seed().await;
+        crate::search::install_search_indexes(&scratch.pool).await.unwrap();
+        let app = app(&scratch).await;
+
+        let (status, results) = get(&app, "/search?q=Moneypool").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((&results[0]["kind"], &results[0]["id"], &results[0]["title"]), (&json!("team"), &json!(2), &json!("Moneypool")));
+
+        let (status, exported) = get(&app, "/export").await;
+        assert_eq!(status, StatusCode::OK);
+        let names:Vec<&str> = exported.as_array().unwrap().iter().map(|team| team["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["Moneypool", "Startup-tec"]);
+        let response = call_service(&app, TestRequest::get().uri("/export?format=csv").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/zip");
+
+        let (status, report) = get(&app, "/integrity").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["consistent"], json!(true));
+
+        let (status, body) = post(&app, "/graphql", json!({ "query": "{ teams { name score badges { badge { name } } } }" })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["errors"].is_null(), "{}", body);
+        assert_eq!(body["data"]["teams"][0]["score"], json!(900));
+        assert_eq!(body["data"]["teams"][0]["badges"].as_array().unwrap().len(), 2);
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn backups_restore_into_an_empty_database() {
+        let source = Scratch::new("routes_backup").await;
+        source.seed().await;
+        let (status, backup) = get(&app(&source).await, "/backup").await;
+        assert_eq!(status, StatusCode::OK);
+        source.drop().await;
+
+        let target = Scratch::new("routes_restore").await;
+        let app = app(&target).await;
+        let (status, report) = post(&app, "/restore", backup.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", report);
+        assert_eq!((&report["teams"], &report["badge_ownerships"], &report["mismatches"]), (&json!(2), &json!(4), &json!([])));
+        assert_eq!(scores(&app).await, [(String::from("Moneypool"), 900), (String::from("Startup-tec"), 900)]);
+
+        // Only an empty database can be restored
+        let (status, _) = post(&app, "/restore", backup).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        target.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn imports_and_patches_change_teams() {
+        let scratch = Scratch::new("routes_write").await;
+        scratch.seed().await;
+        let app = app(&scratch).await;
+
+        let files = json!({
+            "teams": "name,description,location,stage,creation_date,logo_url,banner_url\nRocket,A team,Monterrey,1,2023-02-26,,\n",
+            "labels": "team,label\nRocket,Web3\n",
+            "badges": "team,badge,acquisition_date\nRocket,Logro 1,2023-03-01\n",
+        });
+        let (status, report) = post(&app, "/import", files.clone()).await;
+        assert_eq!(status, StatusCode::OK, "{}", report);
+        assert_eq!(report, json!({ "teams": 1, "persons": 0, "labels": 1, "badges": 1 }));
+        assert!(scores(&app).await.contains(&(String::from("Rocket"), 200)));
+        let (status, errors) = post(&app, "/import", files).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(!errors.as_array().unwrap().is_empty());
+
+        let operations = json!([{ "op": "replace", "path": "/description", "value": "Renamed" }, { "op": "remove", "path": "/badges/0" }]);
+        let (status, team) = send(&app, TestRequest::patch().uri("/teams/1").set_payload(operations.to_string())).await;
+        assert_eq!(status, StatusCode::OK, "{}", team);
+        assert_eq!((&team["description"], &team["score"]), (&json!("Renamed"), &json!(700)));
+        let (status, _) = send(&app, TestRequest::patch().uri("/teams/99").set_payload("[]")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        scratch.drop().await;
+    }
+}
@@ -0,0 +1,45 @@
+// A database of its own for each test that needs Postgres, so tests can empty
+// tables or break foreign keys without touching anyone's data. It is created
+// next to the one in DATABASE_URL, with the tables and score triggers, and
+// dropped by `drop`.
+use std::str::FromStr;
+use sqlx::postgres::{PgConnectOptions, PgPool};
+
+pub struct Scratch {
+    admin: PgPool,
+    name: String,
+    pub pool: PgPool,
+}
+
+impl Scratch {
+    // `test` tells apart the databases of tests running at the same time
+    pub async fn new(test: &str) -> Scratch {
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let admin = PgPool::connect(&url).await.unwrap();
+        let name = format!("scratch_{}_{}", test, std::process::id());
+        sqlx::query(&format!("DROP DATABASE IF EXISTS {}", name)).execute(&admin).await.unwrap();
+        sqlx::query(&format!("CREATE DATABASE {}", name)).execute(&admin).await.unwrap();
+        let pool = PgPool::connect_with(PgConnectOptions::from_str(&url).unwrap().database(&name)).await.unwrap();
+        sqlx::Executor::execute(&pool, include_str!("../database_information/psql-create-tables.sql")).await.unwrap();
+        crate::ranking::install_score_triggers(&pool).await.unwrap();
+        Scratch { admin, name, pool }
+    }
+
+    // Loads the sample data with the scores and ranks its badges give
+    pub async fn seed(&self) {
+        sqlx::Executor::execute(&self.pool, include_str!("../database_information/psql-temp-values.sql")).await.unwrap();
+        // The triggers added the badge points to the stored scores
+        crate::update_scores(self.pool.clone()).await;
+        crate::update_ranking(self.pool.clone()).await;
+    }
+
+    pub async fn execute(&self, sql: &str) {
+        sqlx::Executor::execute(&self.pool, sql).await.unwrap();
+    }
+
+    pub async fn drop(self) {
+        self.pool.close().await;
+        // Connections can still be closing on the server
+        sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", self.name)).execute(&self.admin).await.unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{Serialize, Deserialize};
+use utoipa::{ToSchema, IntoParams};
+use sqlx::{Executor, FromRow};
+
+// Words whose similarity with the query is below this are not considered typos of it
+const FUZZY_THRESHOLD: f32 = 0.4;
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(FromRow, Serialize, Debug, ToSchema)]
+pub struct SearchResult {
+    pub kind: String,
+    pub id: i64,
+    pub title: String,
+    pub snippet: String,
+    pub rank: f32,
+}
+
+// The pg_trgm extension and the indexes of every document
+const SEARCH_INDEXES: &str = include_str!("../database_information/psql-search.sql");
+
+// Creates the extension and indexes that are missing, run when the server starts
+pub async fn install_search_indexes(pool: &sqlx::postgres::PgPool) -> Result<(), sqlx::Error> {
+    pool.execute(SEARCH_INDEXES).await?;
+    Ok(())
+}
+
+// `document` with the characters that are special in HTML escaped, so only the
+// `<b>` added by ts_headline is markup
+fn escaped(document: &str) -> String {
+    [("&", "&amp;"), ("<", "&lt;"), (">", "&gt;"), ("\"", "&quot;"), ("''", "&#39;")].iter()
+        .fold(document.to_string(), |sql, (from, to)| format!("replace({}, '{}', '{}')", sql, from, to))
+}
+
+// Every searchable table with the text that is matched for it. The documents
+// must match the expressions in `database_information/psql-search.sql` for the
+// indexes to be used.
+const SOURCES: [(&str, &str, &str); 3] = [
+    ("team", "teams", "name || ' ' || description || ' ' || location"),
+    ("person", "persons", "name || ' ' || career"),
+    ("badge", "badges", "name || ' ' || description"),
+];
+
+// Full text search in spanish and english plus trigram similarity for typos.
+// The snippet is HTML: the text is escaped and matches are wrapped in `<b>`.
+pub async fn search(query: &SearchQuery, pool: &sqlx::postgres::PgPool) -> Result<Vec<SearchResult>, sqlx::Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let selects: Vec<String> = SOURCES.iter().map(|(kind, table, document)| format!(
+        "SELECT '{kind}' AS kind, id, name AS title, \
+            ts_headline('spanish', {html}, spanish || english, 'StartSel=<b>, StopSel=</b>, MaxWords=20, MinWords=5') AS snippet, \
+            GREATEST(ts_rank(to_tsvector('spanish', {document}), spanish), ts_rank(to_tsvector('english', {document}), english), word_similarity($1, {document})) AS rank \
+        FROM {table}, terms \
+        WHERE to_tsvector('spanish', {document}) @@ spanish \
+            OR to_tsvector('english', {document}) @@ english \
+            OR word_similarity($1, {document}) >= $2",
+        html = escaped(document),
+    )).collect();
+    let sql = format!(
+        "WITH terms AS (SELECT websearch_to_tsquery('spanish', $1) AS spanish, websearch_to_tsquery('english', $1) AS english) \
+        {} ORDER BY rank DESC, title LIMIT $3",
+        selects.join(" UNION ALL ")
+    );
+    sqlx::query_as::<sqlx::postgres::Postgres, SearchResult>(&sql)
+        .bind(query.q.trim())
+        .bind(FUZZY_THRESHOLD)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Searches a team whose text looks like markup, on a stock database in
+    // DATABASE_URL without the search indexes installed beforehand
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn snippets_escape_the_stored_text() {
+        let pool = sqlx::postgres::PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
+        install_search_indexes(&pool).await.unwrap();
+        // Installing twice is what every restart does
+        install_search_indexes(&pool).await.unwrap();
+        let (id,):(i64,) = sqlx::query_as("INSERT INTO teams (score, stage, name, description, creation_date, location) VALUES (0, 1, $1, $2, '2023-01-01', 'Monterrey') RETURNING id")
+            .bind(format!("search test {}", std::process::id()))
+            .bind("Rocketship <i>'s</i> & \"co\"")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let results = search(&SearchQuery { q: String::from("rocketship"), limit: Some(MAX_LIMIT) }, &pool).await;
+        sqlx::query("DELETE FROM teams WHERE id = $1").bind(id).execute(&pool).await.unwrap();
+        let results = results.unwrap();
+        let result = results.iter().find(|result| result.kind == "team" && result.id == id).unwrap();
+        assert!(result.snippet.starts_with("<b>Rocketship</b> &lt;i&gt;&#39;s&lt;/i&gt; &amp; &quot;co&quot;"), "{}", result.snippet);
+    }
+}
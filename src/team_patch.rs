@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use sqlx::{Postgres, Transaction};
+use crate::*;
+use crate::validation::ValidationFailure;
+
+// Applies an RFC 6902 JSON Patch to the team as returned by `/team/{id}`. The
+// patched document is compared with the original and only the differences are
+// written, all in one transaction. `id`, `rank` and `score` are read only.
+//
+// `labels` entries only need an `id`. New `persons` are entries without an `id`,
+// new `badges` entries need `acquisition_date` and `badge.id` (or `badge_id`).
+
+#[derive(Debug)]
+pub enum PatchError {
+    NotFound,
+    // The operations can't be applied to the team, a failed `test` included
+    Conflict(String),
+    // The patched team is not something that can be stored
+    Invalid(String),
+    Validation(ValidationFailure),
+    Database(String),
+    // The repository has no Postgres pool
+    Unsupported,
+}
+
+impl From<sqlx::Error> for PatchError {
+    fn from(err: sqlx::Error) -> Self {
+        PatchError::Database(format!("ERROR ADDING TO DATABASE: {}", err))
+    }
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct PatchedTeam {
+    id: i64,
+    rank: Option<i32>,
+    score: i64,
+    stage: i32,
+    name: String,
+    logo_url: Option<String>,
+    banner_url: Option<String>,
+    description: String,
+    creation_date: String,
+    location: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PatchedLabel {
+    id: i64,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct PatchedPerson {
+    id: Option<i64>,
+    name: String,
+    career: String,
+    graduation_date: String,
+    picture_url: Option<String>,
+    portafolio_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PatchedBadge {
+    id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct PatchedOwnership {
+    id: Option<i64>,
+    acquisition_date: String,
+    badge: Option<PatchedBadge>,
+    badge_id: Option<i64>,
+}
+
+// The document before and after the patch, split in its parts
+struct Document {
+    team: PatchedTeam,
+    labels: Vec<PatchedLabel>,
+    persons: Vec<PatchedPerson>,
+    badges: Vec<PatchedOwnership>,
+}
+
+impl Document {
+    fn from(mut value: serde_json::Value) -> Result<Document, String> {
+        let mut take = |key: &str| value.as_object_mut().and_then(|object| object.remove(key)).unwrap_or(serde_json::Value::Array(vec![]));
+        let labels = serde_json::from_value(take("labels")).map_err(|err| format!("INVALID labels: {}", err))?;
+        let persons = serde_json::from_value(take("persons")).map_err(|err| format!("INVALID persons: {}", err))?;
+        let badges = serde_json::from_value(take("badges")).map_err(|err| format!("INVALID badges: {}", err))?;
+        let team = serde_json::from_value(value).map_err(|err| format!("INVALID TEAM: {}", err))?;
+        Ok(Document { team, labels, persons, badges })
+    }
+}
+
+fn validate<T: validator::Validate>(value: &T) -> Result<(), PatchError> {
+    validation::check(value).map_err(PatchError::Validation)
+}
+
+pub async fn apply(state: &AppState, id: i64, operations: json_patch::Patch) -> Result<Team, PatchError> {
+    let pool = state.repo.pool().ok_or(PatchError::Unsupported)?;
+    let mut tx = pool.begin().await?;
+    // Other patches of the same team wait until this one is done
+    let raw_team = sqlx::query_as::<Postgres, RawTeam>("SELECT * FROM teams WHERE id = $1 FOR UPDATE")
+        .bind(id)
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(PatchError::NotFound)?;
+    let original = serde_json::to_value(Team::from(raw_team, state.repo.as_ref()).await).unwrap();
+    let mut patched = original.clone();
+    json_patch::patch(&mut patched, &operations).map_err(|err| PatchError::Conflict(err.to_string()))?;
+
+    let before = Document::from(original).map_err(PatchError::Invalid)?;
+    let after = Document::from(patched).map_err(PatchError::Invalid)?;
+
+    patch_team(&mut tx, &before.team, &after.team).await?;
+    patch_labels(&mut tx, id, &before.labels, &after.labels).await?;
+    patch_persons(&mut tx, id, &before.persons, &after.persons).await?;
+    let (badges_changed, awarded) = patch_badges(&mut tx, id, &before.badges, &after.badges).await?;
+    tx.commit().await?;
+    state.metrics.awarded(awarded);
+
+    if badges_changed || before.team.name != after.team.name {
+        // The ranking depends on the score and breaks ties by name
+        operations::rerank(state).await;
+        state.cache.invalidate_all();
+    } else {
+        state.cache.invalidate_team(id);
+    }
+    let raw_team = sqlx::query_as::<Postgres, RawTeam>("SELECT * FROM teams WHERE id = $1")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    Ok(Team::from(raw_team, state.repo.as_ref()).await)
+}
+
+async fn patch_team(tx: &mut Transaction<'_, Postgres>, before: &PatchedTeam, after: &PatchedTeam) -> Result<(), PatchError> {
+    if before == after { return Ok(()); }
+    for (field, changed) in [("id", before.id != after.id), ("rank", before.rank != after.rank), ("score", before.score != after.score)] {
+        if changed { return Err(PatchError::Invalid(format!("{} IS READ ONLY", field))); }
+    }
+    validate(&CreateTeam {
+        name: after.name.clone(),
+        description: after.description.clone(),
+        location: after.location.clone(),
+        stage: after.stage,
+        creation_date: after.creation_date.clone(),
+        banner_url: after.banner_url.clone(),
+        logo_url: after.logo_url.clone(),
+    })?;
+    sqlx::query("UPDATE teams SET stage = $1, name = $2, logo_url = $3, banner_url = $4, description = $5, creation_date = $6::DATE, location = $7 WHERE id = $8")
+        .bind(after.stage)
+        .bind(&after.name)
+        .bind(&after.logo_url)
+        .bind(&after.banner_url)
+        .bind(&after.description)
+        .bind(&after.creation_date)
+        .bind(&after.location)
+        .bind(after.id)
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
+
+async fn patch_labels(tx: &mut Transaction<'_, Postgres>, team_id: i64, before: &[PatchedLabel], after: &[PatchedLabel]) -> Result<(), PatchError> {
+    let removed:Vec<i64> = before.iter().map(|label| label.id).filter(|id| !after.iter().any(|label| label.id == *id)).collect();
+    let mut added:Vec<i64> = after.iter().map(|label| label.id).filter(|id| !before.iter().any(|label| label.id == *id)).collect();
+    added.sort();
+    added.dedup();
+    if !removed.is_empty() {
+        sqlx::query("DELETE FROM label_ownerships WHERE team_id = $1 AND label_id = ANY($2)")
+            .bind(team_id)
+            .bind(&removed)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for label_id in added {
+        sqlx::query("INSERT INTO label_ownerships (team_id, label_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(team_id)
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn patch_persons(tx: &mut Transaction<'_, Postgres>, team_id: i64, before: &[PatchedPerson], after: &[PatchedPerson]) -> Result<(), PatchError> {
+    let existing:HashMap<i64, &PatchedPerson> = before.iter().filter_map(|person| person.id.map(|id| (id, person))).collect();
+    let kept:Vec<i64> = after.iter().filter_map(|person| person.id).collect();
+    for person in after {
+        if let Some(id) = person.id {
+            match existing.get(&id) {
+                None => return Err(PatchError::Invalid(format!("PERSON {} IS NOT IN THE TEAM", id))),
+                Some(original) if *original == person => continue,
+                Some(_) => {},
+            }
+        }
+        validate(&CreatePerson {
+            team_id,
+            name: person.name.clone(),
+            career: person.career.clone(),
+            graduation_date: person.graduation_date.clone(),
+            picture_url: person.picture_url.clone(),
+            portafolio_url: person.portafolio_url.clone(),
+        })?;
+        let query = match person.id {
+            Some(_) => "UPDATE persons SET name = $2, career = $3, graduation_date = $4::DATE, picture_url = $5, portafolio_url = $6 WHERE id = $7 AND team_id = $1",
+            None => "INSERT INTO persons (team_id, name, career, graduation_date, picture_url, portafolio_url) VALUES ($1, $2, $3, $4::DATE, $5, $6)",
+        };
+        let mut query = sqlx::query(query)
+            .bind(team_id)
+            .bind(&person.name)
+            .bind(&person.career)
+            .bind(&person.graduation_date)
+            .bind(&person.picture_url)
+            .bind(&person.portafolio_url);
+        if let Some(id) = person.id { query = query.bind(id); }
+        query.execute(&mut *tx).await?;
+    }
+    let removed:Vec<i64> = existing.keys().copied().filter(|id| !kept.contains(id)).collect();
+    if !removed.is_empty() {
+        sqlx::query("DELETE FROM persons WHERE team_id = $1 AND id = ANY($2)")
+            .bind(team_id)
+            .bind(&removed)
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+// Returns whether the team's badges changed, and so its score and rank, and
+// how many badges it was given
+async fn patch_badges(tx: &mut Transaction<'_, Postgres>, team_id: i64, before: &[PatchedOwnership], after: &[PatchedOwnership]) -> Result<(bool, usize), PatchError> {
+    let mut changed = false;
+    let mut awarded = 0;
+    let existing:HashMap<i64, &PatchedOwnership> = before.iter().filter_map(|ownership| ownership.id.map(|id| (id, ownership))).collect();
+    let kept:Vec<i64> = after.iter().filter_map(|ownership| ownership.id).collect();
+    for ownership in after {
+        let badge_id = ownership.badge_id.or(ownership.badge.as_ref().map(|badge| badge.id));
+        if let Some(id) = ownership.id {
+            let original = existing.get(&id).ok_or_else(|| PatchError::Invalid(format!("BADGE OWNERSHIP {} IS NOT IN THE TEAM", id)))?;
+            // Only the date of an ownership can change, another badge is a new ownership
+            let original_badge = original.badge.as_ref().map(|badge| badge.id);
+            if ownership.badge.as_ref().map(|badge| badge.id) != original_badge || badge_id != original_badge {
+                return Err(PatchError::Invalid(format!("THE BADGE OF BADGE OWNERSHIP {} IS READ ONLY", id)));
+            }
+            if original.acquisition_date == ownership.acquisition_date { continue; }
+        }
+        validate(&CreateBadgeOwnership {
+            team_id,
+            badge_id: badge_id.unwrap_or_default(),
+            acquisition_date: ownership.acquisition_date.clone(),
+        })?;
+        match ownership.id {
+            Some(id) => {
+                sqlx::query("UPDATE badge_ownerships SET acquisition_date = $1::DATE WHERE id = $2")
+                    .bind(&ownership.acquisition_date)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            },
+            None => {
+                let badge_id = badge_id.ok_or_else(|| PatchError::Invalid(String::from("NEW BADGES NEED badge_id")))?;
+                sqlx::query("INSERT INTO badge_ownerships (team_id, badge_id, acquisition_date) VALUES ($1, $2, $3::DATE)")
+                    .bind(team_id)
+                    .bind(badge_id)
+                    .bind(&ownership.acquisition_date)
+                    .execute(&mut *tx)
+                    .await?;
+                changed = true;
+                awarded += 1;
+            },
+        }
+    }
+    let removed:Vec<i64> = existing.keys().copied().filter(|id| !kept.contains(id)).collect();
+    if !removed.is_empty() {
+        sqlx::query("DELETE FROM badge_ownerships WHERE team_id = $1 AND id = ANY($2)")
+            .bind(team_id)
+            .bind(&removed)
+            .execute(&mut *tx)
+            .await?;
+        changed = true;
+    }
+    Ok((changed, awarded))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::*;
+    use crate::scratch::Scratch;
+    use super::{apply, PatchError};
+
+    // Ids of the only team, label and badge of the test database
+    const TEAM: i64 = 1;
+    const LABEL: i64 = 1;
+    const BADGE: i64 = 1;
+
+    // A database of its own with one team, label and a badge worth 300 points
+    async fn scratch(test: &str) -> (Scratch, AppState) {
+        let scratch = Scratch::new(test).await;
+        let state = AppState::new(std::sync::Arc::new(repository::PgRepository::new(scratch.pool.clone())));
+        let team = CreateTeam {
+            name: String::from("Rocket"),
+            description: String::from("Before"),
+            location: String::from("Monterrey"),
+            stage: 1,
+            creation_date: String::from("2023-01-01"),
+            banner_url: None,
+            logo_url: None,
+        };
+        operations::create_team(&state, team).await.unwrap();
+        operations::create_label(&state, CreateLabel { name: String::from("Web3") }).await.unwrap();
+        let category = operations::create_category(&state, CreateCategory { name: String::from("Talk") }).await.unwrap();
+        let badge = CreateBadge { name: String::from("Demo day"), description: String::from("test"), points: 300, category };
+        operations::create_badge(&state, badge).await.unwrap();
+        (scratch, state)
+    }
+
+    async fn patch(state: &AppState, operations: Value) -> Result<Team, PatchError> {
+        apply(state, TEAM, serde_json::from_value(operations).unwrap()).await
+    }
+
+    async fn team(state: &AppState) -> Team {
+        Team::from(state.repo.team(TEAM).await.unwrap(), state.repo.as_ref()).await
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn add_replace_and_remove() {
+        let (scratch, state) = scratch("team_patch_add").await;
+        let team = patch(&state, json!([
+            { "op": "replace", "path": "/description", "value": "After" },
+            { "op": "add", "path": "/labels/-", "value": { "id": LABEL } },
+            { "op": "add", "path": "/persons/-", "value": { "name": "Ana", "career": "ITC", "graduation_date": "2022-06-01" } },
+            { "op": "add", "path": "/badges/-", "value": { "badge_id": BADGE, "acquisition_date": "2023-02-01" } },
+        ])).await.unwrap();
+        assert_eq!(team.description, "After");
+        assert_eq!(team.labels.iter().map(|label| label.id).collect::<Vec<_>>(), [LABEL]);
+        assert_eq!(team.persons.iter().map(|person| &person.name[..]).collect::<Vec<_>>(), ["Ana"]);
+        assert_eq!(team.score, 300);
+        assert!(team.rank.is_some());
+        let metrics = state.metrics.render(&state).await;
+        assert!(metrics.contains("starterspace_badges_awarded_total 1\n"), "{}", metrics);
+
+        let team = patch(&state, json!([
+            { "op": "replace", "path": "/badges/0/acquisition_date", "value": "2023-03-01" },
+            { "op": "replace", "path": "/persons/0/career", "value": "IMT" },
+        ])).await.unwrap();
+        assert_eq!(team.badges.len(), 1);
+        assert_eq!(team.persons[0].career, "IMT");
+        assert_eq!(team.score, 300);
+        assert_eq!(serde_json::to_value(&team.badges[0]).unwrap()["acquisition_date"], "2023-03-01");
+
+        let team = patch(&state, json!([
+            { "op": "remove", "path": "/labels/0" },
+            { "op": "remove", "path": "/persons/0" },
+            { "op": "remove", "path": "/badges/0" },
+        ])).await.unwrap();
+        assert!(team.labels.is_empty() && team.persons.is_empty() && team.badges.is_empty());
+        assert_eq!((team.score, team.rank), (0, None));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn invalid_patches_are_refused() {
+        let (scratch, state) = scratch("team_patch_invalid").await;
+        patch(&state, json!([{ "op": "add", "path": "/badges/-", "value": { "badge_id": BADGE, "acquisition_date": "2023-02-01" } }])).await.unwrap();
+
+        let refused = [
+            (json!([{ "op": "remove", "path": "/nowhere" }]), "conflict"),
+            (json!([{ "op": "test", "path": "/description", "value": "Other" }]), "conflict"),
+            (json!([{ "op": "replace", "path": "/score", "value": 9000 }]), "score IS READ ONLY"),
+            (json!([{ "op": "replace", "path": "/badges/0/badge/id", "value": 2 }]), "IS READ ONLY"),
+            (json!([{ "op": "add", "path": "/badges/0/badge_id", "value": 2 }]), "IS READ ONLY"),
+            (json!([{ "op": "add", "path": "/persons/-", "value": { "id": 1, "name": "Ana", "career": "ITC", "graduation_date": "2022-06-01" } }]), "IS NOT IN THE TEAM"),
+            (json!([{ "op": "add", "path": "/badges/-", "value": { "acquisition_date": "2023-02-01" } }]), "NEW BADGES NEED badge_id"),
+            (json!([{ "op": "replace", "path": "/stage", "value": 0 }]), "validation"),
+        ];
+        for (operations, expected) in refused {
+            let error = patch(&state, operations.clone()).await.unwrap_err();
+            let matched = match &error {
+                PatchError::Conflict(_) => expected == "conflict",
+                PatchError::Validation(_) => expected == "validation",
+                PatchError::Invalid(err) => err.contains(expected),
+                _ => false,
+            };
+            assert!(matched, "{} gave {:?}", operations, error);
+        }
+        let team = team(&state).await;
+        assert_eq!((team.score, team.stage, team.badges.len()), (300, 1, 1));
+        scratch.drop().await;
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database in DATABASE_URL"]
+    async fn failures_roll_everything_back() {
+        let (scratch, state) = scratch("team_patch_rollback").await;
+        // The label and description are written before the person fails validation
+        let error = patch(&state, json!([
+            { "op": "replace", "path": "/description", "value": "After" },
+            { "op": "add", "path": "/labels/-", "value": { "id": LABEL } },
+            { "op": "add", "path": "/persons/-", "value": { "name": "Ana", "career": "ITC", "graduation_date": "2999-01-01" } },
+        ])).await.unwrap_err();
+        assert!(matches!(error, PatchError::Validation(_)));
+        // And before the badge that doesn't exist fails in the database
+        let error = patch(&state, json!([
+            { "op": "replace", "path": "/description", "value": "After" },
+            { "op": "add", "path": "/labels/-", "value": { "id": LABEL } },
+            { "op": "add", "path": "/badges/-", "value": { "badge_id": -1, "acquisition_date": "2023-02-01" } },
+        ])).await.unwrap_err();
+        assert!(matches!(error, PatchError::Database(_)));
+
+        let team = team(&state).await;
+        assert_eq!(team.description, "Before");
+        assert!(team.labels.is_empty() && team.persons.is_empty() && team.badges.is_empty());
+        assert_eq!(team.score, 0);
+        scratch.drop().await;
+    }
+}
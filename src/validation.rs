@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicI32, Ordering};
+use serde::Serialize;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+use crate::patch::Patch;
+
+// Limits shared by the create and edit payloads. Names and urls are VARCHAR(255)
+// columns, descriptions are TEXT but are shown whole on the site.
+pub const MAX_NAME: u64 = 255;
+pub const MAX_URL: u64 = 255;
+pub const MAX_DESCRIPTION: u64 = 5000;
+pub const MIN_STAGE: i32 = 1;
+// The highest stage is `teams.max_stage` in the configuration
+pub const DEFAULT_MAX_STAGE: i32 = 5;
+static MAX_STAGE: AtomicI32 = AtomicI32::new(DEFAULT_MAX_STAGE);
+// Teams in a single award or revoke
+pub const MAX_BATCH: u64 = 1000;
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+// Body of every 422 response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ValidationFailure {
+    pub errors: Vec<FieldError>,
+}
+
+// Runs the rules declared on `value`, listing every failing field sorted by name
+pub fn check<T: Validate>(value: &T) -> Result<(), ValidationFailure> {
+    let errors = match value.validate() {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors,
+    };
+    let mut errors:Vec<FieldError> = errors.field_errors().into_iter().flat_map(|(field, errors)| {
+        errors.iter().map(move |error| FieldError {
+            field: field.to_string(),
+            code: error.code.to_string(),
+            message: message(error),
+        })
+    }).collect();
+    errors.sort_by(|a, b| a.field.cmp(&b.field));
+    Err(ValidationFailure { errors })
+}
+
+// An edit payload without any member
+pub fn empty_edit() -> ValidationFailure {
+    ValidationFailure { errors: vec![FieldError {
+        field: String::from("body"),
+        code: String::from("empty"),
+        message: String::from("must set at least one field"),
+    }] }
+}
+
+fn message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message { return message.to_string(); }
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    match &error.code[..] {
+        "length" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must have between {} and {} characters", min, max),
+            (None, Some(max)) => format!("must have at most {} characters", max),
+            (Some(min), None) => format!("must have at least {} characters", min),
+            (None, None) => String::from("has an invalid length"),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("must be between {} and {}", min, max),
+            (None, Some(max)) => format!("must be at most {}", max),
+            (Some(min), None) => format!("must be at least {}", min),
+            (None, None) => String::from("is out of range"),
+        },
+        "url" => String::from("must be a valid url"),
+        code => String::from(code),
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Borrowed(message));
+    error
+}
+
+fn parse_date(date: &str) -> Result<actix_web::cookie::time::Date, ValidationError> {
+    let format = actix_web::cookie::time::format_description::parse("[year]-[month]-[day]").unwrap();
+    actix_web::cookie::time::Date::parse(date, &format).map_err(|_| error("date", "must be a date in YYYY-MM-DD format"))
+}
+
+// Dates of things that already happened: creations, graduations and acquisitions
+pub fn past_date(date: &str) -> Result<(), ValidationError> {
+    let date = parse_date(date)?;
+    if date > actix_web::cookie::time::OffsetDateTime::now_utc().date() {
+        return Err(error("past_date", "can't be in the future"));
+    }
+    Ok(())
+}
+
+pub fn set_max_stage(max: i32) {
+    MAX_STAGE.store(max, Ordering::Relaxed);
+}
+
+pub fn max_stage() -> i32 {
+    MAX_STAGE.load(Ordering::Relaxed)
+}
+
+// Same code and params as `range`, with the highest stage read at runtime
+pub fn stage(stage: i32) -> Result<(), ValidationError> {
+    let max = max_stage();
+    if (MIN_STAGE..=max).contains(&stage) { return Ok(()); }
+    let mut error = ValidationError::new("range");
+    error.add_param(Cow::Borrowed("min"), &MIN_STAGE);
+    error.add_param(Cow::Borrowed("max"), &max);
+    error.add_param(Cow::Borrowed("value"), &stage);
+    Err(error)
+}
+
+pub fn patch_stage(stage: &Patch<i32>) -> Result<(), ValidationError> {
+    stage.value().map_or(Ok(()), |stage| self::stage(*stage))
+}
+
+// Team ids of a batch award or revoke
+pub fn batch(team_ids: &[i64]) -> Result<(), ValidationError> {
+    if (1..=MAX_BATCH as usize).contains(&team_ids.len()) { return Ok(()); }
+    let mut error = ValidationError::new("length");
+    error.add_param(Cow::Borrowed("min"), &1);
+    error.add_param(Cow::Borrowed("max"), &MAX_BATCH);
+    error.add_param(Cow::Borrowed("value"), &team_ids.len());
+    error.message = Some(Cow::Owned(format!("must have between 1 and {} teams", MAX_BATCH)));
+    Err(error)
+}
+
+// Columns that can't be cleared with `null` in an edit payload
+pub fn required<T>(value: &Patch<T>) -> Result<(), ValidationError> {
+    if matches!(value, Patch::Null) {
+        return Err(error("null", "can't be null"));
+    }
+    Ok(())
+}
+
+pub fn patch_past_date(date: &Patch<String>) -> Result<(), ValidationError> {
+    date.value().map_or(Ok(()), |date| past_date(date))
+}
+
+pub fn patch_url(url: &Patch<String>) -> Result<(), ValidationError> {
+    url.value().map_or(Ok(()), |url| http_url(url))
+}
+
+pub fn http_url(url: &str) -> Result<(), ValidationError> {
+    let valid = (url.starts_with("https://") || url.starts_with("http://"))
+        && url.split("://").nth(1).is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/'))
+        && !url.chars().any(char::is_whitespace);
+    if !valid {
+        return Err(error("url", "must be a valid http or https url"));
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::Team;
+
+// Scalar fields of `Team`, always cheap to return
+pub const TEAM_FIELDS: [&str; 10] = ["id", "rank", "score", "stage", "name", "logo_url", "banner_url", "description", "creation_date", "location"];
+// Fields that need their own queries
+pub const TEAM_RELATIONS: [&str; 3] = ["labels", "persons", "badges"];
+
+#[derive(Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct TeamQuery {
+    // Comma separated fields to return, every field by default
+    pub fields: Option<String>,
+    // Comma separated relations to load: labels, persons and badges. All of them
+    // are loaded when neither `fields` nor `include` is given.
+    pub include: Option<String>,
+}
+
+// Which parts of a team a request wants
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamView {
+    fields: Option<Vec<String>>,
+    pub labels: bool,
+    pub persons: bool,
+    pub badges: bool,
+}
+
+fn split(list: &str) -> Vec<String> {
+    list.split(',').map(|item| item.trim()).filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+impl TeamView {
+    pub fn full() -> TeamView {
+        TeamView { fields: None, labels: true, persons: true, badges: true }
+    }
+
+    pub fn from(query: &TeamQuery) -> Result<TeamView, String> {
+        if query.fields.is_none() && query.include.is_none() { return Ok(TeamView::full()); }
+        let fields = query.fields.as_deref().map(split);
+        let include = query.include.as_deref().map(split).unwrap_or_default();
+        for field in fields.iter().flatten() {
+            if !TEAM_FIELDS.contains(&&field[..]) && !TEAM_RELATIONS.contains(&&field[..]) {
+                return Err(format!("UNKNOWN FIELD {}", field));
+            }
+        }
+        for relation in &include {
+            if !TEAM_RELATIONS.contains(&&relation[..]) {
+                return Err(format!("UNKNOWN INCLUDE {}", relation));
+            }
+        }
+        // A relation is loaded when it is included or asked for as a field
+        let wants = |relation: &str| include.iter().chain(fields.iter().flatten()).any(|item| item == relation);
+        Ok(TeamView {
+            labels: wants("labels"),
+            persons: wants("persons"),
+            badges: wants("badges"),
+            fields,
+        })
+    }
+
+    pub fn is_full(&self) -> bool {
+        *self == TeamView::full()
+    }
+
+    // Serializes only the fields of the view. Without `fields` every scalar is kept.
+    pub fn select(&self, team: &Team) -> serde_json::Value {
+        let mut value = serde_json::to_value(team).unwrap();
+        if let Some(object) = value.as_object_mut() {
+            object.retain(|key, _| match key.as_str() {
+                "labels" => self.labels,
+                "persons" => self.persons,
+                "badges" => self.badges,
+                key => match &self.fields {
+                    Some(fields) => fields.iter().any(|field| field == key),
+                    None => true,
+                },
+            });
+        }
+        value
+    }
+}
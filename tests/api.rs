@@ -0,0 +1,717 @@
+// Runs every REST route against `MemoryRepository`, seeded with
+// `database_information/psql-temp-values.sql`. No database is needed.
+// tests/sqlite.rs runs the same tests against SQLite.
+//
+// The seed stores a score of 800 for both teams while their badges add up to
+// 900 (Logro 1 is worth 200 and Platica de Felipe Ivan 700), and ranks them
+// 1 (Startup-tec) and 2 (Moneypool).
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::http::header::HeaderMap;
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+use starterspace_backend::cache::TeamCache;
+use starterspace_backend::config::CorsConfig;
+use starterspace_backend::metrics;
+use starterspace_backend::repository::{MemoryRepository, Repository, Totals};
+use starterspace_backend::*;
+
+const SEED: &str = include_str!("../database_information/psql-temp-values.sql");
+
+// The backend under test, found at the root of the test crate. Unused when
+// tests/sqlite.rs includes this file and brings its own.
+#[allow(dead_code)]
+pub async fn repository() -> Arc<dyn Repository> {
+    let repo = MemoryRepository::new();
+    repo.seed(SEED).unwrap();
+    Arc::new(repo)
+}
+
+async fn state() -> AppState {
+    AppState { cache: Arc::new(TeamCache::new(true)), ..AppState::new(crate::repository().await) }
+}
+
+async fn app() -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    test::init_service(App::new().app_data(web::Data::new(state().await)).configure(routes::configure)).await
+}
+
+async fn send<S>(app: &S, request: test::TestRequest) -> (StatusCode, Value)
+where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let response = test::call_service(app, request.to_request()).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn get<S>(app: &S, uri: &str) -> (StatusCode, Value)
+where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    send(app, test::TestRequest::get().uri(uri)).await
+}
+
+async fn post<S>(app: &S, uri: &str, body: Value) -> (StatusCode, Value)
+where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    send(app, test::TestRequest::post().uri(uri).set_payload(body.to_string())).await
+}
+
+// (id, score, rank) of every team, in the order of `/teams`
+async fn standings<S>(app: &S) -> Vec<(i64, i64, Option<i64>)>
+where S: Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let (status, teams) = get(app, "/teams?fields=id,score,rank").await;
+    assert_eq!(status, StatusCode::OK);
+    teams.as_array().unwrap().iter()
+        .map(|team| (team["id"].as_i64().unwrap(), team["score"].as_i64().unwrap(), team["rank"].as_i64()))
+        .collect()
+}
+
+fn ids(values: &Value) -> Vec<i64> {
+    let mut ids:Vec<i64> = values.as_array().unwrap().iter().map(|value| value["id"].as_i64().unwrap()).collect();
+    ids.sort();
+    ids
+}
+
+// Ids of the badges a team owns
+fn badge_ids(team: &Value) -> Vec<i64> {
+    let mut ids:Vec<i64> = team["badges"].as_array().unwrap().iter().map(|owned| owned["badge"]["id"].as_i64().unwrap()).collect();
+    ids.sort();
+    ids
+}
+
+fn new_team(name: &str) -> Value {
+    json!({ "name": name, "description": "A team", "location": "Monterrey, Mexico", "stage": 1, "creation_date": "2023-02-26" })
+}
+
+#[actix_web::test]
+async fn teams_are_sorted_by_score_then_name() {
+    let app = app().await;
+    let (status, teams) = get(&app, "/teams").await;
+    assert_eq!(status, StatusCode::OK);
+    let names:Vec<&str> = teams.as_array().unwrap().iter().map(|team| team["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Moneypool", "Startup-tec"]);
+    assert_eq!(standings(&app).await, [(2, 800, Some(2)), (1, 800, Some(1))]);
+
+    let moneypool = &teams[0];
+    assert_eq!(moneypool["labels"].as_array().unwrap().len(), 2);
+    assert_eq!(moneypool["persons"][0]["name"], "Nacho");
+    assert_eq!(moneypool["badges"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn teams_select_fields() {
+    let app = app().await;
+    let (status, teams) = get(&app, "/teams?fields=id,name").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(teams[0], json!({ "id": 2, "name": "Moneypool" }));
+
+    let (status, _) = get(&app, "/teams?fields=nope").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get(&app, "/teams?include=nope").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// The backend under test, counting the queries of each team relation
+struct Counting {
+    inner: Arc<dyn Repository>,
+    labels: AtomicUsize,
+    badges: AtomicUsize,
+    persons: AtomicUsize,
+}
+
+impl Counting {
+    // Calls of `team_labels`, `team_badges` and `team_persons`
+    fn calls(&self) -> (usize, usize, usize) {
+        (self.labels.load(Ordering::SeqCst), self.badges.load(Ordering::SeqCst), self.persons.load(Ordering::SeqCst))
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for Counting {
+    async fn teams(&self) -> Vec<RawTeam> { self.inner.teams().await }
+    async fn team(&self, id: i64) -> Option<RawTeam> { self.inner.team(id).await }
+    async fn labels(&self) -> Vec<Label> { self.inner.labels().await }
+    async fn badges(&self) -> Vec<RawBadge> { self.inner.badges().await }
+    async fn categories(&self) -> Vec<Category> { self.inner.categories().await }
+    async fn team_labels(&self, team_id: i64) -> Vec<Label> {
+        self.labels.fetch_add(1, Ordering::SeqCst);
+        self.inner.team_labels(team_id).await
+    }
+    async fn team_badges(&self, team_id: i64) -> Vec<OwnedBadge> {
+        self.badges.fetch_add(1, Ordering::SeqCst);
+        self.inner.team_badges(team_id).await
+    }
+    async fn team_persons(&self, team_id: i64) -> Vec<Person> {
+        self.persons.fetch_add(1, Ordering::SeqCst);
+        self.inner.team_persons(team_id).await
+    }
+    async fn person_team(&self, id: i64) -> Option<i64> { self.inner.person_team(id).await }
+    async fn create_team(&self, team: CreateTeam) -> Result<i64, String> { self.inner.create_team(team).await }
+    async fn create_badge(&self, badge: CreateBadge) -> Result<i64, String> { self.inner.create_badge(badge).await }
+    async fn create_label(&self, label: CreateLabel) -> Result<i64, String> { self.inner.create_label(label).await }
+    async fn create_category(&self, category: CreateCategory) -> Result<i64, String> { self.inner.create_category(category).await }
+    async fn add_label(&self, label_ownership: CreateLabelOwnership) -> Result<(), String> { self.inner.add_label(label_ownership).await }
+    async fn add_badge(&self, badge_ownership: CreateBadgeOwnership) -> Result<(), String> { self.inner.add_badge(badge_ownership).await }
+    async fn add_person(&self, person: CreatePerson) -> Result<(), String> { self.inner.add_person(person).await }
+    async fn remove_label(&self, label_ownership: DeleteOwnedLabel) -> Result<(), String> { self.inner.remove_label(label_ownership).await }
+    async fn remove_badge(&self, badge_ownership: DeleteOwnedBadge) -> Result<(), String> { self.inner.remove_badge(badge_ownership).await }
+    async fn award_badge(&self, award: &BadgeAward) -> Result<Vec<i64>, String> { self.inner.award_badge(award).await }
+    async fn revoke_badge(&self, revoke: &BadgeRevoke) -> Result<Vec<i64>, String> { self.inner.revoke_badge(revoke).await }
+    async fn delete(&self, kind: &str, id: i64, force: bool) -> Result<(), String> { self.inner.delete(kind, id, force).await }
+    async fn edit(&self, id: i64, body: EditBody) -> Result<(), String> { self.inner.edit(id, body).await }
+    async fn seed(&self, sql: &str) -> Result<(), String> { self.inner.seed(sql).await }
+    async fn totals(&self) -> Result<Totals, String> { self.inner.totals().await }
+    async fn rank_teams(&self) -> Vec<RankTeam> { self.inner.rank_teams().await }
+    async fn write_ranks(&self, changes: &[(i64, Option<i32>)]) { self.inner.write_ranks(changes).await }
+    async fn recompute_scores(&self) { self.inner.recompute_scores().await }
+}
+
+#[actix_web::test]
+async fn relations_outside_the_view_are_not_queried() {
+    let repo = Arc::new(Counting { inner: crate::repository().await, labels: AtomicUsize::new(0), badges: AtomicUsize::new(0), persons: AtomicUsize::new(0) });
+    let state = AppState { cache: Arc::new(TeamCache::new(true)), ..AppState::new(repo.clone()) };
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).configure(routes::configure)).await;
+
+    for uri in ["/teams?fields=id,name,score,rank", "/team/1?fields=id,name,score,rank"] {
+        assert_eq!(get(&app, uri).await.0, StatusCode::OK);
+    }
+    assert_eq!(repo.calls(), (0, 0, 0));
+    // One query per team of the seed, then one for team 1
+    for uri in ["/teams?include=badges", "/team/1?include=badges"] {
+        assert_eq!(get(&app, uri).await.0, StatusCode::OK);
+    }
+    assert_eq!(repo.calls(), (0, 3, 0));
+}
+
+#[actix_web::test]
+async fn team_by_id() {
+    let app = app().await;
+    let (status, team) = get(&app, "/team/1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(team["name"], "Startup-tec");
+    assert_eq!(team["persons"][0]["name"], "Felipe Ivan");
+    assert_eq!(ids(&team["labels"]), [2, 3]);
+    let owned = team["badges"].as_array().unwrap().iter().find(|owned| owned["badge"]["id"] == 2).unwrap();
+    assert_eq!(owned["acquisition_date"], "2023-01-23");
+    assert_eq!(owned["badge"]["points"], 700);
+    assert_eq!(owned["badge"]["category"]["name"], "Talk");
+
+    let (status, team) = get(&app, "/team/1?fields=name").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(team, json!({ "name": "Startup-tec" }));
+
+    let (status, body) = get(&app, "/team/99").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "ID does not exist");
+    let (status, _) = get(&app, "/team/1?fields=nope").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn lists() {
+    let app = app().await;
+    let (status, labels) = get(&app, "/labels").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&labels), [1, 2, 3, 4]);
+    let (status, badges) = get(&app, "/badges").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&badges), [1, 2]);
+    let (status, categories) = get(&app, "/categories").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&categories), [1, 2, 3]);
+}
+
+#[actix_web::test]
+async fn create_team() {
+    let app = app().await;
+    let (status, id) = post(&app, "/create/team", new_team("Rocket")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(id, 3);
+    let (status, team) = get(&app, "/team/3").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(team["name"], "Rocket");
+    assert_eq!(team["score"], 0);
+    assert_eq!(team["rank"], Value::Null);
+    // The cached list has to show the new team
+    assert_eq!(standings(&app).await.len(), 3);
+
+    let (status, body) = post(&app, "/create/team", new_team("Rocket")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.as_str().unwrap().starts_with("ERROR ADDING TO DATABASE"));
+    let (status, body) = send(&app, test::TestRequest::post().uri("/create/team").set_payload("{")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.as_str().unwrap().starts_with("ERROR PARSING JSON"));
+    let (status, body) = post(&app, "/create/team", json!({ "name": "", "description": "A team", "location": "Here", "stage": 9, "creation_date": "2999-01-01" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let mut fields:Vec<&str> = body["errors"].as_array().unwrap().iter().map(|error| error["field"].as_str().unwrap()).collect();
+    fields.sort();
+    assert_eq!(fields, ["creation_date", "name", "stage"]);
+}
+
+#[actix_web::test]
+async fn invalid_creates_list_every_field_error() {
+    let app = app().await;
+    let (status, body) = post(&app, "/create/team", json!({ "name": "", "description": "A team", "location": "Here", "stage": 9, "creation_date": "2999-01-01", "banner_url": "ftp://example.com" })).await;
+    assert_eq!((status, body), (StatusCode::UNPROCESSABLE_ENTITY, json!({ "errors": [
+        { "field": "banner_url", "code": "url", "message": "must be a valid http or https url" },
+        { "field": "creation_date", "code": "past_date", "message": "can't be in the future" },
+        { "field": "name", "code": "length", "message": "must have between 1 and 255 characters" },
+        { "field": "stage", "code": "range", "message": "must be between 1 and 5" },
+    ] })));
+}
+
+#[actix_web::test]
+async fn create_badge_label_and_category() {
+    let app = app().await;
+    let (status, id) = post(&app, "/create/category", json!({ "name": "Stage 3" })).await;
+    assert_eq!((status, id), (StatusCode::OK, json!(4)));
+    let (status, id) = post(&app, "/create/label", json!({ "name": "Fintech" })).await;
+    assert_eq!((status, id), (StatusCode::OK, json!(5)));
+    let (status, id) = post(&app, "/create/badge", json!({ "name": "Demo day", "description": "Pitched", "points": 50, "category": 4 })).await;
+    assert_eq!((status, id), (StatusCode::OK, json!(3)));
+
+    let (status, _) = post(&app, "/create/category", json!({ "name": "Talk" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/create/label", json!({ "name": "Web3" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/create/badge", json!({ "name": "Logro 1", "description": "Again", "points": 50, "category": 1 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/create/badge", json!({ "name": "Lost", "description": "No category", "points": 50, "category": 99 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/create/badge", json!({ "name": "Negative", "description": "Points", "points": -1, "category": 1 })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = post(&app, "/create/label", json!({ "name": "" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn add_label() {
+    let app = app().await;
+    // Fill the cache first, adding a label has to invalidate it
+    get(&app, "/team/1").await;
+    let (status, body) = post(&app, "/add/label", json!({ "team_id": 1, "label_id": 4 })).await;
+    assert_eq!((status, body), (StatusCode::OK, json!("Success")));
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(ids(&team["labels"]), [2, 3, 4]);
+
+    let (status, _) = post(&app, "/add/label", json!({ "team_id": 1, "label_id": 4 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/label", json!({ "team_id": 99, "label_id": 1 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/label", json!({ "team_id": 1, "label_id": 99 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn add_person() {
+    let app = app().await;
+    get(&app, "/team/2").await;
+    let person = json!({ "team_id": 2, "name": "Ana", "career": "ITC", "graduation_date": "2020-06-01", "picture_url": null, "portafolio_url": "https://ana.dev" });
+    let (status, _) = post(&app, "/add/person", person).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, team) = get(&app, "/team/2").await;
+    let names:Vec<&str> = team["persons"].as_array().unwrap().iter().map(|person| person["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Nacho", "Ana"]);
+
+    let (status, _) = post(&app, "/add/person", json!({ "team_id": 99, "name": "Ana", "career": "ITC", "graduation_date": "2020-06-01" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/person", json!({ "team_id": 2, "name": "Ana", "career": "ITC", "graduation_date": "2020-06-01", "picture_url": "ftp://ana" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn add_badge_moves_score_and_rank() {
+    let app = app().await;
+    post(&app, "/create/badge", json!({ "name": "Demo day", "description": "Pitched", "points": 50, "category": 1 })).await;
+    let (status, _) = post(&app, "/add/badge", json!({ "team_id": 1, "badge_id": 3, "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings(&app).await, [(1, 850, Some(1)), (2, 800, Some(2))]);
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(badge_ids(&team), [1, 2, 3]);
+
+    let (status, _) = post(&app, "/add/badge", json!({ "team_id": 1, "badge_id": 3, "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/badge", json!({ "team_id": 1, "badge_id": 99, "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/badge", json!({ "team_id": 99, "badge_id": 3, "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/add/badge", json!({ "team_id": 2, "badge_id": 3, "acquisition_date": "2999-01-01" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(standings(&app).await, [(1, 850, Some(1)), (2, 800, Some(2))]);
+}
+
+#[actix_web::test]
+async fn award_and_revoke_badges() {
+    let app = app().await;
+    post(&app, "/create/team", new_team("Rocket")).await;
+    post(&app, "/create/badge", json!({ "name": "Demo day", "description": "Pitched", "points": 300, "category": 1 })).await;
+
+    let (status, report) = post(&app, "/award/badge", json!({ "badge_id": 3, "team_ids": [3, 2, 3], "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report, json!({ "awarded": [2, 3], "duplicates": [] }));
+    assert_eq!(standings(&app).await, [(2, 1100, Some(1)), (1, 800, Some(2)), (3, 300, Some(3))]);
+
+    let (_, report) = post(&app, "/award/badge", json!({ "badge_id": 3, "team_ids": [1, 2], "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(report, json!({ "awarded": [1], "duplicates": [2] }));
+    // Startup-tec and Moneypool tie, the tie is broken by name
+    assert_eq!(standings(&app).await, [(2, 1100, Some(1)), (1, 1100, Some(1)), (3, 300, Some(2))]);
+
+    let (status, report) = post(&app, "/revoke/badge", json!({ "badge_id": 3, "team_ids": [3, 1, 99] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report, json!({ "revoked": [1, 3], "not_owned": [99] }));
+    // Rocket has no points left, so no rank
+    assert_eq!(standings(&app).await, [(2, 1100, Some(1)), (1, 800, Some(2)), (3, 0, None)]);
+
+    let (status, body) = post(&app, "/award/badge", json!({ "badge_id": 99, "team_ids": [1], "acquisition_date": "2023-05-01" })).await;
+    assert_eq!((status, body), (StatusCode::BAD_REQUEST, json!("BADGE 99 DOES NOT EXIST")));
+    let (status, body) = post(&app, "/award/badge", json!({ "badge_id": 3, "team_ids": [1, 98, 99], "acquisition_date": "2023-05-01" })).await;
+    assert_eq!((status, body), (StatusCode::BAD_REQUEST, json!("TEAMS DO NOT EXIST: [98, 99]")));
+    let (status, _) = post(&app, "/award/badge", json!({ "badge_id": 3, "team_ids": [], "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, body) = post(&app, "/revoke/badge", json!({ "badge_id": 3, "team_ids": [] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["message"], "must have between 1 and 1000 teams");
+    let (status, _) = post(&app, "/revoke/badge", json!({ "badge_id": 3, "team_ids": vec![1; 1001] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(standings(&app).await, [(2, 1100, Some(1)), (1, 800, Some(2)), (3, 0, None)]);
+}
+
+#[actix_web::test]
+async fn award_replays_idempotency_key() {
+    let app = app().await;
+    let award = || test::TestRequest::post()
+        .uri("/award/badge")
+        .insert_header(("Idempotency-Key", "award-1"))
+        .set_payload(json!({ "badge_id": 1, "team_ids": [1], "acquisition_date": "2023-05-01" }).to_string());
+    let first = send(&app, award()).await;
+    assert_eq!(first.1, json!({ "awarded": [], "duplicates": [1] }));
+    assert_eq!(send(&app, award()).await, first);
+}
+
+#[actix_web::test]
+async fn delete_ownership() {
+    let app = app().await;
+    get(&app, "/teams").await;
+    let (status, body) = post(&app, "/delete_ownership?kind=label", json!({ "team_id": 1, "label_id": 2 })).await;
+    assert_eq!((status, body), (StatusCode::OK, json!("OK")));
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(ids(&team["labels"]), [3]);
+
+    let (status, _) = post(&app, "/delete_ownership?kind=badge", json!({ "team_id": 1, "badge_id": 2 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings(&app).await, [(2, 800, Some(1)), (1, 100, Some(2))]);
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(badge_ids(&team), [1]);
+
+    let (status, body) = post(&app, "/delete_ownership?kind=person", json!({ "team_id": 1 })).await;
+    assert_eq!((status, body), (StatusCode::BAD_REQUEST, json!("NO type FOUND")));
+    let (status, _) = post(&app, "/delete_ownership?kind=badge", json!({ "team_id": 1 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(&app, test::TestRequest::post().uri("/delete_ownership").set_payload("{}")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn delete_needs_force_when_linked() {
+    let app = app().await;
+    for kind in ["label", "badge", "category", "team"] {
+        let (status, body) = post(&app, &format!("/delete?kind={}&id=1", kind), Value::Null).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", kind);
+        assert!(body.as_str().unwrap().starts_with("ERROR ADDING TO DATABASE"), "{}", kind);
+    }
+    let (status, body) = post(&app, "/delete?kind=nope&id=1", Value::Null).await;
+    assert_eq!((status, body), (StatusCode::BAD_REQUEST, json!("TYPE IS NOT AVAILABLE FOR DELETION")));
+    let (status, _) = post(&app, "/delete?kind=team", Value::Null).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Unlinked objects don't need it
+    let (status, _) = post(&app, "/delete?kind=label&id=4", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post(&app, "/delete?kind=category&id=3", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post(&app, "/delete?kind=person&id=1", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, labels) = get(&app, "/labels").await;
+    assert_eq!(ids(&labels), [1, 2, 3]);
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(team["persons"], json!([]));
+}
+
+#[actix_web::test]
+async fn forced_deletes_take_points_away() {
+    let app = app().await;
+    // Start from scores that match the badges
+    post(&app, "/update/rankings", Value::Null).await;
+    let (status, _) = post(&app, "/delete?kind=badge&id=2&force=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings(&app).await, [(2, 200, Some(1)), (1, 200, Some(1))]);
+
+    let (status, _) = post(&app, "/delete?kind=category&id=2&force=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, badges) = get(&app, "/badges").await;
+    assert_eq!(badges, json!([]));
+    assert_eq!(standings(&app).await, [(2, 0, None), (1, 0, None)]);
+
+    let (status, _) = post(&app, "/delete?kind=team&id=2&force=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(get(&app, "/team/2").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(standings(&app).await, [(1, 0, None)]);
+
+    let (status, _) = post(&app, "/delete?kind=label&id=2&force=true", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(ids(&team["labels"]), [3]);
+}
+
+#[actix_web::test]
+async fn edit() {
+    let app = app().await;
+    get(&app, "/teams").await;
+    let (status, body) = post(&app, "/edit?kind=team&id=1", json!({ "name": "Startup TEC", "logo_url": "https://logo.png" })).await;
+    assert_eq!((status, body), (StatusCode::OK, json!("Success")));
+    let (_, team) = get(&app, "/team/1").await;
+    assert_eq!(team["name"], "Startup TEC");
+    assert_eq!(team["logo_url"], "https://logo.png");
+    assert_eq!(team["description"], "TEC startup club");
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "logo_url": null })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(get(&app, "/team/1").await.1["logo_url"], Value::Null);
+
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "description": "O'Brien's \\ club" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(get(&app, "/team/1").await.1["description"], "O'Brien's \\ club");
+
+    let (status, _) = post(&app, "/edit?kind=label&id=1", json!({ "name": "Blockchain" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post(&app, "/edit?kind=category&id=3", json!({ "name": "Stage two" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, labels) = get(&app, "/labels").await;
+    assert!(labels.as_array().unwrap().iter().any(|label| label["name"] == "Blockchain"));
+
+    // Moving a person changes both teams
+    let (status, _) = post(&app, "/edit?kind=person&id=1", json!({ "team_id": 2, "career": "IMT" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(get(&app, "/team/1").await.1["persons"], json!([]));
+    let (_, team) = get(&app, "/team/2").await;
+    let moved = team["persons"].as_array().unwrap().iter().find(|person| person["name"] == "Felipe Ivan").unwrap();
+    assert_eq!(moved["career"], "IMT");
+
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "name": "Moneypool" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/edit?kind=person&id=1", json!({ "team_id": 99 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/edit?kind=badge&id=1", json!({ "category": 99 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "name": null })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "stage": 0 })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = post(&app, "/edit?kind=team&id=1", json!({ "name": 5 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = post(&app, "/edit?kind=team&id=1", json!({})).await;
+    assert_eq!((status, body), (StatusCode::UNPROCESSABLE_ENTITY, json!({ "errors": [{ "field": "body", "code": "empty", "message": "must set at least one field" }] })));
+    let (status, body) = post(&app, "/edit?kind=nope&id=1", json!({})).await;
+    assert_eq!((status, body), (StatusCode::BAD_REQUEST, json!("TYPE IS NOT AVAILABLE FOR DELETION")));
+}
+
+#[actix_web::test]
+async fn editing_points_moves_scores_and_ranks() {
+    let app = app().await;
+    post(&app, "/create/badge", json!({ "name": "Demo day", "description": "Pitched", "points": 10, "category": 1 })).await;
+    post(&app, "/add/badge", json!({ "team_id": 2, "badge_id": 3, "acquisition_date": "2023-05-01" })).await;
+    assert_eq!(standings(&app).await, [(2, 810, Some(1)), (1, 800, Some(2))]);
+
+    let (status, _) = post(&app, "/edit?kind=badge&id=1", json!({ "points": 150 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings(&app).await, [(2, 760, Some(1)), (1, 750, Some(2))]);
+    let (status, _) = post(&app, "/edit?kind=badge&id=3", json!({ "points": 0 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings(&app).await, [(2, 750, Some(1)), (1, 750, Some(1))]);
+    let (status, _) = post(&app, "/edit?kind=badge&id=3", json!({ "points": -5 })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[actix_web::test]
+async fn update_rankings_recomputes_scores() {
+    let app = app().await;
+    // The seeded scores are 800 while the badges add up to 900
+    assert_eq!(standings(&app).await, [(2, 800, Some(2)), (1, 800, Some(1))]);
+    let (status, body) = post(&app, "/update/rankings", Value::Null).await;
+    assert_eq!((status, body), (StatusCode::OK, json!("OK")));
+    assert_eq!(standings(&app).await, [(2, 900, Some(1)), (1, 900, Some(1))]);
+
+    post(&app, "/delete_ownership?kind=badge", json!({ "team_id": 2, "badge_id": 1 })).await;
+    assert_eq!(standings(&app).await, [(1, 900, Some(1)), (2, 700, Some(2))]);
+    post(&app, "/update/rankings", Value::Null).await;
+    assert_eq!(standings(&app).await, [(1, 900, Some(1)), (2, 700, Some(2))]);
+}
+
+#[actix_web::test]
+async fn postgres_features_are_not_available() {
+    let app = app().await;
+    for uri in ["/search?q=tec", "/export", "/backup", "/integrity"] {
+        let (status, body) = get(&app, uri).await;
+        assert_eq!((status, body), (StatusCode::NOT_IMPLEMENTED, json!("NOT AVAILABLE WITH THIS DATABASE")), "{}", uri);
+    }
+    let (status, _) = send(&app, test::TestRequest::patch().uri("/teams/1").set_payload("[]")).await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+    let (status, _) = post(&app, "/import", json!({ "teams": "name,description,activation_date\n" })).await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+    let backup = json!({ "version": 1, "created_at": "", "badge_categories": [], "labels": [], "badges": [], "teams": [], "persons": [], "label_ownerships": [], "badge_ownerships": [] });
+    let (status, _) = post(&app, "/restore", backup).await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+    let state = state().await;
+    let app = test::init_service(App::new().app_data(web::Data::new(graphql::schema(state.clone()))).app_data(web::Data::new(state)).configure(routes::configure)).await;
+    let (status, body) = post(&app, "/graphql", json!({ "query": "{ teams { id } }" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["errors"][0]["message"], json!("NOT AVAILABLE WITH THIS DATABASE"));
+}
+
+#[actix_web::test]
+async fn cache_stats_count_repeated_lists() {
+    let app = app().await;
+    let (status, before) = get(&app, "/cache/stats").await;
+    assert_eq!(status, StatusCode::OK);
+    let mut keys:Vec<&String> = before.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["cached_teams", "enabled", "hit_rate", "hits", "invalidations", "list_cached", "misses"]);
+    assert_eq!((&before["enabled"], &before["list_cached"]), (&json!(true), &json!(false)));
+
+    get(&app, "/teams").await;
+    get(&app, "/teams").await;
+    let (_, after) = get(&app, "/cache/stats").await;
+    assert_eq!(after["hits"].as_u64().unwrap(), before["hits"].as_u64().unwrap() + 1);
+    assert_eq!(after["misses"].as_u64().unwrap(), before["misses"].as_u64().unwrap() + 1);
+    assert_eq!(after["list_cached"], json!(true));
+    assert_eq!(after["hit_rate"], json!(0.5));
+}
+
+#[actix_web::test]
+async fn documentation_is_served() {
+    let app = app().await;
+    let (status, spec) = get(&app, "/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(spec["info"]["title"], json!("StarterSpace API"));
+    assert!(spec["paths"]["/teams"]["get"].is_object());
+    assert!(spec["components"]["schemas"]["Team"].is_object());
+
+    for (uri, text) in [("/docs", "StarterSpace API"), ("/graphql", "graphiql")] {
+        let response = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert!(response.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/html"), "{}", uri);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.contains(text), "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn bodies_that_are_not_utf8_are_refused() {
+    let app = app().await;
+    for (method, uri) in [(Method::PATCH, "/teams/1"), (Method::POST, "/award/badge"), (Method::POST, "/revoke/badge"), (Method::POST, "/import"), (Method::POST, "/restore")] {
+        let (status, body) = send(&app, test::TestRequest::default().method(method).uri(uri).set_payload(vec![b'[', 0xff, b']'])).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+        assert!(body.as_str().unwrap().starts_with("ERROR PARSING JSON"), "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn cors_allows_only_listed_origins() {
+    let cors = CorsConfig { origins: vec![String::from("https://admin.example.com")], credentials: true, ..CorsConfig::default() };
+    let app = test::init_service(App::new().wrap(cors.middleware()).app_data(web::Data::new(state().await)).configure(routes::configure)).await;
+    let header = |headers: &HeaderMap, name: &str| headers.get(name).map(|value| value.to_str().unwrap().to_string());
+
+    // Preflight of a JSON POST with an idempotency key
+    let preflight = |origin: &str| test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/create/team")
+        .insert_header(("Origin", origin))
+        .insert_header(("Access-Control-Request-Method", "POST"))
+        .insert_header(("Access-Control-Request-Headers", "content-type, idempotency-key"))
+        .to_request();
+    let response = test::call_service(&app, preflight("https://admin.example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(response.headers(), "access-control-allow-origin").as_deref(), Some("https://admin.example.com"));
+    assert_eq!(header(response.headers(), "access-control-allow-credentials").as_deref(), Some("true"));
+    assert!(header(response.headers(), "access-control-allow-methods").unwrap().contains("POST"));
+    assert!(header(response.headers(), "access-control-allow-headers").unwrap().to_lowercase().contains("idempotency-key"));
+    let response = test::call_service(&app, preflight("https://evil.example.com")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(header(response.headers(), "access-control-allow-origin"), None);
+
+    let request = test::TestRequest::get().uri("/labels").insert_header(("Origin", "https://admin.example.com")).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(response.headers(), "access-control-allow-origin").as_deref(), Some("https://admin.example.com"));
+    assert!(header(response.headers(), "access-control-expose-headers").unwrap().to_lowercase().contains("idempotent-replayed"));
+
+    // Same-origin requests and tools like curl send no Origin
+    let response = test::call_service(&app, test::TestRequest::get().uri("/labels").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn cors_defaults_allow_any_origin() {
+    let app = test::init_service(App::new().wrap(CorsConfig::default().middleware()).app_data(web::Data::new(state().await)).configure(routes::configure)).await;
+    let request = test::TestRequest::get().uri("/teams").insert_header(("Origin", "https://startups.example.com")).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.headers().get("access-control-allow-origin").unwrap(), "*");
+}
+
+#[actix_web::test]
+async fn readiness_follows_the_server_phase() {
+    let state = state().await;
+    let health = state.health.clone();
+    let app = test::init_service(App::new().app_data(web::Data::new(state)).configure(routes::configure)).await;
+
+    assert_eq!(get(&app, "/healthz").await, (StatusCode::OK, json!("OK")));
+    let (status, readiness) = get(&app, "/readyz").await;
+    assert_eq!((status, readiness), (StatusCode::SERVICE_UNAVAILABLE, json!({ "ready": false, "phase": "starting", "database": "OK" })));
+    health.serving();
+    let (status, readiness) = get(&app, "/readyz").await;
+    assert_eq!((status, readiness["ready"].clone()), (StatusCode::OK, json!(true)));
+    health.stopping();
+    let (status, readiness) = get(&app, "/readyz").await;
+    assert_eq!((status, readiness["phase"].clone()), (StatusCode::SERVICE_UNAVAILABLE, json!("stopping")));
+    // The process is still up while it drains
+    assert_eq!(get(&app, "/healthz").await.0, StatusCode::OK);
+
+    let (status, version) = get(&app, "/version").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!version["commit"].as_str().unwrap().is_empty());
+    assert!(version["build_time"].as_str().unwrap().ends_with('Z'));
+}
+
+#[actix_web::test]
+async fn metrics_count_requests_and_awards() {
+    let app = test::init_service(App::new().app_data(web::Data::new(state().await)).wrap(actix_web::middleware::from_fn(metrics::track)).configure(routes::configure)).await;
+    let today = actix_web::cookie::time::OffsetDateTime::now_utc().date().to_string();
+
+    get(&app, "/team/1").await;
+    get(&app, "/team/2").await;
+    assert_eq!(get(&app, "/team/99").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get(&app, "/nowhere").await.0, StatusCode::NOT_FOUND);
+    post(&app, "/create/team", new_team("Rocket")).await;
+    post(&app, "/create/badge", json!({ "name": "Demo day", "description": "Pitched", "points": 300, "category": 1 })).await;
+    let (_, report) = post(&app, "/award/badge", json!({ "badge_id": 3, "team_ids": [2, 3], "acquisition_date": today })).await;
+    assert_eq!(report["awarded"], json!([2, 3]));
+
+    let response = test::call_service(&app, test::TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    for line in [
+        r#"starterspace_http_requests_total{method="GET",route="/team/{id}",status="200"} 2"#,
+        r#"starterspace_http_requests_total{method="GET",route="/team/{id}",status="404"} 1"#,
+        r#"starterspace_http_requests_total{method="GET",route="unmatched",status="404"} 1"#,
+        r#"starterspace_http_request_duration_seconds_count{method="POST",route="/award/badge"} 1"#,
+        r#"starterspace_http_errors_total{status="404"} 2"#,
+        r#"starterspace_ranking_duration_seconds_count{operation="update_ranking"} 1"#,
+        "starterspace_badges_awarded_total 2",
+        "starterspace_db_pool_max_connections 10",
+        r#"starterspace_rows{table="teams"} 3"#,
+        r#"starterspace_rows{table="badge_ownerships_dated_today"} 2"#,
+    ] {
+        assert!(body.lines().any(|metric| metric == line), "{} is not in\n{}", line, body);
+    }
+}
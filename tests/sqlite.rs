@@ -0,0 +1,54 @@
+// Runs the tests of api.rs against `SqliteRepository` on an in-memory database,
+// so the migrations, the score triggers and the date handling run for real.
+//
+//     cargo test --features sqlite --test sqlite
+#![cfg(feature = "sqlite")]
+
+use std::sync::Arc;
+use sqlx::pool::PoolOptions;
+use starterspace_backend::repository::{Repository, SqliteRepository};
+use starterspace_backend::{BadgeAward, CreateBadgeOwnership, DeleteOwnedBadge, DeleteOwnedLabel};
+
+#[path = "api.rs"]
+mod api;
+
+const SEED: &str = include_str!("../database_information/sqlite-temp-values.sql");
+
+// Every connection to `sqlite::memory:` opens its own empty database, so the
+// pool keeps a single one for good. The triggers score the seed at 900 per
+// team, the stored 800 and ranks of api.rs are written over them.
+pub async fn repository() -> Arc<dyn Repository> {
+    let pool_options = PoolOptions::new().max_connections(1).min_connections(1).idle_timeout(None).max_lifetime(None);
+    let repo = SqliteRepository::connect("sqlite::memory:", pool_options).await.unwrap();
+    repo.seed(SEED).await.unwrap();
+    repo.seed("UPDATE teams SET score = 800, rank = id").await.unwrap();
+    Arc::new(repo)
+}
+
+#[actix_web::test]
+async fn migrations_and_triggers_are_installed() {
+    let repo = repository().await;
+    assert_eq!(repo.ready().await, Ok(()));
+    assert_eq!(repo.totals().await.unwrap().badge_ownerships, 4);
+    // Recomputing replaces the stored 800 with what the badges are worth
+    repo.recompute_scores().await;
+    let scores:Vec<i64> = repo.teams().await.iter().map(|team| team.score).collect();
+    assert_eq!(scores, [900, 900]);
+}
+
+// Writes the validation would have refused, and a database that fails, come
+// back as errors instead of panics
+#[actix_web::test]
+async fn failures_are_returned() {
+    let repo = repository().await;
+    let invalid = "ERROR ADDING TO DATABASE: invalid input syntax for type date: \"2023-02-30\"";
+    let ownership = CreateBadgeOwnership { team_id: 1, badge_id: 1, acquisition_date: String::from("2023-02-30") };
+    assert_eq!(repo.add_badge(ownership).await, Err(String::from(invalid)));
+    let award = BadgeAward { badge_id: 1, acquisition_date: String::from("2023-02-30"), team_ids: vec![1] };
+    assert_eq!(repo.award_badge(&award).await, Err(String::from(invalid)));
+
+    repo.seed("DROP TABLE label_ownerships").await.unwrap();
+    repo.seed("DROP TABLE badge_ownerships").await.unwrap();
+    assert!(repo.remove_label(DeleteOwnedLabel { team_id: 1, label_id: 2 }).await.unwrap_err().starts_with("ERROR ADDING TO DATABASE"));
+    assert!(repo.remove_badge(DeleteOwnedBadge { team_id: 1, badge_id: 2 }).await.unwrap_err().starts_with("ERROR ADDING TO DATABASE"));
+}